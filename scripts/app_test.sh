@@ -112,6 +112,8 @@ test_list=(
     "libc"
 )
 
+make -C "$ROOT" disk_img
+
 for t in ${test_list[@]}; do
     APP=$t
     APP_DIR=$(realpath "$(pwd)/apps/$t")
@@ -0,0 +1,350 @@
+//! Epoll instances, which report the events of the files they watch.
+//!
+//! Each watched file has an entry, which its [`PollSet`] wakes up: the entry
+//! is then queued on the ready list of the instance, from which `epoll_wait`
+//! takes the entries whose files have events. A level-triggered entry is
+//! queued again after its events are reported, so that they are reported
+//! until they are gone, while an edge-triggered entry waits for its file to
+//! wake it up again.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+use super::{FileLike, Kstat, PollEvents, PollSet, PollWaker, StatusFlags};
+use crate::{fs::OpenFlags, task::wait_interruptible_timeout};
+
+/// The flags of the events of `epoll_ctl`
+const EPOLLEXCLUSIVE: u32 = 1 << 28;
+const EPOLLWAKEUP: u32 = 1 << 29;
+const EPOLLONESHOT: u32 = 1 << 30;
+const EPOLLET: u32 = 1 << 31;
+
+/// The flags of an entry, besides its events.
+const PRIVATE_BITS: u32 = EPOLLWAKEUP | EPOLLONESHOT | EPOLLET | EPOLLEXCLUSIVE;
+/// The events and flags allowed with `EPOLLEXCLUSIVE`.
+const EXCLUSIVE_OK_BITS: u32 = PollEvents::READABLE
+    .union(PollEvents::WRITABLE)
+    .union(PollEvents::ALWAYS)
+    .bits()
+    | EPOLLWAKEUP
+    | EPOLLET
+    | EPOLLEXCLUSIVE;
+
+/// `struct epoll_event`, which is packed on x86_64.
+#[repr(C)]
+#[cfg_attr(target_arch = "x86_64", repr(packed))]
+#[derive(Clone, Copy)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+/// A file watched by an epoll instance.
+struct Entry {
+    /// The file, which the entry does not keep open: the entry is dropped
+    /// once the file is closed.
+    file: Weak<dyn FileLike>,
+    /// The requested events and flags. A one-shot entry has no events once
+    /// they are reported, until `EPOLL_CTL_MOD` rearms it.
+    events: AtomicU32,
+    data: AtomicU64,
+    /// Whether the entry is on the ready list.
+    queued: AtomicBool,
+    epoll: Weak<Inner>,
+}
+
+impl Entry {
+    /// Returns the requested events which the file has, or `None` if the
+    /// entry is disabled or the file is closed.
+    fn ready_events(&self) -> Option<PollEvents> {
+        let events = self.events.load(Ordering::Acquire);
+        if events & !PRIVATE_BITS == 0 {
+            return None;
+        }
+        let file = self.file.upgrade()?;
+        let ready = file.poll() & (PollEvents::from_bits_truncate(events) | PollEvents::ALWAYS);
+        (!ready.is_empty()).then_some(ready)
+    }
+}
+
+impl PollWaker for Entry {
+    /// Queues the entry if the file may have one of its events. An exclusive
+    /// entry takes the wakeup then.
+    fn wake(self: Arc<Self>, events: PollEvents) -> bool {
+        let wanted = self.events.load(Ordering::Acquire);
+        if wanted & !PRIVATE_BITS == 0
+            || (events & (PollEvents::from_bits_truncate(wanted) | PollEvents::ALWAYS)).is_empty()
+        {
+            return false;
+        }
+        let Some(epoll) = self.epoll.upgrade() else {
+            return false;
+        };
+        epoll.queue(self);
+        true
+    }
+}
+
+/// The state of an epoll instance, which its entries refer to.
+struct Inner {
+    /// The entries, by the descriptor and the address of their file, as a
+    /// file may be watched through several descriptors.
+    entries: Mutex<BTreeMap<(i32, usize), Arc<Entry>>>,
+    /// The entries which may have events.
+    ready: Mutex<VecDeque<Arc<Entry>>>,
+    /// The number of entries in `ready`, read without locking it.
+    ready_len: AtomicUsize,
+    /// The threads in `epoll_wait` wait here for ready entries.
+    wq: WaitQueue,
+    /// The wakers watching the instance, which is readable while it has
+    /// events.
+    poll: Arc<PollSet>,
+}
+
+impl Inner {
+    fn queue(&self, entry: Arc<Entry>) {
+        if !entry.queued.swap(true, Ordering::AcqRel) {
+            let mut ready = self.ready.lock();
+            ready.push_back(entry);
+            self.ready_len.store(ready.len(), Ordering::Release);
+        }
+        self.wq.notify_all(false);
+        self.poll.wake(PollEvents::READABLE);
+    }
+
+    /// Takes the events of at most `max` ready entries.
+    fn take_events(&self, max: usize) -> Vec<EpollEvent> {
+        let mut events = Vec::new();
+        let mut requeued = Vec::new();
+        let mut ready = self.ready.lock();
+        while events.len() < max {
+            let Some(entry) = ready.pop_front() else {
+                break;
+            };
+            entry.queued.store(false, Ordering::Release);
+            let Some(ready_events) = entry.ready_events() else {
+                continue;
+            };
+            events.push(EpollEvent {
+                events: ready_events.bits(),
+                data: entry.data.load(Ordering::Acquire),
+            });
+            let flags = entry.events.load(Ordering::Acquire);
+            if flags & EPOLLONESHOT != 0 {
+                entry.events.store(flags & PRIVATE_BITS, Ordering::Release);
+            } else if flags & EPOLLET == 0 {
+                requeued.push(entry);
+            }
+        }
+        for entry in requeued {
+            if !entry.queued.swap(true, Ordering::AcqRel) {
+                ready.push_back(entry);
+            }
+        }
+        self.ready_len.store(ready.len(), Ordering::Release);
+        events
+    }
+
+    /// Whether the instance watches `other`, directly or through the epoll
+    /// instances it watches.
+    fn watches(&self, other: &Inner) -> bool {
+        let entries: Vec<_> = self.entries.lock().values().cloned().collect();
+        entries.iter().any(|entry| {
+            let Some(file) = entry.file.upgrade() else {
+                return false;
+            };
+            let Some(epoll) = file.as_any().downcast_ref::<Epoll>() else {
+                return false;
+            };
+            core::ptr::eq(&*epoll.inner, other) || epoll.inner.watches(other)
+        })
+    }
+}
+
+/// An epoll instance.
+pub struct Epoll {
+    inner: Arc<Inner>,
+    flags: StatusFlags,
+}
+
+impl Epoll {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                entries: Mutex::new(BTreeMap::new()),
+                ready: Mutex::new(VecDeque::new()),
+                ready_len: AtomicUsize::new(0),
+                wq: WaitQueue::new(),
+                poll: Arc::new(PollSet::new()),
+            }),
+            flags: StatusFlags::new(OpenFlags::O_RDWR),
+        }
+    }
+
+    /// Starts watching `file`, open as `fd`, for the events and flags of
+    /// `event`, as `EPOLL_CTL_ADD`.
+    ///
+    /// Fails with `EPERM` if the events of `file` never change, e.g. if it is
+    /// a regular file, and with `ELOOP` if it is an epoll instance watching
+    /// this one.
+    pub fn add(&self, fd: i32, file: &Arc<dyn FileLike>, event: EpollEvent) -> LinuxResult {
+        let poll_set = file.poll_set().ok_or(LinuxError::EPERM)?;
+        let events = event.events | PollEvents::ALWAYS.bits();
+        let exclusive = events & EPOLLEXCLUSIVE != 0;
+        if let Some(epoll) = file.as_any().downcast_ref::<Epoll>() {
+            if Arc::ptr_eq(&epoll.inner, &self.inner) || exclusive {
+                return Err(LinuxError::EINVAL);
+            }
+            if epoll.inner.watches(&self.inner) {
+                return Err(LinuxError::ELOOP);
+            }
+        }
+        if exclusive && events & !EXCLUSIVE_OK_BITS != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+
+        let mut entries = self.inner.entries.lock();
+        // The entries of the closed files are dropped here, as their files
+        // do not drop them.
+        entries.retain(|_, entry| entry.file.strong_count() > 0);
+        let key = (fd, Arc::as_ptr(file) as *const () as usize);
+        if entries.contains_key(&key) {
+            return Err(LinuxError::EEXIST);
+        }
+        let entry = Arc::new(Entry {
+            file: Arc::downgrade(file),
+            events: AtomicU32::new(events),
+            data: AtomicU64::new(event.data),
+            queued: AtomicBool::new(false),
+            epoll: Arc::downgrade(&self.inner),
+        });
+        entries.insert(key, entry.clone());
+        drop(entries);
+        let waker: Weak<dyn PollWaker> = Arc::downgrade(&entry) as _;
+        poll_set.register(waker, exclusive);
+        if entry.ready_events().is_some() {
+            self.inner.queue(entry);
+        }
+        Ok(())
+    }
+
+    /// Changes the events and flags of the entry of `file`, open as `fd`, to
+    /// the ones of `event`, as `EPOLL_CTL_MOD`. This rearms a one-shot entry.
+    pub fn modify(&self, fd: i32, file: &Arc<dyn FileLike>, event: EpollEvent) -> LinuxResult {
+        let entry = self.entry(fd, file)?;
+        if (event.events | entry.events.load(Ordering::Acquire)) & EPOLLEXCLUSIVE != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        entry
+            .events
+            .store(event.events | PollEvents::ALWAYS.bits(), Ordering::Release);
+        entry.data.store(event.data, Ordering::Release);
+        if entry.ready_events().is_some() {
+            self.inner.queue(entry);
+        }
+        Ok(())
+    }
+
+    /// Stops watching `file`, open as `fd`, as `EPOLL_CTL_DEL`.
+    pub fn delete(&self, fd: i32, file: &Arc<dyn FileLike>) -> LinuxResult {
+        let entry = self.entry(fd, file)?;
+        // Disabled, in case it is still on the ready list.
+        entry.events.store(0, Ordering::Release);
+        self.inner
+            .entries
+            .lock()
+            .remove(&(fd, Arc::as_ptr(file) as *const () as usize));
+        Ok(())
+    }
+
+    fn entry(&self, fd: i32, file: &Arc<dyn FileLike>) -> LinuxResult<Arc<Entry>> {
+        let key = (fd, Arc::as_ptr(file) as *const () as usize);
+        self.inner
+            .entries
+            .lock()
+            .get(&key)
+            .filter(|entry| entry.file.strong_count() > 0)
+            .cloned()
+            .ok_or(LinuxError::ENOENT)
+    }
+
+    /// Returns the events of at most `max` files, blocking until there are
+    /// some or `timeout` expires, if it is not `None`.
+    ///
+    /// Fails with `EINTR` if the thread is interrupted by a signal.
+    pub fn wait(&self, max: usize, timeout: Option<Duration>) -> LinuxResult<Vec<EpollEvent>> {
+        let inner = &self.inner;
+        let deadline = timeout.map(|timeout| monotonic_time() + timeout);
+        loop {
+            let events = inner.take_events(max);
+            if !events.is_empty() {
+                return Ok(events);
+            }
+            let timeout = deadline.map(|deadline| deadline.saturating_sub(monotonic_time()));
+            if timeout == Some(Duration::ZERO) {
+                return Ok(events);
+            }
+            match wait_interruptible_timeout(&inner.wq, timeout, || {
+                inner.ready_len.load(Ordering::Acquire) > 0
+            }) {
+                Err(LinuxError::ETIMEDOUT) => {}
+                res => res?,
+            }
+        }
+    }
+}
+
+impl FileLike for Epoll {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        // An anonymous inode.
+        Ok(Kstat {
+            mode: 0o600,
+            nlink: 1,
+            blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    /// The instance is readable while one of its entries has events.
+    fn poll(&self) -> PollEvents {
+        let ready: Vec<_> = self.inner.ready.lock().iter().cloned().collect();
+        if ready.iter().any(|entry| entry.ready_events().is_some()) {
+            PollEvents::READABLE
+        } else {
+            PollEvents::empty()
+        }
+    }
+
+    fn poll_set(&self) -> Option<Arc<PollSet>> {
+        Some(self.inner.poll.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
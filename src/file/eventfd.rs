@@ -0,0 +1,171 @@
+//! Event counters read and written as files (`eventfd`).
+
+use alloc::sync::Arc;
+use core::{
+    any::Any,
+    mem::size_of,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::WaitQueue;
+
+use super::{FileLike, Kstat, PollEvents, PollSet, StatusFlags};
+use crate::{fs::OpenFlags, task::wait_interruptible};
+
+/// The highest value of the counter.
+const COUNTER_MAX: u64 = u64::MAX - 1;
+
+/// A file holding a counter: a write adds to it, and a read returns it and
+/// resets it, or decrements it in semaphore mode.
+pub struct EventFd {
+    counter: AtomicU64,
+    /// Whether a read returns 1 and decrements the counter, rather than
+    /// taking all of it (`EFD_SEMAPHORE`).
+    semaphore: bool,
+    /// Readers wait here for the counter not to be 0.
+    read_wq: WaitQueue,
+    /// Writers wait here for room in the counter.
+    write_wq: WaitQueue,
+    poll: Arc<PollSet>,
+    flags: StatusFlags,
+}
+
+impl EventFd {
+    pub fn new(initval: u64, semaphore: bool, nonblocking: bool) -> Self {
+        let mut flags = OpenFlags::O_RDWR;
+        flags.set(OpenFlags::O_NONBLOCK, nonblocking);
+        Self {
+            counter: AtomicU64::new(initval),
+            semaphore,
+            read_wq: WaitQueue::new(),
+            write_wq: WaitQueue::new(),
+            poll: Arc::new(PollSet::new()),
+            flags: StatusFlags::new(flags),
+        }
+    }
+
+    /// Reads the counter as a `u64`, blocking while it is 0 unless
+    /// `nonblocking` is set.
+    fn do_read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        if buf.len() < size_of::<u64>() {
+            return Err(LinuxError::EINVAL);
+        }
+        let value = loop {
+            let taken = self
+                .counter
+                .fetch_update(
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    |counter| match counter {
+                        0 => None,
+                        _ if self.semaphore => Some(counter - 1),
+                        _ => Some(0),
+                    },
+                );
+            if let Ok(counter) = taken {
+                break if self.semaphore { 1 } else { counter };
+            }
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            wait_interruptible(&self.read_wq, || self.counter.load(Ordering::Acquire) > 0)?;
+        };
+        buf[..size_of::<u64>()].copy_from_slice(&value.to_ne_bytes());
+        self.write_wq.notify_all(false);
+        self.poll.wake(PollEvents::WRITABLE);
+        Ok(size_of::<u64>())
+    }
+
+    /// Adds a `u64` to the counter, blocking while it would exceed its
+    /// highest value unless `nonblocking` is set.
+    fn do_write(&self, buf: &[u8], nonblocking: bool) -> LinuxResult<usize> {
+        let Some(bytes) = buf.get(..size_of::<u64>()) else {
+            return Err(LinuxError::EINVAL);
+        };
+        let value = u64::from_ne_bytes(bytes.try_into().unwrap());
+        if value == u64::MAX {
+            return Err(LinuxError::EINVAL);
+        }
+        let fits = |counter: u64| counter <= COUNTER_MAX - value;
+        loop {
+            let added = self
+                .counter
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |counter| {
+                    fits(counter).then(|| counter + value)
+                });
+            if added.is_ok() {
+                break;
+            }
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            wait_interruptible(&self.write_wq, || {
+                fits(self.counter.load(Ordering::Acquire))
+            })?;
+        }
+        if value > 0 {
+            self.read_wq.notify_all(false);
+            self.poll.wake(PollEvents::READABLE);
+        }
+        Ok(size_of::<u64>())
+    }
+}
+
+impl FileLike for EventFd {
+    /// Reads the counter as a `u64`, blocking while it is 0 unless the file
+    /// is non-blocking.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, self.flags.nonblocking())
+    }
+
+    fn read_nowait(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, true)
+    }
+
+    /// Adds a `u64` to the counter, blocking while it would exceed its
+    /// highest value unless the file is non-blocking.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.do_write(buf, self.flags.nonblocking())
+    }
+
+    fn write_nowait(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.do_write(buf, true)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        // An anonymous inode.
+        Ok(Kstat {
+            mode: 0o600,
+            nlink: 1,
+            blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    /// The file is readable while the counter is not 0, and writable while
+    /// 1 can be added to it.
+    fn poll(&self) -> PollEvents {
+        let counter = self.counter.load(Ordering::Acquire);
+        let mut events = PollEvents::empty();
+        if counter > 0 {
+            events |= PollEvents::READABLE;
+        }
+        if counter < COUNTER_MAX {
+            events |= PollEvents::WRITABLE;
+        }
+        events
+    }
+
+    fn poll_set(&self) -> Option<Arc<PollSet>> {
+        Some(self.poll.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,412 @@
+//! Files and file descriptors.
+//!
+//! Every kind of file (console, pipe, signalfd, ...) implements [`FileLike`].
+//! A process refers to its files by the descriptors in its [`FdTable`],
+//! which may be shared with other processes created by `clone` with
+//! `CLONE_FILES`.
+
+mod epoll;
+mod eventfd;
+mod pipe;
+mod poll;
+mod timerfd;
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+pub use self::{
+    epoll::{Epoll, EpollEvent},
+    eventfd::EventFd,
+    pipe::PipeEnd,
+    poll::{PollEvents, PollSet, PollWaker, Poller},
+    timerfd::{TimerFd, CLOCK_MONOTONIC, CLOCK_REALTIME},
+};
+use crate::{
+    fs::OpenFlags,
+    resource::RLIMIT_NOFILE,
+    tty::{console, TtyFile},
+};
+
+/// The highest number of file descriptors of a process, whatever its
+/// `RLIMIT_NOFILE` is.
+pub const NR_OPEN: usize = 1 << 20;
+
+/// The file type bits of `st_mode`.
+pub const S_IFMT: u32 = 0o170_000;
+pub const S_IFIFO: u32 = 0o010_000;
+pub const S_IFCHR: u32 = 0o020_000;
+pub const S_IFDIR: u32 = 0o040_000;
+pub const S_IFLNK: u32 = 0o120_000;
+
+/// Returns the device number of the device `major`:`minor`, as encoded in
+/// `st_dev` and `st_rdev`.
+pub const fn makedev(major: u32, minor: u32) -> u64 {
+    let (major, minor) = (major as u64, minor as u64);
+    ((major & 0xffff_f000) << 32)
+        | ((major & 0xfff) << 8)
+        | ((minor & 0xffff_ff00) << 12)
+        | (minor & 0xff)
+}
+
+/// The status of a file, converted to the `stat` or `statx` layout for user
+/// space.
+///
+/// The owner is always root, and the timestamps are not tracked.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Kstat {
+    /// The device containing the file.
+    pub dev: u64,
+    /// The inode number.
+    pub ino: u64,
+    /// The file type and permissions.
+    pub mode: u32,
+    /// The number of hard links.
+    pub nlink: u32,
+    /// The device of a device file.
+    pub rdev: u64,
+    pub size: u64,
+    /// The preferred block size for I/O.
+    pub blksize: u32,
+    /// The number of 512-byte blocks allocated.
+    pub blocks: u64,
+}
+
+/// Where `lseek` moves the offset of a file from.
+#[derive(Debug, Clone, Copy)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+impl SeekFrom {
+    /// Returns the new offset of a file whose offset is `offset` and size is
+    /// `size`, which must fit in the signed offsets of user space.
+    pub fn apply(self, offset: u64, size: u64) -> LinuxResult<u64> {
+        let (base, delta) = match self {
+            Self::Start(pos) => (pos, 0),
+            Self::Current(delta) => (offset, delta),
+            Self::End(delta) => (size, delta),
+        };
+        base.checked_add_signed(delta)
+            .filter(|&pos| pos <= i64::MAX as u64)
+            .ok_or(LinuxError::EINVAL)
+    }
+}
+
+/// The access mode and status flags of an open file, shared by all the
+/// descriptors referring to it, as returned by `F_GETFL`.
+pub struct StatusFlags(AtomicU32);
+
+impl StatusFlags {
+    /// The flags which can be changed by `F_SETFL`.
+    const SETTABLE: OpenFlags = OpenFlags::O_APPEND.union(OpenFlags::O_NONBLOCK);
+
+    /// Creates the status flags of a file opened with `flags`, without the
+    /// flags only used to open it.
+    pub fn new(flags: OpenFlags) -> Self {
+        let transient = OpenFlags::O_CREAT
+            | OpenFlags::O_EXCL
+            | OpenFlags::O_NOCTTY
+            | OpenFlags::O_TRUNC
+            | OpenFlags::O_CLOEXEC;
+        Self(AtomicU32::new((flags - transient).bits()))
+    }
+
+    pub fn get(&self) -> OpenFlags {
+        OpenFlags::from_bits_retain(self.0.load(Ordering::Acquire))
+    }
+
+    /// Replaces the flags which can be changed by `F_SETFL` with the ones in
+    /// `flags`, ignoring the others.
+    pub fn set(&self, flags: OpenFlags) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |old| {
+                let old = OpenFlags::from_bits_retain(old);
+                Some(((old - Self::SETTABLE) | (flags & Self::SETTABLE)).bits())
+            });
+    }
+
+    pub fn nonblocking(&self) -> bool {
+        self.get().contains(OpenFlags::O_NONBLOCK)
+    }
+}
+
+/// An open file.
+pub trait FileLike: Send + Sync {
+    /// Reads from the file into `buf`, returning the number of bytes read.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize>;
+
+    /// Writes `buf` to the file, returning the number of bytes written.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize>;
+
+    /// Reads from the file into `buf` as [`FileLike::read`], but fails with
+    /// `EAGAIN` instead of blocking, as if the file were non-blocking (e.g.
+    /// for `RWF_NOWAIT`).
+    ///
+    /// By default, the file is read as usual, for the files which never
+    /// block.
+    fn read_nowait(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.read(buf)
+    }
+
+    /// Writes `buf` to the file as [`FileLike::write`], but fails with
+    /// `EAGAIN` instead of blocking, as if the file were non-blocking.
+    ///
+    /// By default, the file is written as usual, for the files which never
+    /// block.
+    fn write_nowait(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.write(buf)
+    }
+
+    /// Returns the status of the file.
+    fn stat(&self) -> LinuxResult<Kstat>;
+
+    /// Returns the access mode and status flags of the file.
+    fn status_flags(&self) -> &StatusFlags;
+
+    /// Moves the offset of the file, returning the new offset.
+    fn seek(&self, _pos: SeekFrom) -> LinuxResult<u64> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Reads from the file at `offset` into `buf`, without using or moving
+    /// the offset of the file.
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Writes `buf` to the file at `offset`, without using or moving the
+    /// offset of the file.
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::ESPIPE)
+    }
+
+    /// Reads from the file into `bufs` in order, as a single read, which
+    /// returns the number of bytes read once there is some input.
+    ///
+    /// By default, only the first non-empty buffer is read into, as reading
+    /// into the next ones could block although some input was read.
+    fn read_vectored(&self, bufs: &mut [&mut [u8]]) -> LinuxResult<usize> {
+        match bufs.iter_mut().find(|buf| !buf.is_empty()) {
+            Some(buf) => self.read(buf),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns the events of the file which are ready.
+    ///
+    /// By default, the file is always readable and writable, as regular
+    /// files are.
+    fn poll(&self) -> PollEvents {
+        PollEvents::READABLE | PollEvents::WRITABLE
+    }
+
+    /// Returns the wakers woken up when the events of the file change, or
+    /// `None` if they never do.
+    fn poll_set(&self) -> Option<Arc<PollSet>> {
+        None
+    }
+
+    /// Returns the absolute path of the file, if it is in the filesystem.
+    fn path(&self) -> Option<&str> {
+        None
+    }
+
+    /// Executes the device-specific `ioctl` command `cmd` with the argument
+    /// `arg`. Fails with `ENOTTY` if the file does not support it.
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> LinuxResult<isize> {
+        Err(LinuxError::ENOTTY)
+    }
+
+    /// Returns the file as [`Any`], to downcast it to its concrete type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A file descriptor.
+#[derive(Clone)]
+struct FdEntry {
+    file: Arc<dyn FileLike>,
+    /// Whether the descriptor is closed by `execve` (`FD_CLOEXEC`).
+    cloexec: bool,
+}
+
+/// The file descriptor table of a process.
+pub struct FdTable {
+    /// The files indexed by descriptor, where closed descriptors are `None`.
+    entries: Mutex<Vec<Option<FdEntry>>>,
+}
+
+impl FdTable {
+    /// Creates an empty table.
+    pub const fn new() -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Creates a table with the console as the standard input, output and
+    /// error, which share the same open file.
+    pub fn with_stdio() -> Self {
+        let console: Arc<dyn FileLike> = Arc::new(TtyFile::new(console(), OpenFlags::O_RDWR));
+        let entry = FdEntry {
+            file: console,
+            cloexec: false,
+        };
+        let entries = alloc::vec![Some(entry); 3];
+        Self {
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Creates a copy of the table, referring to the same files, as `fork`
+    /// does.
+    pub fn fork(&self) -> Self {
+        Self {
+            entries: Mutex::new(self.entries.lock().clone()),
+        }
+    }
+
+    /// Returns the file of the descriptor `fd`.
+    pub fn get(&self, fd: i32) -> LinuxResult<Arc<dyn FileLike>> {
+        let entries = self.entries.lock();
+        usize::try_from(fd)
+            .ok()
+            .and_then(|fd| entries.get(fd)?.as_ref())
+            .map(|entry| entry.file.clone())
+            .ok_or(LinuxError::EBADF)
+    }
+
+    /// Adds `file` at the lowest free descriptor not lower than `min`, which
+    /// must be lower than `limit`.
+    pub fn add(
+        &self,
+        file: Arc<dyn FileLike>,
+        cloexec: bool,
+        min: usize,
+        limit: usize,
+    ) -> LinuxResult<i32> {
+        let mut entries = self.entries.lock();
+        let fd = (min..entries.len())
+            .find(|&fd| entries[fd].is_none())
+            .unwrap_or(entries.len().max(min));
+        if fd >= limit.min(NR_OPEN) {
+            return Err(LinuxError::EMFILE);
+        }
+        if fd >= entries.len() {
+            entries.resize(fd + 1, None);
+        }
+        entries[fd] = Some(FdEntry { file, cloexec });
+        Ok(fd as i32)
+    }
+
+    /// Puts `file` at the descriptor `fd`, which must be lower than `limit`,
+    /// closing the file which was there, as `dup2` does.
+    pub fn set(
+        &self,
+        fd: i32,
+        file: Arc<dyn FileLike>,
+        cloexec: bool,
+        limit: usize,
+    ) -> LinuxResult {
+        let fd = usize::try_from(fd)
+            .ok()
+            .filter(|&fd| fd < limit.min(NR_OPEN))
+            .ok_or(LinuxError::EBADF)?;
+        let mut entries = self.entries.lock();
+        if fd >= entries.len() {
+            entries.resize(fd + 1, None);
+        }
+        let old = entries[fd].replace(FdEntry { file, cloexec });
+        // The old file is dropped after the table is unlocked.
+        drop(entries);
+        drop(old);
+        Ok(())
+    }
+
+    /// Returns whether the descriptor `fd` has `FD_CLOEXEC`.
+    pub fn cloexec(&self, fd: i32) -> LinuxResult<bool> {
+        let entries = self.entries.lock();
+        usize::try_from(fd)
+            .ok()
+            .and_then(|fd| entries.get(fd)?.as_ref())
+            .map(|entry| entry.cloexec)
+            .ok_or(LinuxError::EBADF)
+    }
+
+    /// Sets or clears `FD_CLOEXEC` of the descriptor `fd`.
+    pub fn set_cloexec(&self, fd: i32, cloexec: bool) -> LinuxResult {
+        let mut entries = self.entries.lock();
+        let entry = usize::try_from(fd)
+            .ok()
+            .and_then(|fd| entries.get_mut(fd)?.as_mut())
+            .ok_or(LinuxError::EBADF)?;
+        entry.cloexec = cloexec;
+        Ok(())
+    }
+
+    /// Closes the descriptor `fd`, returning its file.
+    pub fn remove(&self, fd: i32) -> LinuxResult<Arc<dyn FileLike>> {
+        let mut entries = self.entries.lock();
+        usize::try_from(fd)
+            .ok()
+            .and_then(|fd| entries.get_mut(fd)?.take())
+            .map(|entry| entry.file)
+            .ok_or(LinuxError::EBADF)
+    }
+
+    /// Closes the descriptors with `FD_CLOEXEC`, as `execve` does.
+    pub fn close_on_exec(&self) {
+        let mut entries = self.entries.lock();
+        // The files are dropped after the table is unlocked.
+        let mut closed = Vec::new();
+        for entry in entries.iter_mut() {
+            if entry.as_ref().is_some_and(|entry| entry.cloexec) {
+                closed.push(entry.take());
+            }
+        }
+        drop(entries);
+        drop(closed);
+    }
+}
+
+/// Returns the file of the descriptor `fd` of the current process.
+pub fn get_file(fd: i32) -> LinuxResult<Arc<dyn FileLike>> {
+    current().task_ext().process.fd_table().get(fd)
+}
+
+/// Closes the descriptor `fd` of the current process.
+pub fn close_file(fd: i32) -> LinuxResult {
+    let file = current().task_ext().process.fd_table().remove(fd)?;
+    // The file is closed once the last descriptor referring to it is closed.
+    drop(file);
+    Ok(())
+}
+
+/// Returns the `RLIMIT_NOFILE` of the current process, above which it cannot
+/// have descriptors.
+pub fn nofile_limit() -> usize {
+    let limit = current()
+        .task_ext()
+        .process
+        .rlimits
+        .lock()
+        .get(RLIMIT_NOFILE)
+        .cur;
+    limit.try_into().unwrap_or(usize::MAX)
+}
+
+/// Adds `file` to the current process at its lowest free descriptor, within
+/// its `RLIMIT_NOFILE`.
+pub fn add_file(file: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult<i32> {
+    let fd_table = current().task_ext().process.fd_table();
+    fd_table.add(file, cloexec, 0, nofile_limit())
+}
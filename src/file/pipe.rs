@@ -0,0 +1,298 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use axtask::{current, TaskExtRef, WaitQueue};
+use memory_addr::PAGE_SIZE_4K;
+
+use super::{FileLike, Kstat, PollEvents, PollSet, StatusFlags, S_IFIFO};
+use crate::{
+    fs::OpenFlags,
+    signal::{send_thread_signal, SigInfo, SIGPIPE, SI_USER},
+    task::wait_interruptible,
+};
+
+/// The default capacity of a pipe.
+const PIPE_DEF_SIZE: usize = 16 * PAGE_SIZE_4K;
+/// The highest capacity of a pipe set by `F_SETPIPE_SZ`, as the default
+/// `/proc/sys/fs/pipe-max-size` of Linux.
+const PIPE_MAX_SIZE: usize = 1 << 20;
+/// Writes of at most this many bytes are atomic: they are not interleaved
+/// with other writes.
+const PIPE_BUF: usize = 4096;
+
+/// The buffer shared by the ends of a pipe.
+struct Pipe {
+    data: Mutex<VecDeque<u8>>,
+    /// The number of bytes in `data`, read without locking it.
+    len: AtomicUsize,
+    capacity: AtomicUsize,
+    /// The number of open read and write ends.
+    readers: AtomicUsize,
+    writers: AtomicUsize,
+    /// Readers wait here for data, or for all writers to close.
+    read_wq: WaitQueue,
+    /// Writers wait here for room, or for all readers to close.
+    write_wq: WaitQueue,
+    poll: Arc<PollSet>,
+}
+
+impl Pipe {
+    fn room(&self) -> usize {
+        self.capacity
+            .load(Ordering::Acquire)
+            .saturating_sub(self.len.load(Ordering::Acquire))
+    }
+}
+
+/// The read or write end of a pipe.
+pub struct PipeEnd {
+    pipe: Arc<Pipe>,
+    writable: bool,
+    flags: StatusFlags,
+}
+
+impl PipeEnd {
+    /// Creates a pipe, returning its read and write ends.
+    pub fn new_pair(nonblocking: bool) -> (Self, Self) {
+        let pipe = Arc::new(Pipe {
+            data: Mutex::new(VecDeque::new()),
+            len: AtomicUsize::new(0),
+            capacity: AtomicUsize::new(PIPE_DEF_SIZE),
+            readers: AtomicUsize::new(1),
+            writers: AtomicUsize::new(1),
+            read_wq: WaitQueue::new(),
+            write_wq: WaitQueue::new(),
+            poll: Arc::new(PollSet::new()),
+        });
+        let end = |writable| {
+            let mut flags = if writable {
+                OpenFlags::O_WRONLY
+            } else {
+                OpenFlags::empty()
+            };
+            flags.set(OpenFlags::O_NONBLOCK, nonblocking);
+            Self {
+                pipe: pipe.clone(),
+                writable,
+                flags: StatusFlags::new(flags),
+            }
+        };
+        (end(false), end(true))
+    }
+
+    /// Returns the capacity of the pipe, for `F_GETPIPE_SZ`.
+    pub fn capacity(&self) -> usize {
+        self.pipe.capacity.load(Ordering::Acquire)
+    }
+
+    /// Sets the capacity of the pipe to at least `size` bytes, for
+    /// `F_SETPIPE_SZ`, returning the actual capacity.
+    ///
+    /// The capacity is a power of two number of pages, and cannot be lower
+    /// than the data in the pipe.
+    pub fn set_capacity(&self, size: usize) -> LinuxResult<usize> {
+        if size > PIPE_MAX_SIZE {
+            return Err(LinuxError::EPERM);
+        }
+        let capacity = size.max(PAGE_SIZE_4K).next_power_of_two();
+        let data = self.pipe.data.lock();
+        if data.len() > capacity {
+            return Err(LinuxError::EBUSY);
+        }
+        self.pipe.capacity.store(capacity, Ordering::Release);
+        drop(data);
+        self.pipe.write_wq.notify_all(false);
+        self.pipe.poll.wake(PollEvents::WRITABLE);
+        Ok(capacity)
+    }
+
+    /// Reads the available data, blocking until there is some unless
+    /// `nonblocking` is set. Returns 0 once all write ends are closed and
+    /// the pipe is empty.
+    fn do_read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        if self.writable {
+            return Err(LinuxError::EBADF);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let pipe = &self.pipe;
+        loop {
+            // The writers are counted first, so that the data they wrote
+            // before closing is seen below.
+            let writers = pipe.writers.load(Ordering::Acquire);
+            let mut data = pipe.data.lock();
+            if !data.is_empty() {
+                let len = buf.len().min(data.len());
+                for (dst, src) in buf.iter_mut().zip(data.drain(..len)) {
+                    *dst = src;
+                }
+                pipe.len.store(data.len(), Ordering::Release);
+                drop(data);
+                pipe.write_wq.notify_all(false);
+                pipe.poll.wake(PollEvents::WRITABLE);
+                return Ok(len);
+            }
+            drop(data);
+            if writers == 0 {
+                return Ok(0);
+            }
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            wait_interruptible(&pipe.read_wq, || {
+                pipe.len.load(Ordering::Acquire) > 0 || pipe.writers.load(Ordering::Acquire) == 0
+            })?;
+        }
+    }
+
+    /// Writes all of `buf`, blocking while the pipe is full unless
+    /// `nonblocking` is set. A write of at most `PIPE_BUF` bytes is done at
+    /// once.
+    fn do_write(&self, buf: &[u8], nonblocking: bool) -> LinuxResult<usize> {
+        if !self.writable {
+            return Err(LinuxError::EBADF);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let pipe = &self.pipe;
+        let atomic = buf.len() <= PIPE_BUF;
+        let mut written = 0;
+        let result = loop {
+            if pipe.readers.load(Ordering::Acquire) == 0 {
+                let curr = current();
+                let pid = curr.task_ext().process.pid();
+                send_thread_signal(&curr, SigInfo::new(SIGPIPE, SI_USER).with_sender(pid));
+                break Err(LinuxError::EPIPE);
+            }
+            let remaining = &buf[written..];
+            let needed = if atomic { remaining.len() } else { 1 };
+            let mut data = pipe.data.lock();
+            let room = pipe
+                .capacity
+                .load(Ordering::Acquire)
+                .saturating_sub(data.len());
+            if room >= needed {
+                let len = room.min(remaining.len());
+                data.extend(&remaining[..len]);
+                pipe.len.store(data.len(), Ordering::Release);
+                drop(data);
+                pipe.read_wq.notify_all(false);
+                pipe.poll.wake(PollEvents::READABLE);
+                written += len;
+                if written == buf.len() {
+                    break Ok(());
+                }
+                continue;
+            }
+            drop(data);
+            if nonblocking {
+                break Err(LinuxError::EAGAIN);
+            }
+            if let Err(e) = wait_interruptible(&pipe.write_wq, || {
+                pipe.room() >= needed || pipe.readers.load(Ordering::Acquire) == 0
+            }) {
+                break Err(e);
+            }
+        };
+        match result {
+            Err(e) if written == 0 => Err(e),
+            _ => Ok(written),
+        }
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        let pipe = &self.pipe;
+        if self.writable {
+            pipe.writers.fetch_sub(1, Ordering::AcqRel);
+            pipe.read_wq.notify_all(false);
+            pipe.poll.wake(PollEvents::POLLHUP);
+        } else {
+            pipe.readers.fetch_sub(1, Ordering::AcqRel);
+            pipe.write_wq.notify_all(false);
+            pipe.poll.wake(PollEvents::POLLERR);
+        }
+    }
+}
+
+impl FileLike for PipeEnd {
+    /// Reads the available data, blocking until there is some unless the
+    /// pipe is non-blocking. Returns 0 once all write ends are closed and
+    /// the pipe is empty.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, self.flags.nonblocking())
+    }
+
+    fn read_nowait(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, true)
+    }
+
+    /// Writes all of `buf`, blocking while the pipe is full unless it is
+    /// non-blocking. A write of at most `PIPE_BUF` bytes is done at once.
+    ///
+    /// Fails with `EPIPE` and sends `SIGPIPE` to the current thread if all
+    /// read ends are closed. A partial write returns the bytes written.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.do_write(buf, self.flags.nonblocking())
+    }
+
+    fn write_nowait(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.do_write(buf, true)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            // Both ends have the inode of the pipe.
+            ino: Arc::as_ptr(&self.pipe) as u64,
+            mode: S_IFIFO | 0o600,
+            nlink: 1,
+            blksize: PAGE_SIZE_4K as u32,
+            ..Default::default()
+        })
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    /// The read end is readable while the pipe has data, and hung up once
+    /// all write ends are closed. The write end is writable while a write of
+    /// `PIPE_BUF` bytes does not block, and has an error once all read ends
+    /// are closed.
+    fn poll(&self) -> PollEvents {
+        let pipe = &self.pipe;
+        let mut events = PollEvents::empty();
+        if self.writable {
+            if pipe.room() >= PIPE_BUF {
+                events |= PollEvents::WRITABLE;
+            }
+            if pipe.readers.load(Ordering::Acquire) == 0 {
+                events |= PollEvents::POLLERR;
+            }
+        } else {
+            if pipe.len.load(Ordering::Acquire) > 0 {
+                events |= PollEvents::READABLE;
+            }
+            if pipe.writers.load(Ordering::Acquire) == 0 {
+                events |= PollEvents::POLLHUP;
+            }
+        }
+        events
+    }
+
+    fn poll_set(&self) -> Option<Arc<PollSet>> {
+        Some(self.pipe.poll.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,174 @@
+//! Waiting for the events of files, e.g. for them to become readable.
+//!
+//! A file whose events change has a [`PollSet`], which wakes up the
+//! [`PollWaker`]s registered on it whenever its events may have changed:
+//! the threads blocked in `poll` or `select`, which are [`Poller`]s, and the
+//! entries of the epoll instances watching the file.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+use crate::task::wait_interruptible_timeout;
+
+bitflags::bitflags! {
+    /// The events of a file, for `poll` and `epoll`.
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/poll.h>
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PollEvents: u32 {
+        /// There is data to read.
+        const POLLIN = 0x1;
+        /// There is urgent data to read.
+        const POLLPRI = 0x2;
+        /// Writing does not block.
+        const POLLOUT = 0x4;
+        /// An error occurred, e.g. the read end of a pipe is closed.
+        const POLLERR = 0x8;
+        /// The other end hung up, e.g. the write end of a pipe is closed.
+        const POLLHUP = 0x10;
+        /// The file descriptor is not open.
+        const POLLNVAL = 0x20;
+        const POLLRDNORM = 0x40;
+        const POLLRDBAND = 0x80;
+        const POLLWRNORM = 0x100;
+        const POLLWRBAND = 0x200;
+        /// The peer of a stream socket shut down writing.
+        const POLLRDHUP = 0x2000;
+    }
+}
+
+impl PollEvents {
+    /// The events of a file with data to read.
+    pub const READABLE: Self = Self::POLLIN.union(Self::POLLRDNORM);
+    /// The events of a file to which writing does not block.
+    pub const WRITABLE: Self = Self::POLLOUT.union(Self::POLLWRNORM);
+    /// The events which are reported whether they are requested or not.
+    pub const ALWAYS: Self = Self::POLLERR.union(Self::POLLHUP);
+}
+
+/// Something woken up by the files it watches when their events may have
+/// changed.
+pub trait PollWaker: Send + Sync {
+    /// Called when the `events` of a watched file may have changed. Returns
+    /// whether the wakeup was taken, which only matters to exclusive wakers.
+    fn wake(self: Arc<Self>, events: PollEvents) -> bool;
+}
+
+struct Entry {
+    waker: Weak<dyn PollWaker>,
+    exclusive: bool,
+}
+
+/// The wakers watching a file.
+///
+/// A waker is registered until it is dropped.
+pub struct PollSet(Mutex<Vec<Entry>>);
+
+impl PollSet {
+    pub const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    /// Registers `waker`. An exclusive waker is only woken up if no exclusive
+    /// waker registered before it takes the wakeup, as with
+    /// `EPOLLEXCLUSIVE`.
+    pub fn register(&self, waker: Weak<dyn PollWaker>, exclusive: bool) {
+        let mut entries = self.0.lock();
+        entries.retain(|entry| entry.waker.strong_count() > 0);
+        entries.push(Entry { waker, exclusive });
+    }
+
+    /// Wakes up the wakers, as the `events` of the file may have changed.
+    pub fn wake(&self, events: PollEvents) {
+        let wakers: Vec<_> = {
+            let mut entries = self.0.lock();
+            entries.retain(|entry| entry.waker.strong_count() > 0);
+            entries
+                .iter()
+                .filter_map(|entry| Some((entry.waker.upgrade()?, entry.exclusive)))
+                .collect()
+        };
+        // The wakers may wake up the wakers of other files, e.g. those of a
+        // watched epoll instance, so they are called without the lock.
+        let mut taken = false;
+        for (waker, exclusive) in wakers {
+            if !exclusive {
+                waker.wake(events);
+            } else if !taken {
+                taken = waker.wake(events);
+            }
+        }
+    }
+}
+
+/// A thread waiting in `poll` or `select` for the events of some files.
+pub struct Poller {
+    /// Whether a file woke up the thread since it last polled the files.
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl PollWaker for Poller {
+    fn wake(self: Arc<Self>, _events: PollEvents) -> bool {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_all(false);
+        true
+    }
+}
+
+impl Poller {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            woken: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+        })
+    }
+
+    /// Registers the poller on the file of `set`.
+    pub fn watch(self: &Arc<Self>, set: &PollSet) {
+        let waker: Weak<dyn PollWaker> = Arc::downgrade(self) as _;
+        set.register(waker, false);
+    }
+
+    /// Calls `poll` until it returns some events, which are returned, then
+    /// again whenever a watched file wakes up the poller, or until `timeout`
+    /// expires, if it is not `None`, in which case `None` is returned.
+    ///
+    /// Fails with `EINTR` if the thread is interrupted by a signal.
+    pub fn wait<T>(
+        &self,
+        timeout: Option<Duration>,
+        mut poll: impl FnMut() -> LinuxResult<Option<T>>,
+    ) -> LinuxResult<Option<T>> {
+        let deadline = timeout.map(|timeout| monotonic_time() + timeout);
+        loop {
+            // Cleared before polling, so that the events of the files which
+            // change afterwards are not missed.
+            self.woken.store(false, Ordering::Release);
+            if let Some(events) = poll()? {
+                return Ok(Some(events));
+            }
+            let timeout = deadline.map(|deadline| deadline.saturating_sub(monotonic_time()));
+            if timeout == Some(Duration::ZERO) {
+                return Ok(None);
+            }
+            match wait_interruptible_timeout(&self.wq, timeout, || {
+                self.woken.load(Ordering::Acquire)
+            }) {
+                Err(LinuxError::ETIMEDOUT) => {}
+                res => res?,
+            }
+        }
+    }
+}
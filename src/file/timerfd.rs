@@ -0,0 +1,301 @@
+//! Timers read as files (`timerfd`).
+//!
+//! A timer counts its expirations lazily, when it is read, from its next
+//! expiration and its interval. Readers block until the next expiration with
+//! a timeout, while the pollers are woken up at each expiration by the
+//! "timerfd" kernel task, which sleeps until the next expiration of all the
+//! timers.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    mem::size_of,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{monotonic_time, wall_time};
+use axsync::Mutex;
+use axtask::WaitQueue;
+
+use super::{FileLike, Kstat, PollEvents, PollSet, StatusFlags};
+use crate::{
+    fs::OpenFlags,
+    task::{wait_interruptible, wait_interruptible_timeout},
+};
+
+/// The clocks of timers.
+pub const CLOCK_REALTIME: u32 = 0;
+pub const CLOCK_MONOTONIC: u32 = 1;
+
+/// The value of [`TimerFd::deadline_ns`] when the timer is disarmed.
+const DISARMED: u64 = u64::MAX;
+
+/// The setting of a timer, in monotonic time.
+#[derive(Clone, Copy)]
+struct Setting {
+    /// The next expiration, or `None` if the timer is disarmed.
+    deadline: Option<Duration>,
+    /// The period of the timer, or 0 if it expires once.
+    interval: Duration,
+    /// When the pollers are next woken up, the first expiration after the
+    /// ones they were woken up for.
+    notify_at: Option<Duration>,
+}
+
+impl Setting {
+    /// Returns the number of expirations until `now`.
+    fn expirations(&self, now: Duration) -> u64 {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {
+                if self.interval.is_zero() {
+                    1
+                } else {
+                    1 + ((now - deadline).as_nanos() / self.interval.as_nanos()) as u64
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    /// Returns the first expiration after `now`, if any.
+    fn next_after(&self, now: Duration) -> Option<Duration> {
+        let deadline = self.deadline?;
+        if deadline > now {
+            return Some(deadline);
+        }
+        if self.interval.is_zero() {
+            return None;
+        }
+        let periods = self.expirations(now) as u128;
+        Some(deadline + Duration::from_nanos((self.interval.as_nanos() * periods) as u64))
+    }
+}
+
+/// A timer read as a file: a read returns the number of expirations since
+/// the timer was armed or last read, as a `u64`.
+pub struct TimerFd {
+    /// The clock of the absolute expirations.
+    clock: u32,
+    setting: Mutex<Setting>,
+    /// The next expiration in nanoseconds, or [`DISARMED`], read without
+    /// locking the setting.
+    deadline_ns: AtomicU64,
+    /// Readers wait here for the timer to expire.
+    read_wq: WaitQueue,
+    poll: Arc<PollSet>,
+    flags: StatusFlags,
+}
+
+impl TimerFd {
+    pub fn new(clock: u32, nonblocking: bool) -> Arc<Self> {
+        let mut flags = OpenFlags::O_RDWR;
+        flags.set(OpenFlags::O_NONBLOCK, nonblocking);
+        let timer = Arc::new(Self {
+            clock,
+            setting: Mutex::new(Setting {
+                deadline: None,
+                interval: Duration::ZERO,
+                notify_at: None,
+            }),
+            deadline_ns: AtomicU64::new(DISARMED),
+            read_wq: WaitQueue::new(),
+            poll: Arc::new(PollSet::new()),
+            flags: StatusFlags::new(flags),
+        });
+        TIMERS.lock().push(Arc::downgrade(&timer));
+        if !TIMER_TASK_STARTED.swap(true, Ordering::AcqRel) {
+            axtask::spawn_raw(
+                || run_timers(),
+                "timerfd".into(),
+                crate::config::KERNEL_STACK_SIZE,
+            );
+        }
+        timer
+    }
+
+    fn store_deadline(&self, deadline: Option<Duration>) {
+        let ns = deadline.map_or(DISARMED, |deadline| deadline.as_nanos() as u64);
+        self.deadline_ns.store(ns, Ordering::Release);
+    }
+
+    fn expired(&self) -> bool {
+        self.deadline_ns.load(Ordering::Acquire) <= monotonic_time().as_nanos() as u64
+    }
+
+    /// Returns the time until the next expiration, or 0 if the timer is
+    /// disarmed, and the interval.
+    pub fn get(&self) -> (Duration, Duration) {
+        let setting = *self.setting.lock();
+        let now = monotonic_time();
+        let left = setting
+            .next_after(now)
+            .map_or(Duration::ZERO, |next| next - now);
+        (left, setting.interval)
+    }
+
+    /// Arms the timer to expire after `value`, or at `value` of its clock if
+    /// `absolute` is set, then every `interval` if it is not 0, or disarms
+    /// it if `value` is 0. The expirations not read are discarded.
+    ///
+    /// Returns the previous setting, as [`TimerFd::get`] does.
+    pub fn set(&self, value: Duration, interval: Duration, absolute: bool) -> (Duration, Duration) {
+        let old = self.get();
+        let now = monotonic_time();
+        let deadline = if value.is_zero() {
+            None
+        } else if !absolute {
+            Some(now + value)
+        } else if self.clock == CLOCK_REALTIME {
+            // The realtime clock is the monotonic one with an offset, as it
+            // is never set.
+            let offset = wall_time().saturating_sub(now);
+            Some(value.saturating_sub(offset))
+        } else {
+            Some(value)
+        };
+        *self.setting.lock() = Setting {
+            deadline,
+            interval,
+            notify_at: deadline,
+        };
+        self.store_deadline(deadline);
+        self.read_wq.notify_all(false);
+        TIMERS_CHANGED.store(true, Ordering::Release);
+        TIMER_WQ.notify_all(false);
+        old
+    }
+
+    /// Wakes up the pollers if the timer expired since they were last woken
+    /// up, and returns when they are to be woken up next.
+    fn notify_pollers(&self, now: Duration) -> Option<Duration> {
+        let mut setting = self.setting.lock();
+        let notify_at = setting.notify_at?;
+        if notify_at > now {
+            return Some(notify_at);
+        }
+        let next = setting.next_after(now);
+        setting.notify_at = next;
+        drop(setting);
+        self.poll.wake(PollEvents::READABLE);
+        next
+    }
+
+    /// Reads the number of expirations, blocking until there is one unless
+    /// `nonblocking` is set.
+    fn do_read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        if buf.len() < size_of::<u64>() {
+            return Err(LinuxError::EINVAL);
+        }
+        let expirations = loop {
+            let mut setting = self.setting.lock();
+            let now = monotonic_time();
+            let expirations = setting.expirations(now);
+            if expirations > 0 {
+                setting.deadline = setting.next_after(now);
+                self.store_deadline(setting.deadline);
+                break expirations;
+            }
+            drop(setting);
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            let deadline = self.deadline_ns.load(Ordering::Acquire);
+            if deadline == DISARMED {
+                wait_interruptible(&self.read_wq, || self.expired())?;
+            } else {
+                let timeout = Duration::from_nanos(deadline).saturating_sub(now);
+                match wait_interruptible_timeout(&self.read_wq, Some(timeout), || self.expired()) {
+                    Err(LinuxError::ETIMEDOUT) => {}
+                    res => res?,
+                }
+            }
+        };
+        buf[..size_of::<u64>()].copy_from_slice(&expirations.to_ne_bytes());
+        Ok(size_of::<u64>())
+    }
+}
+
+impl FileLike for TimerFd {
+    /// Reads the number of expirations, blocking until there is one unless
+    /// the file is non-blocking.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, self.flags.nonblocking())
+    }
+
+    fn read_nowait(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, true)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        // An anonymous inode.
+        Ok(Kstat {
+            mode: 0o600,
+            nlink: 1,
+            blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    /// The file is readable once the timer expired.
+    fn poll(&self) -> PollEvents {
+        if self.expired() {
+            PollEvents::READABLE
+        } else {
+            PollEvents::empty()
+        }
+    }
+
+    fn poll_set(&self) -> Option<Arc<PollSet>> {
+        Some(self.poll.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The timers, whose pollers are woken up by the "timerfd" task.
+static TIMERS: Mutex<Vec<Weak<TimerFd>>> = Mutex::new(Vec::new());
+static TIMER_TASK_STARTED: AtomicBool = AtomicBool::new(false);
+/// Whether a timer was set since the "timerfd" task last looked at them.
+static TIMERS_CHANGED: AtomicBool = AtomicBool::new(false);
+/// The "timerfd" task waits here for the next expiration, or for a timer to
+/// be set.
+static TIMER_WQ: WaitQueue = WaitQueue::new();
+
+fn run_timers() -> ! {
+    loop {
+        TIMERS_CHANGED.store(false, Ordering::Release);
+        let timers: Vec<_> = {
+            let mut timers = TIMERS.lock();
+            timers.retain(|timer| timer.strong_count() > 0);
+            timers.iter().filter_map(Weak::upgrade).collect()
+        };
+        let now = monotonic_time();
+        let next = timers
+            .iter()
+            .filter_map(|timer| timer.notify_pollers(now))
+            .min();
+        drop(timers);
+        let changed = || TIMERS_CHANGED.load(Ordering::Acquire);
+        match next {
+            Some(next) => {
+                TIMER_WQ.wait_timeout_until(next.saturating_sub(monotonic_time()), changed);
+            }
+            None => TIMER_WQ.wait_until(changed),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! The page cache of regular files.
+//!
+//! The pages of a file mapped into memory are cached in frames shared by all
+//! its mappings and open files, indexed by its canonical path. Reads and
+//! writes go through the cached pages, so that they see the writes to shared
+//! mappings, which reach `axfs` only when the pages are written back.
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use axerrno::AxResult;
+use axfs::fops;
+use axsync::Mutex;
+use memory_addr::PAGE_SIZE_4K;
+
+use super::link::is_below;
+use crate::mm::Frame;
+
+const PAGE_SIZE: u64 = PAGE_SIZE_4K as u64;
+
+/// The cached pages of a file, indexed by their positions in the file in
+/// pages.
+pub struct PageCache {
+    pages: Mutex<BTreeMap<u64, Arc<Frame>>>,
+}
+
+/// The page caches of the files, indexed by their canonical paths. A cache
+/// is dropped with the last of its open files and mappings.
+static CACHES: Mutex<BTreeMap<String, Weak<PageCache>>> = Mutex::new(BTreeMap::new());
+
+/// Returns the page cache of the file at the canonical path `path`.
+pub fn get(path: &str) -> Arc<PageCache> {
+    let mut caches = CACHES.lock();
+    if let Some(cache) = caches.get(path).and_then(Weak::upgrade) {
+        return cache;
+    }
+    caches.retain(|_, cache| cache.strong_count() > 0);
+    let cache = Arc::new(PageCache {
+        pages: Mutex::new(BTreeMap::new()),
+    });
+    caches.insert(String::from(path), Arc::downgrade(&cache));
+    cache
+}
+
+/// Forgets the page cache of the file at `path`, which was removed.
+pub fn remove(path: &str) {
+    CACHES.lock().remove(path);
+}
+
+/// Moves the page caches of the file or directory moved from `from` to `to`.
+pub fn moved(from: &str, to: &str) {
+    let mut caches = CACHES.lock();
+    let moved_paths: Vec<String> = caches
+        .keys()
+        .filter(|path| path.as_str() == from || is_below(path, from))
+        .cloned()
+        .collect();
+    for path in moved_paths {
+        let cache = caches.remove(&path).unwrap();
+        caches.insert(alloc::format!("{to}{}", &path[from.len()..]), cache);
+    }
+}
+
+impl PageCache {
+    /// Returns the page `index` of `file`, reading it from `file` if it is
+    /// not cached. The part of the page beyond the end of the file is
+    /// zero-filled.
+    pub fn page(&self, file: &fops::File, index: u64) -> AxResult<Arc<Frame>> {
+        let mut pages = self.pages.lock();
+        if let Some(frame) = pages.get(&index) {
+            return Ok(frame.clone());
+        }
+        let frame = Frame::new_zeroed()?;
+        let buf = frame.as_mut_slice();
+        let mut len = 0;
+        while len < buf.len() {
+            match file.read_at(index * PAGE_SIZE + len as u64, &mut buf[len..])? {
+                0 => break,
+                read => len += read,
+            }
+        }
+        pages.insert(index, frame.clone());
+        Ok(frame)
+    }
+
+    /// Reads from `file` at `offset`, from the cached pages where there are
+    /// some.
+    pub fn read_at(&self, file: &fops::File, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let pages = self.pages.lock();
+        let size = file.get_attr()?.size();
+        let end = size.min(offset.saturating_add(buf.len() as u64));
+        let mut pos = offset;
+        while pos < end {
+            let index = pos / PAGE_SIZE;
+            let start = (pos - offset) as usize;
+            if let Some(frame) = pages.get(&index) {
+                let len = end.min((index + 1) * PAGE_SIZE) - pos;
+                let page_offset = (pos % PAGE_SIZE) as usize;
+                buf[start..start + len as usize]
+                    .copy_from_slice(&frame.as_slice()[page_offset..page_offset + len as usize]);
+                pos += len;
+            } else {
+                // Up to the next cached page.
+                let next = pages
+                    .range(index..)
+                    .next()
+                    .map_or(end, |(&i, _)| i * PAGE_SIZE);
+                let len = (end.min(next) - pos) as usize;
+                match file.read_at(pos, &mut buf[start..start + len])? {
+                    0 => break,
+                    read => pos += read as u64,
+                }
+            }
+        }
+        Ok((pos - offset) as usize)
+    }
+
+    /// Writes to `file` at `offset`, and to the cached pages.
+    pub fn write_at(&self, file: &fops::File, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        let pages = self.pages.lock();
+        let len = file.write_at(offset, buf)?;
+        let end = offset + len as u64;
+        let first = offset / PAGE_SIZE;
+        for (&index, frame) in pages.range(first..end.div_ceil(PAGE_SIZE)) {
+            let page_start = index * PAGE_SIZE;
+            let from = offset.max(page_start);
+            let to = end.min(page_start + PAGE_SIZE);
+            frame.as_mut_slice()[(from - page_start) as usize..(to - page_start) as usize]
+                .copy_from_slice(&buf[(from - offset) as usize..(to - offset) as usize]);
+        }
+        Ok(len)
+    }
+
+    /// Writes the cached pages of `[start, end)` back to `file`, which is not
+    /// extended by them.
+    pub fn write_back(&self, file: &fops::File, start: u64, end: u64) -> AxResult {
+        let pages = self.pages.lock();
+        let size = file.get_attr()?.size();
+        let end = end.min(size);
+        if start >= end {
+            return Ok(());
+        }
+        for (&index, frame) in pages.range(start / PAGE_SIZE..end.div_ceil(PAGE_SIZE)) {
+            let page_start = index * PAGE_SIZE;
+            let len = (end.min(page_start + PAGE_SIZE) - page_start) as usize;
+            file.write_at(page_start, &frame.as_slice()[..len])?;
+        }
+        Ok(())
+    }
+
+    /// Drops the cached pages, as the file was truncated to 0.
+    ///
+    /// The pages still mapped keep their content.
+    pub fn clear(&self) {
+        self.pages.lock().clear();
+    }
+}
@@ -0,0 +1,104 @@
+//! The `devpts` filesystem at `/dev/pts`, with the slaves of the
+//! pseudo-terminals, and `/dev/ptmx`, which creates them.
+//!
+//! They are looked up before `axfs`, which knows nothing about them.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use axerrno::{LinuxError, LinuxResult};
+
+use super::{DirEntry, Directory, OpenFlags, DT_CHR, DT_DIR};
+use crate::{
+    file::{makedev, FileLike, Kstat, S_IFCHR, S_IFDIR},
+    tty::{open_ptmx, open_pts, pts_indices, pts_rdev, PTMX_RDEV},
+};
+
+const PTMX: &str = "/dev/ptmx";
+const PTS: &str = "/dev/pts";
+
+/// The device number of `devpts`, an anonymous device.
+const DEVPTS_DEV: u64 = makedev(0, 22);
+
+/// A file of `devpts`, or `/dev/ptmx`.
+pub enum Node {
+    Ptmx,
+    /// The directory `/dev/pts`.
+    Root,
+    /// The slave `/dev/pts/{index}`.
+    Slave(u32),
+}
+
+impl Node {
+    /// Returns the node at the absolute path `path`, if there is one.
+    pub fn lookup(path: &str) -> Option<Self> {
+        match path {
+            PTMX => return Some(Self::Ptmx),
+            PTS => return Some(Self::Root),
+            _ => {}
+        }
+        let name = path.strip_prefix(PTS)?.strip_prefix('/')?;
+        if name == "ptmx" {
+            return Some(Self::Ptmx);
+        }
+        let index: u32 = name.parse().ok()?;
+        // There is no leading zero in the names.
+        (alloc::format!("{index}") == name && pts_indices().contains(&index))
+            .then_some(Self::Slave(index))
+    }
+
+    pub fn stat(&self) -> Kstat {
+        let (ino, mode, rdev) = match *self {
+            Self::Ptmx => (2, S_IFCHR | 0o666, PTMX_RDEV),
+            Self::Root => (1, S_IFDIR | 0o755, 0),
+            Self::Slave(index) => (index as u64 + 3, S_IFCHR | 0o620, pts_rdev(index)),
+        };
+        Kstat {
+            dev: DEVPTS_DEV,
+            ino,
+            mode,
+            nlink: if matches!(self, Self::Root) { 2 } else { 1 },
+            rdev,
+            blksize: 1024,
+            ..Default::default()
+        }
+    }
+
+    pub fn open(&self, flags: OpenFlags) -> LinuxResult<Arc<dyn FileLike>> {
+        match *self {
+            Self::Ptmx => open_ptmx(flags),
+            Self::Root if flags.writable() => Err(LinuxError::EISDIR),
+            Self::Root => Ok(Arc::new(Directory::new(PTS, flags))),
+            Self::Slave(index) => open_pts(index, flags),
+        }
+    }
+}
+
+/// Returns the entries added to the directory `dir` by `devpts`.
+pub fn entries(dir: &str) -> Vec<DirEntry> {
+    let nodes = match dir {
+        "/dev" => alloc::vec![
+            (String::from("ptmx"), Node::Ptmx),
+            (String::from("pts"), Node::Root),
+        ],
+        PTS => core::iter::once((String::from("ptmx"), Node::Ptmx))
+            .chain(
+                pts_indices()
+                    .into_iter()
+                    .map(|index| (alloc::format!("{index}"), Node::Slave(index))),
+            )
+            .collect(),
+        _ => Vec::new(),
+    };
+    nodes
+        .into_iter()
+        .map(|(name, node)| DirEntry {
+            name,
+            ino: node.stat().ino,
+            file_type: if matches!(node, Node::Root) {
+                DT_DIR
+            } else {
+                DT_CHR
+            },
+        })
+        .collect()
+}
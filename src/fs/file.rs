@@ -0,0 +1,340 @@
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::{any::Any, mem::size_of};
+
+use axerrno::{AxResult, LinuxError, LinuxResult};
+use axfs::fops;
+use axsync::Mutex;
+
+use super::{
+    cache::{self, PageCache},
+    inode, parent, read_dir, stat, to_kstat, DirEntry, OpenFlags, DT_DIR,
+};
+use crate::{
+    file::{FileLike, Kstat, SeekFrom, StatusFlags},
+    mm::Frame,
+};
+
+/// A regular file opened in the filesystem.
+pub struct File {
+    path: String,
+    /// The inode number, kept while the file is open even if it is moved.
+    ino: u64,
+    inner: fops::File,
+    /// The pages of the file shared with its other open files and mappings.
+    cache: Arc<PageCache>,
+    /// The offset of the next read or write.
+    offset: Mutex<u64>,
+    flags: StatusFlags,
+}
+
+impl File {
+    pub fn new(path: &str, inner: fops::File, flags: OpenFlags) -> Self {
+        let cache = cache::get(path);
+        if flags.contains(OpenFlags::O_TRUNC) {
+            cache.clear();
+        }
+        Self {
+            path: String::from(path),
+            ino: inode::get(path),
+            inner,
+            cache,
+            offset: Mutex::new(0),
+            flags: StatusFlags::new(flags),
+        }
+    }
+
+    /// Returns the page `index` of the file from the page cache, to be
+    /// mapped into memory.
+    pub fn page(&self, index: u64) -> AxResult<Arc<Frame>> {
+        self.cache.page(&self.inner, index)
+    }
+
+    /// Writes the cached pages of `[start, end)` back to the file, as they
+    /// may have been written through shared mappings.
+    ///
+    /// Nothing is written back through a file not opened for writing, as its
+    /// shared mappings cannot be writable.
+    pub fn write_back(&self, start: u64, end: u64) -> AxResult {
+        if !self.flags.get().writable() {
+            return Ok(());
+        }
+        self.cache.write_back(&self.inner, start, end)
+    }
+}
+
+impl FileLike for File {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if !self.flags.get().readable() {
+            return Err(LinuxError::EBADF);
+        }
+        let mut offset = self.offset.lock();
+        let len = self.cache.read_at(&self.inner, *offset, buf)?;
+        *offset += len as u64;
+        Ok(len)
+    }
+
+    /// Writes at the offset, or at the end of the file with `O_APPEND`, which
+    /// `axfs` does not implement for `write_at`.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let flags = self.flags.get();
+        if !flags.writable() {
+            return Err(LinuxError::EBADF);
+        }
+        let mut offset = self.offset.lock();
+        if flags.contains(OpenFlags::O_APPEND) {
+            *offset = self.inner.get_attr()?.size();
+        }
+        let len = self.cache.write_at(&self.inner, *offset, buf)?;
+        *offset += len as u64;
+        Ok(len)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(to_kstat(&self.path, self.ino, &self.inner.get_attr()?))
+    }
+
+    fn seek(&self, pos: SeekFrom) -> LinuxResult<u64> {
+        let mut offset = self.offset.lock();
+        let size = self.inner.get_attr()?.size();
+        *offset = pos.apply(*offset, size)?;
+        Ok(*offset)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        if !self.flags.get().readable() {
+            return Err(LinuxError::EBADF);
+        }
+        Ok(self.cache.read_at(&self.inner, offset, buf)?)
+    }
+
+    /// Writes at `offset`, or at the end of the file with `O_APPEND`, as
+    /// Linux does.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        let flags = self.flags.get();
+        if !flags.writable() {
+            return Err(LinuxError::EBADF);
+        }
+        let offset = if flags.contains(OpenFlags::O_APPEND) {
+            self.inner.get_attr()?.size()
+        } else {
+            offset
+        };
+        Ok(self.cache.write_at(&self.inner, offset, buf)?)
+    }
+
+    /// Reads into all of `bufs`, as reading a regular file does not block.
+    fn read_vectored(&self, bufs: &mut [&mut [u8]]) -> LinuxResult<usize> {
+        let mut len = 0;
+        for buf in bufs.iter_mut() {
+            match self.read(buf) {
+                Ok(read) => {
+                    len += read;
+                    if read < buf.len() {
+                        break;
+                    }
+                }
+                Err(_) if len > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(len)
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A directory opened in the filesystem.
+///
+/// The offset of a directory is the number of entries listed. The entries
+/// are listed from a snapshot taken when listing from the start, so that the
+/// offsets remain valid when entries are added or removed in the meantime.
+pub struct Directory {
+    path: String,
+    cursor: Mutex<DirCursor>,
+    flags: StatusFlags,
+}
+
+/// The position of a listing of a directory.
+struct DirCursor {
+    offset: u64,
+    /// The entries being listed, or `None` before listing.
+    entries: Option<Vec<DirEntry>>,
+}
+
+/// The header of `struct linux_dirent64`, which is followed by the name.
+#[repr(C, packed)]
+struct Dirent64Header {
+    ino: u64,
+    off: i64,
+    reclen: u16,
+    file_type: u8,
+}
+
+impl Directory {
+    pub fn new(path: &str, flags: OpenFlags) -> Self {
+        Self {
+            path: String::from(path),
+            cursor: Mutex::new(DirCursor {
+                offset: 0,
+                entries: None,
+            }),
+            flags: StatusFlags::new(flags),
+        }
+    }
+
+    /// Returns the entries of the directory, `.` and `..` first.
+    fn entries(&self) -> LinuxResult<Vec<DirEntry>> {
+        let mut entries = vec![
+            DirEntry {
+                name: String::from("."),
+                ino: inode::get(&self.path),
+                file_type: DT_DIR,
+            },
+            DirEntry {
+                name: String::from(".."),
+                ino: inode::get(parent(&self.path)),
+                file_type: DT_DIR,
+            },
+        ];
+        entries.extend(read_dir(&self.path)?);
+        Ok(entries)
+    }
+
+    /// Lists the entries after the offset into `buf` as `struct
+    /// linux_dirent64`, returning the number of bytes written, which is 0 at
+    /// the end of the directory.
+    pub fn read_entries(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let mut cursor = self.cursor.lock();
+        if cursor.offset == 0 || cursor.entries.is_none() {
+            cursor.entries = Some(self.entries()?);
+        }
+        let DirCursor { offset, entries } = &mut *cursor;
+        let entries = entries.as_deref().unwrap_or_default();
+
+        let mut len = 0;
+        for entry in entries.iter().skip(*offset as usize) {
+            const HEADER_SIZE: usize = size_of::<Dirent64Header>();
+            let reclen = (HEADER_SIZE + entry.name.len() + 1).next_multiple_of(8);
+            if len + reclen > buf.len() {
+                if len == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                break;
+            }
+            let header = Dirent64Header {
+                ino: entry.ino,
+                // The offset of the next entry.
+                off: (*offset + 1) as i64,
+                reclen: reclen as u16,
+                file_type: entry.file_type,
+            };
+            let record = &mut buf[len..len + reclen];
+            let header: [u8; HEADER_SIZE] = unsafe { core::mem::transmute(header) };
+            record[..HEADER_SIZE].copy_from_slice(&header);
+            let name_end = HEADER_SIZE + entry.name.len();
+            record[HEADER_SIZE..name_end].copy_from_slice(entry.name.as_bytes());
+            record[name_end..].fill(0);
+            len += reclen;
+            *offset += 1;
+        }
+        Ok(len)
+    }
+}
+
+impl FileLike for Directory {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EISDIR)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EISDIR)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        stat(&self.path)
+    }
+
+    /// Moves to a position returned in `d_off`, or to the start with 0,
+    /// which lists the entries again from a new snapshot.
+    fn seek(&self, pos: SeekFrom) -> LinuxResult<u64> {
+        let mut cursor = self.cursor.lock();
+        match pos {
+            SeekFrom::Start(pos) => cursor.offset = pos,
+            SeekFrom::Current(0) => {}
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(cursor.offset)
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A file opened with `O_PATH`, which only refers to its location in the
+/// filesystem.
+pub struct PathFile {
+    path: String,
+    flags: StatusFlags,
+}
+
+impl PathFile {
+    pub fn new(path: &str, flags: OpenFlags) -> Self {
+        Self {
+            path: String::from(path),
+            flags: StatusFlags::new(flags),
+        }
+    }
+}
+
+impl FileLike for PathFile {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        stat(&self.path)
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    fn path(&self) -> Option<&str> {
+        Some(&self.path)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
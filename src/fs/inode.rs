@@ -0,0 +1,54 @@
+//! Inode numbers of the files in `axfs`.
+//!
+//! `axfs` does not expose inode numbers, so they are assigned here in order
+//! when the files are first seen, indexed by their canonical paths. They
+//! follow the files when they are moved, and are forgotten when they are
+//! removed, so that they stay unique and do not change while the files
+//! exist.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axsync::Mutex;
+
+use super::link::is_below;
+
+/// The next inode number to assign. 1 is skipped, as it is often reserved.
+static NEXT_INO: AtomicU64 = AtomicU64::new(2);
+
+/// The inode numbers of the files, indexed by their canonical paths.
+static INODES: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// Returns a new inode number, e.g. for a symbolic link.
+pub fn alloc() -> u64 {
+    NEXT_INO.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Returns the inode number of the file at the canonical path `path`,
+/// assigning one if it has none yet.
+pub fn get(path: &str) -> u64 {
+    *INODES
+        .lock()
+        .entry(String::from(path))
+        .or_insert_with(alloc)
+}
+
+/// Forgets the inode number of the file at `path`, which was removed.
+pub fn remove(path: &str) {
+    INODES.lock().remove(path);
+}
+
+/// Moves the inode numbers of the file or directory moved from `from` to
+/// `to`.
+pub fn moved(from: &str, to: &str) {
+    let mut inodes = INODES.lock();
+    let moved_paths: Vec<String> = inodes
+        .keys()
+        .filter(|path| path.as_str() == from || is_below(path, from))
+        .cloned()
+        .collect();
+    for path in moved_paths {
+        let ino = inodes.remove(&path).unwrap();
+        inodes.insert(alloc::format!("{to}{}", &path[from.len()..]), ino);
+    }
+}
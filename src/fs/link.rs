@@ -0,0 +1,140 @@
+//! Links emulated over `axfs`.
+//!
+//! The root filesystem (FAT) has neither symbolic nor hard links, so they
+//! are kept in memory, indexed by their absolute path, and are lost on
+//! reboot. A hard link is an alias of the path of the file it was created
+//! from, which is its canonical path in `axfs`.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use axsync::Mutex;
+
+#[derive(Clone)]
+pub enum Link {
+    /// A symbolic link to its target, as given to `symlink`, with its inode
+    /// number.
+    Symbolic { target: String, ino: u64 },
+    /// A hard link to the canonical path of a file.
+    Hard(String),
+}
+
+static LINKS: Mutex<BTreeMap<String, Link>> = Mutex::new(BTreeMap::new());
+
+/// Returns whether `path` is in the directory `dir` or below it.
+pub fn is_below(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+pub fn get(path: &str) -> Option<Link> {
+    LINKS.lock().get(path).cloned()
+}
+
+/// Returns the target of the symbolic link at `path`.
+pub fn symlink_target(path: &str) -> Option<String> {
+    match get(path)? {
+        Link::Symbolic { target, .. } => Some(target),
+        Link::Hard(_) => None,
+    }
+}
+
+/// Returns the path of the file at `path` in `axfs`, which differs from
+/// `path` for a hard link.
+pub fn canonical(path: &str) -> String {
+    match get(path) {
+        Some(Link::Hard(canonical)) => canonical,
+        _ => String::from(path),
+    }
+}
+
+/// Returns the hard links to the file at the canonical path `canonical`.
+pub fn aliases(canonical: &str) -> Vec<String> {
+    LINKS
+        .lock()
+        .iter()
+        .filter(|(_, link)| matches!(link, Link::Hard(target) if target == canonical))
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Returns the links directly in the directory `dir`, by name.
+pub fn children(dir: &str) -> Vec<(String, Link)> {
+    LINKS
+        .lock()
+        .iter()
+        .filter_map(|(path, link)| {
+            let (parent, name) = path.rsplit_once('/')?;
+            let parent = if parent.is_empty() { "/" } else { parent };
+            (parent == dir).then(|| (String::from(name), link.clone()))
+        })
+        .collect()
+}
+
+pub fn insert(path: &str, link: Link) {
+    LINKS.lock().insert(String::from(path), link);
+}
+
+pub fn remove(path: &str) -> Option<Link> {
+    LINKS.lock().remove(path)
+}
+
+/// Exchanges the links at `a` and `b` at once, and returns whether both
+/// were links.
+pub fn exchange(a: &str, b: &str) -> bool {
+    let mut links = LINKS.lock();
+    let (Some(link_a), Some(link_b)) = (links.get(a).cloned(), links.get(b).cloned()) else {
+        return false;
+    };
+    links.insert(String::from(a), link_b);
+    links.insert(String::from(b), link_a);
+    true
+}
+
+/// Updates the links after the file or directory at `from` in `axfs` was
+/// moved to `to`: the links below a directory move with it, and hard links
+/// follow their file.
+pub fn moved(from: &str, to: &str) {
+    let rebase = |path: &str| {
+        if path == from {
+            Some(String::from(to))
+        } else if is_below(path, from) {
+            Some(alloc::format!("{to}{}", &path[from.len()..]))
+        } else {
+            None
+        }
+    };
+    let mut links = LINKS.lock();
+    let moved_paths: Vec<String> = links
+        .keys()
+        .filter(|path| is_below(path, from))
+        .cloned()
+        .collect();
+    for path in moved_paths {
+        let link = links.remove(&path).unwrap();
+        links.insert(rebase(&path).unwrap(), link);
+    }
+    for link in links.values_mut() {
+        if let Link::Hard(canonical) = link {
+            if let Some(rebased) = rebase(canonical) {
+                *canonical = rebased;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_below;
+
+    #[test]
+    fn path_below_directory() {
+        assert!(is_below("/a/b", "/a"));
+        assert!(is_below("/a/b/c", "/a"));
+        assert!(is_below("/a", "/"));
+        assert!(!is_below("/a", "/a"));
+        assert!(!is_below("/ab", "/a"));
+        assert!(!is_below("/a", "/a/b"));
+    }
+}
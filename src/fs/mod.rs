@@ -0,0 +1,582 @@
+//! Files in the filesystem and path resolution.
+//!
+//! The root filesystem is provided by `axfs`. Paths given by user space are
+//! resolved here to normalized absolute paths before they are passed to
+//! `axfs`, unless they are in `devpts`.
+
+mod cache;
+mod devpts;
+mod file;
+mod inode;
+mod link;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::{self, FileAttr, OpenOptions};
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+pub use self::file::{Directory, File};
+use self::{devpts::Node, file::PathFile, link::Link};
+use crate::file::{get_file, FileLike, Kstat, S_IFDIR, S_IFLNK, S_IFMT};
+
+/// The `dirfd` referring to the current directory.
+pub const AT_FDCWD: i32 = -100;
+/// Do not follow a symbolic link at the end of the path.
+pub const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+/// Follow a symbolic link at the end of the path.
+pub const AT_SYMLINK_FOLLOW: u32 = 0x400;
+/// Operate on the file `dirfd` itself if the path is empty.
+pub const AT_EMPTY_PATH: u32 = 0x1000;
+
+/// The device number reported for the files of the root filesystem.
+const ROOT_DEV: u64 = 0x800;
+
+/// The highest number of symbolic links followed to resolve a path.
+const MAXSYMLINKS: usize = 40;
+
+/// The `d_type` of character devices, directories, regular files and
+/// symbolic links.
+const DT_CHR: u8 = 2;
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+const DT_LNK: u8 = 10;
+
+bitflags::bitflags! {
+    /// flags for sys_openat
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/fcntl.h>
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OpenFlags: u32 {
+        /// Open for writing only.
+        const O_WRONLY = 0o1;
+        /// Open for reading and writing.
+        const O_RDWR = 0o2;
+        /// Create the file if it does not exist.
+        const O_CREAT = 0o100;
+        /// Fail if the file exists, with `O_CREAT`.
+        const O_EXCL = 0o200;
+        /// Do not make the file the controlling terminal.
+        const O_NOCTTY = 0o400;
+        /// Truncate the file to length 0.
+        const O_TRUNC = 0o1000;
+        /// Write at the end of the file.
+        const O_APPEND = 0o2000;
+        /// Do not block on reads and writes.
+        const O_NONBLOCK = 0o4000;
+        /// Synchronous writes of the data.
+        const O_DSYNC = 0o10000;
+        /// Fail if the file is not a directory.
+        #[cfg(not(target_arch = "aarch64"))]
+        const O_DIRECTORY = 0o200000;
+        #[cfg(target_arch = "aarch64")]
+        const O_DIRECTORY = 0o40000;
+        /// Fail if the last component of the path is a symbolic link.
+        #[cfg(not(target_arch = "aarch64"))]
+        const O_NOFOLLOW = 0o400000;
+        #[cfg(target_arch = "aarch64")]
+        const O_NOFOLLOW = 0o100000;
+        /// Do not update the access time.
+        const O_NOATIME = 0o1000000;
+        /// Close the file on `execve`.
+        const O_CLOEXEC = 0o2000000;
+        /// Synchronous writes of the data and metadata.
+        const O_SYNC = 0o4010000;
+        /// Only refer to the file in the filesystem, without opening it.
+        const O_PATH = 0o10000000;
+    }
+}
+
+impl OpenFlags {
+    /// The bits of the access mode.
+    const O_ACCMODE: u32 = 0o3;
+
+    pub fn readable(&self) -> bool {
+        self.bits() & Self::O_ACCMODE != Self::O_WRONLY.bits()
+    }
+
+    pub fn writable(&self) -> bool {
+        self.bits() & Self::O_ACCMODE != 0
+    }
+}
+
+/// Returns the parent directory of the absolute path `path`.
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+/// The current and root directories of a process, which may be shared with
+/// other processes created by `clone` with `CLONE_FS`.
+///
+/// Both are absolute paths in `axfs`. The paths of the process are resolved
+/// below its root directory, which is `/` unless it called `chroot`.
+pub struct FsContext {
+    cwd: Mutex<String>,
+    root: Mutex<String>,
+}
+
+impl FsContext {
+    /// Creates a context with `/` as the current and root directories.
+    pub fn new() -> Self {
+        Self {
+            cwd: Mutex::new(String::from("/")),
+            root: Mutex::new(String::from("/")),
+        }
+    }
+
+    /// Creates a copy of the context, as `fork` does.
+    pub fn fork(&self) -> Self {
+        Self {
+            cwd: Mutex::new(self.cwd()),
+            root: Mutex::new(self.root()),
+        }
+    }
+
+    pub fn cwd(&self) -> String {
+        self.cwd.lock().clone()
+    }
+
+    pub fn set_cwd(&self, cwd: String) {
+        *self.cwd.lock() = cwd;
+    }
+
+    pub fn root(&self) -> String {
+        self.root.lock().clone()
+    }
+
+    pub fn set_root(&self, root: String) {
+        *self.root.lock() = root;
+    }
+
+    /// Returns the current directory as seen by the process, i.e. relative
+    /// to its root directory, as `getcwd` does.
+    pub fn cwd_in_root(&self) -> String {
+        let cwd = self.cwd();
+        let root = self.root();
+        if root == "/" {
+            cwd
+        } else if cwd == root {
+            String::from("/")
+        } else if link::is_below(&cwd, &root) {
+            String::from(&cwd[root.len()..])
+        } else {
+            // As on Linux, for a current directory out of the root directory.
+            alloc::format!("(unreachable){cwd}")
+        }
+    }
+}
+
+/// Resolves the path `path` of a directory, e.g. for `chdir`.
+pub fn resolve_dir(path: &str) -> LinuxResult<String> {
+    let path = resolve_path(AT_FDCWD, path, true)?;
+    if stat(&path)?.mode & S_IFMT != S_IFDIR {
+        return Err(LinuxError::ENOTDIR);
+    }
+    Ok(path)
+}
+
+/// Returns the filesystem context of the current process.
+pub fn fs_context() -> Arc<FsContext> {
+    current().task_ext().process.fs_context()
+}
+
+/// Returns the current directory of the current process.
+pub fn current_dir() -> String {
+    fs_context().cwd()
+}
+
+/// Resolves `path` to a normalized absolute path. A relative path is relative
+/// to the directory `dirfd`, or to the current directory if it is
+/// [`AT_FDCWD`].
+///
+/// The symbolic links in the path are followed, except for its last
+/// component if `follow` is false and the path has no trailing slash.
+pub fn resolve_path(dirfd: i32, path: &str, follow: bool) -> LinuxResult<String> {
+    if path.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    let root = fs_context().root();
+    let base = if path.starts_with('/') {
+        root.clone()
+    } else if dirfd == AT_FDCWD {
+        current_dir()
+    } else {
+        let dir = get_file(dirfd)?;
+        if dir.stat()?.mode & S_IFMT != S_IFDIR {
+            return Err(LinuxError::ENOTDIR);
+        }
+        // Every directory is in the filesystem.
+        String::from(dir.path().ok_or(LinuxError::ENOTDIR)?)
+    };
+    let mut links = 0;
+    walk(&root, base, path, follow || path.ends_with('/'), &mut links)
+}
+
+/// Resolves `path` relative to the resolved directory `resolved`, below the
+/// root directory `root`, counting the symbolic links followed in `links`.
+fn walk(
+    root: &str,
+    mut resolved: String,
+    path: &str,
+    follow: bool,
+    links: &mut usize,
+) -> LinuxResult<String> {
+    let components: Vec<&str> = path
+        .split('/')
+        .filter(|&component| !component.is_empty() && component != ".")
+        .collect();
+    for (i, &component) in components.iter().enumerate() {
+        if component == ".." {
+            // `..` of the root directory is itself.
+            if resolved != root {
+                resolved.truncate(parent(&resolved).len());
+            }
+            continue;
+        }
+        if resolved != "/" {
+            resolved.push('/');
+        }
+        resolved.push_str(component);
+        if i + 1 == components.len() && !follow {
+            break;
+        }
+        if let Some(target) = link::symlink_target(&resolved) {
+            *links += 1;
+            if *links > MAXSYMLINKS {
+                return Err(LinuxError::ELOOP);
+            }
+            let base = if target.starts_with('/') {
+                String::from(root)
+            } else {
+                String::from(parent(&resolved))
+            };
+            resolved = walk(root, base, &target, true, links)?;
+        }
+    }
+    Ok(resolved)
+}
+
+/// Converts the attributes of the file at the canonical path `path`, with
+/// the inode number `ino`, from `axfs`.
+fn to_kstat(path: &str, ino: u64, attr: &FileAttr) -> Kstat {
+    Kstat {
+        dev: ROOT_DEV,
+        ino,
+        mode: ((attr.file_type() as u32) << 12) | attr.perm().bits() as u32,
+        nlink: 1 + link::aliases(path).len() as u32,
+        rdev: 0,
+        size: attr.size(),
+        blksize: 512,
+        blocks: attr.blocks(),
+    }
+}
+
+/// Returns the status of the file at the absolute path `path`, which is the
+/// symbolic link itself if there is one at `path`.
+pub fn stat(path: &str) -> LinuxResult<Kstat> {
+    if let Some(node) = Node::lookup(path) {
+        return Ok(node.stat());
+    }
+    if let Some(Link::Symbolic { target, ino }) = link::get(path) {
+        return Ok(Kstat {
+            dev: ROOT_DEV,
+            ino,
+            mode: S_IFLNK | 0o777,
+            nlink: 1,
+            size: target.len() as u64,
+            blksize: 512,
+            ..Default::default()
+        });
+    }
+    let path = link::canonical(path);
+    let metadata = axfs::api::metadata(&path)?;
+    Ok(to_kstat(&path, inode::get(&path), metadata.raw_metadata()))
+}
+
+/// Returns whether there is a file, directory or link at `path`.
+fn exists(path: &str) -> bool {
+    Node::lookup(path).is_some() || link::get(path).is_some() || axfs::api::metadata(path).is_ok()
+}
+
+/// Returns whether there is a directory at `path`.
+fn is_dir(path: &str) -> bool {
+    match Node::lookup(path) {
+        Some(node) => matches!(node, Node::Root),
+        None => link::get(path).is_none() && axfs::api::metadata(path).is_ok_and(|m| m.is_dir()),
+    }
+}
+
+/// Checks that a new entry can be created at `path`.
+fn check_new(path: &str) -> LinuxResult {
+    if exists(path) {
+        return Err(LinuxError::EEXIST);
+    }
+    if stat(parent(path))?.mode & S_IFMT != S_IFDIR {
+        return Err(LinuxError::ENOTDIR);
+    }
+    Ok(())
+}
+
+/// Opens the file at the absolute path `path`.
+///
+/// New files get the default permissions of `axfs`, which does not store the
+/// mode given by user space.
+pub fn open(path: &str, flags: OpenFlags) -> LinuxResult<Arc<dyn FileLike>> {
+    // The path is only a symbolic link if it was not followed.
+    if link::symlink_target(path).is_some() && !flags.contains(OpenFlags::O_PATH) {
+        return Err(LinuxError::ELOOP);
+    }
+    if flags.contains(OpenFlags::O_PATH) {
+        stat(path)?;
+        return Ok(Arc::new(PathFile::new(path, flags)));
+    }
+    if let Some(node) = Node::lookup(path) {
+        if flags.contains(OpenFlags::O_CREAT | OpenFlags::O_EXCL) {
+            return Err(LinuxError::EEXIST);
+        }
+        if flags.contains(OpenFlags::O_DIRECTORY) && !matches!(node, Node::Root) {
+            return Err(LinuxError::ENOTDIR);
+        }
+        return node.open(flags);
+    }
+    let path = &link::canonical(path);
+    let creating = flags.contains(OpenFlags::O_CREAT);
+    match axfs::api::metadata(path) {
+        Ok(_) if creating && flags.contains(OpenFlags::O_EXCL) => {
+            return Err(LinuxError::EEXIST);
+        }
+        Ok(metadata) if metadata.is_dir() => {
+            if flags.writable() || creating {
+                return Err(LinuxError::EISDIR);
+            }
+            return Ok(Arc::new(Directory::new(path, flags)));
+        }
+        Ok(_) if flags.contains(OpenFlags::O_DIRECTORY) => {
+            return Err(LinuxError::ENOTDIR);
+        }
+        Ok(_) => {}
+        Err(_) if creating && !flags.contains(OpenFlags::O_DIRECTORY) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let truncating = flags.contains(OpenFlags::O_TRUNC);
+    let mut options = OpenOptions::new();
+    options.read(true);
+    // `axfs` only creates or truncates files opened for writing.
+    options.write(flags.writable() || creating || truncating);
+    options.create(creating);
+    options.truncate(truncating);
+    let inner = fops::File::open(path, &options)?;
+    Ok(Arc::new(File::new(path, inner, flags)))
+}
+
+/// An entry of a directory.
+pub struct DirEntry {
+    pub name: String,
+    pub ino: u64,
+    /// The type of the file, as in `d_type`.
+    pub file_type: u8,
+}
+
+/// Lists the entries of the directory at `path`, without `.` and `..`.
+pub fn read_dir(path: &str) -> LinuxResult<Vec<DirEntry>> {
+    if matches!(Node::lookup(path), Some(Node::Root)) {
+        return Ok(devpts::entries(path));
+    }
+    let mut options = OpenOptions::new();
+    options.read(true);
+    let mut dir = fops::Directory::open_dir(path, &options)?;
+    let mut entries = Vec::new();
+    let mut buf: [fops::DirEntry; 16] = core::array::from_fn(|_| fops::DirEntry::default());
+    loop {
+        let len = dir.read_dir(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        for entry in &buf[..len] {
+            let name = String::from_utf8_lossy(entry.name_as_bytes());
+            if name == "." || name == ".." {
+                continue;
+            }
+            let child = alloc::format!("{}/{name}", path.trim_end_matches('/'));
+            entries.push(DirEntry {
+                ino: inode::get(&child),
+                name: name.into_owned(),
+                file_type: entry.entry_type() as u8,
+            });
+        }
+    }
+    for (name, link) in link::children(path) {
+        let (ino, file_type) = match link {
+            Link::Symbolic { ino, .. } => (ino, DT_LNK),
+            Link::Hard(canonical) => (inode::get(&canonical), DT_REG),
+        };
+        entries.push(DirEntry {
+            name,
+            ino,
+            file_type,
+        });
+    }
+    entries.extend(devpts::entries(path));
+    Ok(entries)
+}
+
+/// Creates a directory at `path`.
+pub fn mkdir(path: &str) -> LinuxResult {
+    check_new(path)?;
+    Ok(axfs::api::create_dir(path)?)
+}
+
+/// Removes the file or link at `path`, or the empty directory at `path` if
+/// `dir` is true.
+pub fn unlink(path: &str, dir: bool) -> LinuxResult {
+    if path == "/" {
+        return Err(LinuxError::EBUSY);
+    }
+    if link::get(path).is_some() {
+        if dir {
+            return Err(LinuxError::ENOTDIR);
+        }
+        link::remove(path);
+        return Ok(());
+    }
+    let is_dir = stat(path)?.mode & S_IFMT == S_IFDIR;
+    match (dir, is_dir) {
+        (true, false) => Err(LinuxError::ENOTDIR),
+        (false, true) => Err(LinuxError::EISDIR),
+        (true, true) => {
+            if !read_dir(path)?.is_empty() {
+                return Err(LinuxError::ENOTEMPTY);
+            }
+            axfs::api::remove_dir(path)?;
+            inode::remove(path);
+            Ok(())
+        }
+        (false, false) => {
+            // The file lives on at one of its hard links.
+            if let Some(alias) = link::aliases(path).first() {
+                link::remove(alias);
+                axfs::api::rename(path, alias)?;
+                link::moved(path, alias);
+                cache::moved(path, alias);
+                inode::moved(path, alias);
+                return Ok(());
+            }
+            axfs::api::remove_file(path)?;
+            cache::remove(path);
+            inode::remove(path);
+            Ok(())
+        }
+    }
+}
+
+/// Moves the file, directory or link at `from` to `to`, where there is
+/// nothing.
+fn move_entry(from: &str, to: &str) -> LinuxResult {
+    if let Some(link) = link::remove(from) {
+        link::insert(to, link);
+        return Ok(());
+    }
+    axfs::api::rename(from, to)?;
+    link::moved(from, to);
+    cache::moved(from, to);
+    inode::moved(from, to);
+    Ok(())
+}
+
+/// Renames the file, directory or link at `old` to `new`, replacing what is
+/// at `new` unless `noreplace` is true.
+pub fn rename(old: &str, new: &str, noreplace: bool) -> LinuxResult {
+    if !exists(old) {
+        return Err(LinuxError::ENOENT);
+    }
+    if old == "/" || new == "/" {
+        return Err(LinuxError::EBUSY);
+    }
+    // A directory cannot be moved below itself.
+    if link::is_below(new, old) {
+        return Err(LinuxError::EINVAL);
+    }
+    if exists(new) {
+        if noreplace {
+            return Err(LinuxError::EEXIST);
+        }
+        // Nothing is done for two links to the same file.
+        if link::canonical(old) == link::canonical(new) {
+            return Ok(());
+        }
+        match (is_dir(old), is_dir(new)) {
+            (true, false) => return Err(LinuxError::ENOTDIR),
+            (false, true) => return Err(LinuxError::EISDIR),
+            (_, new_is_dir) => unlink(new, new_is_dir)?,
+        }
+    } else {
+        check_new(new)?;
+    }
+    move_entry(old, new)
+}
+
+/// Exchanges the links at `old` and `new`.
+///
+/// `axfs` cannot exchange two entries atomically, so like FAT on Linux,
+/// this fails with `EINVAL` unless both are links, which are exchanged in
+/// memory.
+pub fn exchange(old: &str, new: &str) -> LinuxResult {
+    if !exists(old) || !exists(new) {
+        return Err(LinuxError::ENOENT);
+    }
+    if old == new {
+        return Ok(());
+    }
+    if link::exchange(old, new) {
+        Ok(())
+    } else {
+        Err(LinuxError::EINVAL)
+    }
+}
+
+/// Creates a hard link at `new` to the file or symbolic link at `old`.
+pub fn link(old: &str, new: &str) -> LinuxResult {
+    let target = match link::get(old) {
+        // The new link shares the inode number of the symbolic link.
+        Some(link @ Link::Symbolic { .. }) => link,
+        _ => {
+            if stat(old)?.mode & S_IFMT == S_IFDIR {
+                return Err(LinuxError::EPERM);
+            }
+            Link::Hard(link::canonical(old))
+        }
+    };
+    check_new(new)?;
+    link::insert(new, target);
+    Ok(())
+}
+
+/// Creates a symbolic link at `path` to `target`.
+pub fn symlink(target: &str, path: &str) -> LinuxResult {
+    if target.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    check_new(path)?;
+    link::insert(
+        path,
+        Link::Symbolic {
+            target: String::from(target),
+            ino: inode::alloc(),
+        },
+    );
+    Ok(())
+}
+
+/// Returns the target of the symbolic link at `path`.
+pub fn readlink(path: &str) -> LinuxResult<String> {
+    match link::symlink_target(path) {
+        Some(target) => Ok(target),
+        None if exists(path) => Err(LinuxError::EINVAL),
+        None => Err(LinuxError::ENOENT),
+    }
+}
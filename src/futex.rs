@@ -0,0 +1,680 @@
+//! Fast user-space mutexes.
+//!
+//! A futex is a 32-bit word in user memory. Threads block on it until
+//! another thread wakes them up by the same futex, which is identified by a
+//! [`FutexKey`]: private futexes are only shared by the threads of a process,
+//! while other futexes in shared memory are identified by their physical
+//! addresses, so that they also work across processes.
+//!
+//! Priority-inheritance (PI) futexes are locks whose words hold the thread ID
+//! of the owner. The owner inherits the highest priority of the threads
+//! blocked on all the PI futexes it owns, which is recomputed whenever a
+//! waiter comes or leaves, and hands the lock over to the waiter with the
+//! highest priority when it unlocks it.
+
+use alloc::{
+    collections::{btree_map::BTreeMap, VecDeque},
+    sync::Arc,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axsync::Mutex;
+use axtask::{current, TaskExtRef, WaitQueue};
+use memory_addr::VirtAddr;
+
+use crate::{
+    task::{self, wait_interruptible_timeout},
+    uaccess::{check_region, get_user, with_user_atomic},
+};
+
+/// The bits of a PI or robust futex word holding the thread ID of the owner.
+const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+/// Set in a PI or robust futex word if some threads are blocked on it.
+const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// Set in a PI or robust futex word if the owner died without unlocking it.
+const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+
+/// Identifies a futex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FutexKey {
+    /// A futex only used inside the address space.
+    Private { aspace: usize, vaddr: usize },
+    /// A futex in memory shared between address spaces.
+    Shared { paddr: usize },
+}
+
+impl FutexKey {
+    /// Returns the key of the futex at `uaddr` in the current address space.
+    ///
+    /// A futex not in shared memory is private even if `private` is not set.
+    fn new(uaddr: usize, private: bool) -> LinuxResult<Self> {
+        if uaddr & 3 != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        check_region(uaddr, 4, MappingFlags::READ)?;
+        let curr = current();
+        let aspace = curr.task_ext().process.aspace();
+        if !private {
+            if let Some(paddr) = aspace.lock().shared_paddr(VirtAddr::from(uaddr)) {
+                return Ok(Self::Shared {
+                    paddr: paddr.as_usize(),
+                });
+            }
+        }
+        Ok(Self::Private {
+            aspace: Arc::as_ptr(&aspace) as usize,
+            vaddr: uaddr,
+        })
+    }
+}
+
+/// A thread blocked on a futex.
+struct FutexWaiter {
+    /// The futex the thread is queued on, which may be changed by requeueing.
+    key: Mutex<FutexKey>,
+    /// The thread ID of the waiter.
+    tid: u32,
+    /// The priority of the waiter, to be inherited by the owner of a PI
+    /// futex.
+    priority: isize,
+    /// The thread is only woken up by wakers with some of these bits set.
+    bitset: u32,
+    /// Whether the thread has been woken up.
+    woken: AtomicBool,
+    /// The thread blocks here.
+    wq: WaitQueue,
+}
+
+impl FutexWaiter {
+    /// Creates a waiter for the current thread.
+    fn new(key: FutexKey, bitset: u32) -> Arc<Self> {
+        let curr = current();
+        Arc::new(Self {
+            key: Mutex::new(key),
+            tid: curr.id().as_u64() as u32,
+            priority: curr.task_ext().effective_priority(),
+            bitset,
+            woken: AtomicBool::new(false),
+            wq: WaitQueue::new(),
+        })
+    }
+
+    /// Blocks until the waiter is woken up, or fails as
+    /// [`wait_interruptible_timeout`] after removing it from the table.
+    fn block(self: &Arc<Self>, timeout: Option<Duration>) -> LinuxResult {
+        let res =
+            wait_interruptible_timeout(&self.wq, timeout, || self.woken.load(Ordering::Acquire));
+        if res.is_err() {
+            let mut table = FUTEX_TABLE.lock();
+            if self.woken.load(Ordering::Acquire) {
+                // Woken up right after giving up.
+                return Ok(());
+            }
+            let key = *self.key.lock();
+            if let Some(queue) = table.get_mut(&key) {
+                queue.retain(|w| !Arc::ptr_eq(w, self));
+                if queue.is_empty() {
+                    table.remove(&key);
+                }
+            }
+            // The owner of a PI futex no longer inherits the priority of the
+            // waiter.
+            let mut pi_owners = PI_OWNERS.lock();
+            if let Some(&owner) = pi_owners.get(&key) {
+                if !table.contains_key(&key) {
+                    pi_owners.remove(&key);
+                }
+                update_inherited_priority_locked(&table, &pi_owners, owner);
+            }
+        }
+        res
+    }
+
+    fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_all(false);
+    }
+}
+
+/// The waiters of all futexes, in the order they started waiting.
+static FUTEX_TABLE: Mutex<BTreeMap<FutexKey, VecDeque<Arc<FutexWaiter>>>> =
+    Mutex::new(BTreeMap::new());
+
+/// The owners of the PI futexes with waiters, by thread ID.
+///
+/// Only locked with [`FUTEX_TABLE`] locked.
+static PI_OWNERS: Mutex<BTreeMap<FutexKey, u32>> = Mutex::new(BTreeMap::new());
+
+/// Makes the thread `tid` inherit the highest priority of the waiters of the
+/// PI futexes it owns.
+fn update_inherited_priority_locked(
+    table: &BTreeMap<FutexKey, VecDeque<Arc<FutexWaiter>>>,
+    pi_owners: &BTreeMap<FutexKey, u32>,
+    tid: u32,
+) {
+    let Some(thread) = task::get_thread(tid as u64) else {
+        return;
+    };
+    let priority = pi_owners
+        .iter()
+        .filter(|&(_, &owner)| owner == tid)
+        .filter_map(|(key, _)| table.get(key))
+        .flatten()
+        .map(|waiter| waiter.priority)
+        .min()
+        .unwrap_or(isize::MAX);
+    thread.task_ext().set_inherited_priority(priority);
+}
+
+/// Wakes up at most `count` waiters of `key` whose bitsets intersect with
+/// `bitset`.
+fn wake_locked(
+    table: &mut BTreeMap<FutexKey, VecDeque<Arc<FutexWaiter>>>,
+    key: FutexKey,
+    count: usize,
+    bitset: u32,
+) -> usize {
+    let Some(queue) = table.get_mut(&key) else {
+        return 0;
+    };
+    let mut woken = 0;
+    queue.retain(|waiter| {
+        if woken < count && waiter.bitset & bitset != 0 {
+            waiter.wake();
+            woken += 1;
+            false
+        } else {
+            true
+        }
+    });
+    if queue.is_empty() {
+        table.remove(&key);
+    }
+    woken
+}
+
+/// Blocks on the futex at `uaddr` if it contains `val`, until it is woken up
+/// by a waker with some bits of `bitset`.
+///
+/// Fails with `EAGAIN` if the futex does not contain `val`, `ETIMEDOUT` if
+/// it is not woken up within `timeout`, or `EINTR` if the thread is killed.
+pub fn wait(
+    uaddr: usize,
+    private: bool,
+    val: u32,
+    timeout: Option<Duration>,
+    bitset: u32,
+) -> LinuxResult {
+    let key = FutexKey::new(uaddr, private)?;
+    let waiter = FutexWaiter::new(key, bitset);
+    {
+        // Check the value with the table locked, so that a wakeup after the
+        // value is changed cannot be missed.
+        let mut table = FUTEX_TABLE.lock();
+        if get_user(uaddr as *const u32)? != val {
+            return Err(LinuxError::EAGAIN);
+        }
+        table.entry(key).or_default().push_back(waiter.clone());
+    }
+
+    waiter.block(timeout)
+}
+
+/// Wakes up at most `count` waiters of the futex at `uaddr` whose bitsets
+/// intersect with `bitset`.
+///
+/// Returns the number of waiters woken up.
+pub fn wake(uaddr: usize, private: bool, count: usize, bitset: u32) -> LinuxResult<usize> {
+    let key = FutexKey::new(uaddr, private)?;
+    Ok(wake_locked(&mut FUTEX_TABLE.lock(), key, count, bitset))
+}
+
+/// Wakes up at most `count` waiters of the futex at `uaddr`, and moves at
+/// most `requeue_count` of the remaining waiters to the futex at `uaddr2`.
+///
+/// If `cmp` is not `None`, fails with `EAGAIN` unless the futex at `uaddr`
+/// contains it.
+///
+/// Returns the number of waiters woken up or moved.
+pub fn requeue(
+    uaddr: usize,
+    private: bool,
+    count: usize,
+    uaddr2: usize,
+    requeue_count: usize,
+    cmp: Option<u32>,
+) -> LinuxResult<usize> {
+    let key = FutexKey::new(uaddr, private)?;
+    let key2 = FutexKey::new(uaddr2, private)?;
+    let mut table = FUTEX_TABLE.lock();
+    if let Some(cmp) = cmp {
+        if get_user(uaddr as *const u32)? != cmp {
+            return Err(LinuxError::EAGAIN);
+        }
+    }
+    let woken = wake_locked(&mut table, key, count, u32::MAX);
+    if key == key2 {
+        return Ok(woken);
+    }
+    let Some(queue) = table.get_mut(&key) else {
+        return Ok(woken);
+    };
+    let moved: VecDeque<_> = queue.drain(..requeue_count.min(queue.len())).collect();
+    if queue.is_empty() {
+        table.remove(&key);
+    }
+    let requeued = moved.len();
+    for waiter in &moved {
+        *waiter.key.lock() = key2;
+    }
+    table.entry(key2).or_default().extend(moved);
+    Ok(woken + requeued)
+}
+
+/// The operations of `FUTEX_WAKE_OP` on the second futex.
+const FUTEX_OP_SET: u32 = 0;
+const FUTEX_OP_ADD: u32 = 1;
+const FUTEX_OP_OR: u32 = 2;
+const FUTEX_OP_ANDN: u32 = 3;
+const FUTEX_OP_XOR: u32 = 4;
+/// Use `1 << oparg` as the operand.
+const FUTEX_OP_OPARG_SHIFT: u32 = 8;
+
+/// The comparisons of `FUTEX_WAKE_OP` on the old value of the second futex.
+const FUTEX_OP_CMP_EQ: u32 = 0;
+const FUTEX_OP_CMP_NE: u32 = 1;
+const FUTEX_OP_CMP_LT: u32 = 2;
+const FUTEX_OP_CMP_LE: u32 = 3;
+const FUTEX_OP_CMP_GT: u32 = 4;
+const FUTEX_OP_CMP_GE: u32 = 5;
+
+/// Sign-extends the 12-bit field of `encoded` at `shift`.
+const fn sign_extend_12(encoded: u32, shift: u32) -> i32 {
+    ((encoded >> shift) as i32) << 20 >> 20
+}
+
+/// The operation and the comparison encoded in the argument of
+/// `FUTEX_WAKE_OP`.
+struct FutexOp {
+    apply: fn(u32, u32) -> u32,
+    oparg: u32,
+    compare: fn(i32, i32) -> bool,
+    cmparg: i32,
+}
+
+impl FutexOp {
+    /// Decodes `encoded_op`.
+    fn decode(encoded_op: u32) -> LinuxResult<Self> {
+        let op = (encoded_op >> 28) & 0xf;
+        let cmp = (encoded_op >> 24) & 0xf;
+        let mut oparg = sign_extend_12(encoded_op, 12) as u32;
+        let cmparg = sign_extend_12(encoded_op, 0);
+        if op & FUTEX_OP_OPARG_SHIFT != 0 {
+            if oparg > 31 {
+                return Err(LinuxError::EINVAL);
+            }
+            oparg = 1 << oparg;
+        }
+        let apply: fn(u32, u32) -> u32 = match op & !FUTEX_OP_OPARG_SHIFT {
+            FUTEX_OP_SET => |_, arg| arg,
+            FUTEX_OP_ADD => u32::wrapping_add,
+            FUTEX_OP_OR => |val, arg| val | arg,
+            FUTEX_OP_ANDN => |val, arg| val & !arg,
+            FUTEX_OP_XOR => |val, arg| val ^ arg,
+            _ => return Err(LinuxError::ENOSYS),
+        };
+        let compare: fn(i32, i32) -> bool = match cmp {
+            FUTEX_OP_CMP_EQ => |a, b| a == b,
+            FUTEX_OP_CMP_NE => |a, b| a != b,
+            FUTEX_OP_CMP_LT => |a, b| a < b,
+            FUTEX_OP_CMP_LE => |a, b| a <= b,
+            FUTEX_OP_CMP_GT => |a, b| a > b,
+            FUTEX_OP_CMP_GE => |a, b| a >= b,
+            _ => return Err(LinuxError::ENOSYS),
+        };
+        Ok(Self {
+            apply,
+            oparg,
+            compare,
+            cmparg,
+        })
+    }
+
+    /// Returns the new value of the futex holding `val`.
+    fn apply(&self, val: u32) -> u32 {
+        (self.apply)(val, self.oparg)
+    }
+
+    /// Whether the old value `old` of the futex passes the comparison.
+    fn compare(&self, old: u32) -> bool {
+        (self.compare)(old as i32, self.cmparg)
+    }
+}
+
+/// Atomically applies the operation encoded in `encoded_op` to the futex at
+/// `uaddr2`, then wakes up at most `count` waiters of the futex at `uaddr`,
+/// and at most `count2` waiters of the futex at `uaddr2` if the old value of
+/// it passes the comparison encoded in `encoded_op`.
+///
+/// Returns the number of waiters woken up.
+pub fn wake_op(
+    uaddr: usize,
+    private: bool,
+    count: usize,
+    uaddr2: usize,
+    count2: usize,
+    encoded_op: u32,
+) -> LinuxResult<usize> {
+    let op = FutexOp::decode(encoded_op)?;
+
+    let key = FutexKey::new(uaddr, private)?;
+    let key2 = FutexKey::new(uaddr2, private)?;
+    let mut table = FUTEX_TABLE.lock();
+    let old = with_user_atomic(uaddr2 as *const AtomicU32, |futex| {
+        Ok(futex
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |val| {
+                Some(op.apply(val))
+            })
+            .unwrap())
+    })?;
+
+    let mut woken = wake_locked(&mut table, key, count, u32::MAX);
+    if op.compare(old) {
+        woken += wake_locked(&mut table, key2, count2, u32::MAX);
+    }
+    Ok(woken)
+}
+
+/// Hands the PI futex `key` owned by the current thread over to the waiter
+/// with the highest priority (the first one among equals), or unlocks it if
+/// there is no waiter.
+///
+/// The inherited priorities of the current thread and the new owner are
+/// updated. `flags` are kept in the futex word.
+fn hand_over_locked(
+    table: &mut BTreeMap<FutexKey, VecDeque<Arc<FutexWaiter>>>,
+    key: FutexKey,
+    futex: &AtomicU32,
+    flags: u32,
+) {
+    let next = table.get_mut(&key).and_then(|queue| {
+        let (index, _) = queue
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, waiter)| waiter.priority)?;
+        queue.remove(index)
+    });
+    if table.get(&key).is_some_and(|queue| queue.is_empty()) {
+        table.remove(&key);
+    }
+    let mut pi_owners = PI_OWNERS.lock();
+    pi_owners.remove(&key);
+    match next {
+        Some(waiter) => {
+            let waiters = if table.contains_key(&key) {
+                pi_owners.insert(key, waiter.tid);
+                FUTEX_WAITERS
+            } else {
+                0
+            };
+            futex.store(waiter.tid | flags | waiters, Ordering::Release);
+            update_inherited_priority_locked(table, &pi_owners, waiter.tid);
+            waiter.wake();
+        }
+        None => futex.store(flags, Ordering::Release),
+    }
+    let tid = current().id().as_u64() as u32;
+    update_inherited_priority_locked(table, &pi_owners, tid);
+}
+
+/// The outcome of an attempt to lock a PI futex.
+enum LockAttempt {
+    /// The current thread owns the lock.
+    Locked,
+    /// The futex word changed concurrently, the attempt is repeated.
+    Retry,
+    /// The current thread is queued as a waiter of the lock.
+    Queued,
+}
+
+/// Locks the PI futex at `uaddr`.
+///
+/// If it is locked by another thread, the current thread blocks until the
+/// lock is handed over to it, unless `trylock` is set, in which case it
+/// fails with `EAGAIN`. The owner inherits the priority of the current thread
+/// while it waits.
+///
+/// Fails with `EDEADLK` if the current thread already owns the lock, `ESRCH`
+/// if the owner does not exist, or as [`wait`] if the thread does not get the
+/// lock within `timeout` or is killed.
+pub fn lock_pi(
+    uaddr: usize,
+    private: bool,
+    timeout: Option<Duration>,
+    trylock: bool,
+) -> LinuxResult {
+    let key = FutexKey::new(uaddr, private)?;
+    let waiter = FutexWaiter::new(key, u32::MAX);
+    loop {
+        let mut table = FUTEX_TABLE.lock();
+        let attempt = with_user_atomic(uaddr as *const AtomicU32, |futex| {
+            let uval = futex.load(Ordering::Acquire);
+            let owner_tid = uval & FUTEX_TID_MASK;
+            if owner_tid == 0 {
+                // Not locked, but there may be waiters left by a dead owner.
+                let waiters = if table.contains_key(&key) {
+                    FUTEX_WAITERS
+                } else {
+                    0
+                };
+                let new = waiter.tid | (uval & FUTEX_OWNER_DIED) | waiters;
+                if futex
+                    .compare_exchange(uval, new, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    return Ok(LockAttempt::Retry);
+                }
+                if waiters != 0 {
+                    let mut pi_owners = PI_OWNERS.lock();
+                    pi_owners.insert(key, waiter.tid);
+                    update_inherited_priority_locked(&table, &pi_owners, waiter.tid);
+                }
+                return Ok(LockAttempt::Locked);
+            }
+            if owner_tid == waiter.tid {
+                return Err(LinuxError::EDEADLK);
+            }
+            if trylock {
+                return Err(LinuxError::EAGAIN);
+            }
+            if task::get_thread(owner_tid as u64).is_none() {
+                return Err(LinuxError::ESRCH);
+            }
+            // Make the owner unlock the futex in the kernel.
+            if uval & FUTEX_WAITERS == 0
+                && futex
+                    .compare_exchange(
+                        uval,
+                        uval | FUTEX_WAITERS,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_err()
+            {
+                return Ok(LockAttempt::Retry);
+            }
+            table.entry(key).or_default().push_back(waiter.clone());
+            let mut pi_owners = PI_OWNERS.lock();
+            pi_owners.insert(key, owner_tid);
+            update_inherited_priority_locked(&table, &pi_owners, owner_tid);
+            Ok(LockAttempt::Queued)
+        })?;
+        match attempt {
+            LockAttempt::Locked => return Ok(()),
+            LockAttempt::Retry => continue,
+            LockAttempt::Queued => break,
+        }
+    }
+    waiter.block(timeout)
+}
+
+/// Unlocks the PI futex at `uaddr` owned by the current thread, handing it
+/// over to the waiter with the highest priority.
+///
+/// The current thread keeps inheriting the priorities of the waiters of the
+/// other PI futexes it owns.
+pub fn unlock_pi(uaddr: usize, private: bool) -> LinuxResult {
+    let key = FutexKey::new(uaddr, private)?;
+    let mut table = FUTEX_TABLE.lock();
+    with_user_atomic(uaddr as *const AtomicU32, |futex| {
+        if futex.load(Ordering::Acquire) & FUTEX_TID_MASK != current().id().as_u64() as u32 {
+            return Err(LinuxError::EPERM);
+        }
+        hand_over_locked(&mut table, key, futex, 0);
+        Ok(())
+    })
+}
+
+/// The head of the robust futex list of a thread in user space.
+///
+/// Each entry of the list is embedded in a lock, whose futex word is at
+/// `futex_offset` from the entry. The lowest bit of an entry pointer is set
+/// for PI futexes.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RobustListHead {
+    next: usize,
+    futex_offset: isize,
+    list_op_pending: usize,
+}
+
+/// The maximum number of robust futexes walked, to avoid looping forever on
+/// corrupted lists.
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+/// Releases the robust futex at `uaddr` if it is owned by the exiting current
+/// thread: its word is marked `FUTEX_OWNER_DIED`, and a waiter is woken up,
+/// or a PI futex is handed over to a waiter.
+fn handle_futex_death(uaddr: usize, pi: bool) {
+    let Ok(key) = FutexKey::new(uaddr, false) else {
+        return;
+    };
+    let tid = current().id().as_u64() as u32;
+    let mut table = FUTEX_TABLE.lock();
+    // The futex is left alone if it is not accessible.
+    let _ = with_user_atomic(uaddr as *const AtomicU32, |futex| {
+        let uval = futex.load(Ordering::Acquire);
+        if uval & FUTEX_TID_MASK != tid {
+            return Ok(());
+        }
+        if pi && uval & FUTEX_WAITERS != 0 {
+            hand_over_locked(&mut table, key, futex, FUTEX_OWNER_DIED);
+            return Ok(());
+        }
+        let res = futex.fetch_update(Ordering::AcqRel, Ordering::Acquire, |val| {
+            (val & FUTEX_TID_MASK == tid).then_some((val & FUTEX_WAITERS) | FUTEX_OWNER_DIED)
+        });
+        if res.is_ok_and(|old| old & FUTEX_WAITERS != 0) {
+            wake_locked(&mut table, key, 1, u32::MAX);
+        }
+        Ok(())
+    });
+}
+
+/// Releases the robust futexes in the list at `head` held by the exiting
+/// current thread.
+pub fn exit_robust_list(head: *const RobustListHead) {
+    let Ok(list) = get_user(head) else {
+        return;
+    };
+    let futex_addr = |entry: usize| (entry & !1).wrapping_add_signed(list.futex_offset);
+    let mut entry = list.next;
+    let mut count = 0;
+    while entry != head as usize && count < ROBUST_LIST_LIMIT {
+        // Read the next entry first, as the lock may be freed by a waiter.
+        let next = get_user((entry & !1) as *const usize);
+        // The pending entry is handled below.
+        if entry != list.list_op_pending {
+            handle_futex_death(futex_addr(entry), entry & 1 != 0);
+        }
+        let Ok(next) = next else {
+            return;
+        };
+        entry = next;
+        count += 1;
+    }
+    if list.list_op_pending != 0 {
+        let pending = list.list_op_pending;
+        handle_futex_death(futex_addr(pending), pending & 1 != 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `FUTEX_OP(op, oparg, cmp, cmparg)`
+    const fn futex_op(op: u32, oparg: i32, cmp: u32, cmparg: i32) -> u32 {
+        (op & 0xf) << 28 | (cmp & 0xf) << 24 | (oparg as u32 & 0xfff) << 12 | cmparg as u32 & 0xfff
+    }
+
+    #[test]
+    fn wake_op_operations() {
+        let op = FutexOp::decode(futex_op(FUTEX_OP_SET, 7, FUTEX_OP_CMP_EQ, 0)).unwrap();
+        assert_eq!(op.apply(3), 7);
+        let op = FutexOp::decode(futex_op(FUTEX_OP_ADD, 1, FUTEX_OP_CMP_EQ, 0)).unwrap();
+        assert_eq!(op.apply(u32::MAX), 0);
+        let op = FutexOp::decode(futex_op(FUTEX_OP_ADD, -1, FUTEX_OP_CMP_EQ, 0)).unwrap();
+        assert_eq!(op.apply(5), 4);
+        let op = FutexOp::decode(futex_op(FUTEX_OP_OR, 0b100, FUTEX_OP_CMP_EQ, 0)).unwrap();
+        assert_eq!(op.apply(0b011), 0b111);
+        let op = FutexOp::decode(futex_op(FUTEX_OP_ANDN, 0b110, FUTEX_OP_CMP_EQ, 0)).unwrap();
+        assert_eq!(op.apply(0b111), 0b001);
+        let op = FutexOp::decode(futex_op(FUTEX_OP_XOR, 0b101, FUTEX_OP_CMP_EQ, 0)).unwrap();
+        assert_eq!(op.apply(0b110), 0b011);
+        let op = FutexOp::decode(futex_op(
+            FUTEX_OP_OR | FUTEX_OP_OPARG_SHIFT,
+            31,
+            FUTEX_OP_CMP_EQ,
+            0,
+        ))
+        .unwrap();
+        assert_eq!(op.apply(1), 0x8000_0001);
+    }
+
+    #[test]
+    fn wake_op_comparisons() {
+        let cases = [
+            (FUTEX_OP_CMP_EQ, [false, true, false]),
+            (FUTEX_OP_CMP_NE, [true, false, true]),
+            (FUTEX_OP_CMP_LT, [true, false, false]),
+            (FUTEX_OP_CMP_LE, [true, true, false]),
+            (FUTEX_OP_CMP_GT, [false, false, true]),
+            (FUTEX_OP_CMP_GE, [false, true, true]),
+        ];
+        for (cmp, expected) in cases {
+            let op = FutexOp::decode(futex_op(FUTEX_OP_SET, 0, cmp, -1)).unwrap();
+            // The old value is compared as a signed integer.
+            let olds = [-2i32 as u32, -1i32 as u32, 0];
+            assert_eq!(olds.map(|old| op.compare(old)), expected);
+        }
+    }
+
+    #[test]
+    fn wake_op_invalid() {
+        let shift_too_large = futex_op(FUTEX_OP_SET | FUTEX_OP_OPARG_SHIFT, 32, FUTEX_OP_CMP_EQ, 0);
+        assert_eq!(
+            FutexOp::decode(shift_too_large).err(),
+            Some(LinuxError::EINVAL)
+        );
+        let unknown_op = futex_op(5, 0, FUTEX_OP_CMP_EQ, 0);
+        assert_eq!(FutexOp::decode(unknown_op).err(), Some(LinuxError::ENOSYS));
+        let unknown_cmp = futex_op(FUTEX_OP_SET, 0, 6, 0);
+        assert_eq!(FutexOp::decode(unknown_cmp).err(), Some(LinuxError::ENOSYS));
+    }
+}
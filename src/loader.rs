@@ -2,10 +2,13 @@
 //!
 //! It will read and parse ELF files.
 //!
-//! Now these apps are loaded into memory as a part of the kernel image.
-use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+//! Now these apps are loaded into memory as a part of the kernel image, and
+//! installed in the root directory so that `execve` can find them.
+use alloc::{collections::btree_map::BTreeMap, format, vec::Vec};
 use core::arch::global_asm;
 
+use axerrno::{ax_err, AxError, AxResult};
+use axfs::fops::{File, OpenOptions};
 use axhal::paging::MappingFlags;
 use memory_addr::{MemoryAddr, VirtAddr};
 
@@ -65,8 +68,26 @@ pub(crate) fn list_apps() {
     info!("**************/");
 }
 
+/// Installs the apps as files in the root directory, replacing the files
+/// with the same names, so that they can be run by `execve`.
+pub(crate) fn install_apps() {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    options.create(true);
+    options.truncate(true);
+    for i in 0..get_app_count() {
+        let path = format!("/{}", get_app_name(i));
+        let data = get_app_data(i);
+        match File::open(&path, &options).and_then(|file| file.write_at(0, data)) {
+            Ok(len) if len == data.len() => {}
+            Ok(_) => warn!("Failed to install app {}: short write", path),
+            Err(e) => warn!("Failed to install app {}: {:?}", path, e),
+        }
+    }
+}
+
 /// The segment of the elf file, which is used to map the elf file to the memory space
-pub struct ELFSegment {
+pub struct ELFSegment<'a> {
     /// The start virtual address of the segment
     pub start_vaddr: VirtAddr,
     /// The size of the segment
@@ -74,41 +95,44 @@ pub struct ELFSegment {
     /// The flags of the segment which is used to set the page table entry
     pub flags: MappingFlags,
     /// The data of the segment
-    pub data: &'static [u8],
+    pub data: &'a [u8],
     /// The offset of the segment relative to the start of the page
     pub offset: usize,
 }
 
 /// The information of a given ELF file
-pub struct ELFInfo {
+pub struct ELFInfo<'a> {
     /// The entry point of the ELF file
     pub entry: VirtAddr,
     /// The segments of the ELF file
-    pub segments: Vec<ELFSegment>,
+    pub segments: Vec<ELFSegment<'a>>,
     /// The auxiliary vectors of the ELF file
     pub auxv: BTreeMap<u8, usize>,
 }
 
-/// Load the ELF files by the given app name and return
+/// Load the ELF file with the given content and return
 /// the segments of the ELF file
 ///
 /// # Arguments
-/// * `name` - The name of the app
+/// * `data` - The content of the ELF file
 /// * `base_addr` - The minimal address of user space
 ///
 /// # Returns
-/// Entry and information about segments of the given ELF file
-pub(crate) fn load_elf(name: &str, base_addr: VirtAddr) -> ELFInfo {
+/// Entry and information about segments of the given ELF file, which refer
+/// to `data`
+///
+/// # Errors
+/// - [`AxError::InvalidData`] if `data` is not a valid ELF file for the current architecture
+pub(crate) fn load_elf(data: &[u8], base_addr: VirtAddr) -> AxResult<ELFInfo<'_>> {
     use xmas_elf::program::{Flags, SegmentData};
     use xmas_elf::{header, ElfFile};
 
-    let elf = ElfFile::new(
-        get_app_data_by_name(name).unwrap_or_else(|| panic!("failed to get app: {}", name)),
-    )
-    .expect("invalid ELF file");
+    let elf = ElfFile::new(data).map_err(|_| AxError::InvalidData)?;
     let elf_header = elf.header;
 
-    assert_eq!(elf_header.pt1.magic, *b"\x7fELF", "invalid elf!");
+    if elf_header.pt1.magic != *b"\x7fELF" {
+        return ax_err!(InvalidData, "invalid elf!");
+    }
 
     let expect_arch = if cfg!(target_arch = "x86_64") {
         header::Machine::X86_64
@@ -119,11 +143,9 @@ pub(crate) fn load_elf(name: &str, base_addr: VirtAddr) -> ELFInfo {
     } else {
         panic!("Unsupported architecture!");
     };
-    assert_eq!(
-        elf.header.pt2.machine().as_machine(),
-        expect_arch,
-        "invalid ELF arch"
-    );
+    if elf.header.pt2.machine().as_machine() != expect_arch {
+        return ax_err!(InvalidData, "invalid ELF arch");
+    }
 
     fn into_mapflag(f: Flags) -> MappingFlags {
         let mut ret = MappingFlags::USER;
@@ -141,36 +163,36 @@ pub(crate) fn load_elf(name: &str, base_addr: VirtAddr) -> ELFInfo {
 
     let mut segments = Vec::new();
 
-    let elf_offset = kernel_elf_parser::get_elf_base_addr(&elf, base_addr.as_usize()).unwrap();
-    assert!(
-        memory_addr::is_aligned_4k(elf_offset),
-        "ELF base address must be aligned to 4k"
-    );
+    let elf_offset = kernel_elf_parser::get_elf_base_addr(&elf, base_addr.as_usize())
+        .map_err(|_| AxError::InvalidData)?;
+    if !memory_addr::is_aligned_4k(elf_offset) {
+        return ax_err!(InvalidData, "ELF base address must be aligned to 4k");
+    }
 
-    elf.program_iter()
+    for ph in elf
+        .program_iter()
         .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
-        .for_each(|ph| {
-            // align the segment to 4k
-            let st_vaddr = VirtAddr::from(ph.virtual_addr() as usize) + elf_offset;
-            let st_vaddr_align: VirtAddr = st_vaddr.align_down_4k();
-            let ed_vaddr_align = VirtAddr::from((ph.virtual_addr() + ph.mem_size()) as usize)
-                .align_up_4k()
-                + elf_offset;
-            let data = match ph.get_data(&elf).unwrap() {
-                SegmentData::Undefined(data) => data,
-                _ => panic!("failed to get ELF segment data"),
-            };
-            segments.push(ELFSegment {
-                start_vaddr: st_vaddr_align,
-                size: ed_vaddr_align.as_usize() - st_vaddr_align.as_usize(),
-                flags: into_mapflag(ph.flags()),
-                data,
-                offset: st_vaddr.align_offset_4k(),
-            });
+    {
+        // align the segment to 4k
+        let st_vaddr = VirtAddr::from(ph.virtual_addr() as usize) + elf_offset;
+        let st_vaddr_align: VirtAddr = st_vaddr.align_down_4k();
+        let ed_vaddr_align =
+            VirtAddr::from((ph.virtual_addr() + ph.mem_size()) as usize).align_up_4k() + elf_offset;
+        let data = match ph.get_data(&elf) {
+            Ok(SegmentData::Undefined(data)) => data,
+            _ => return ax_err!(InvalidData, "failed to get ELF segment data"),
+        };
+        segments.push(ELFSegment {
+            start_vaddr: st_vaddr_align,
+            size: ed_vaddr_align.as_usize() - st_vaddr_align.as_usize(),
+            flags: into_mapflag(ph.flags()),
+            data,
+            offset: st_vaddr.align_offset_4k(),
         });
-    ELFInfo {
+    }
+    Ok(ELFInfo {
         entry: VirtAddr::from(elf.header.pt2.entry_point() as usize + elf_offset),
         segments,
         auxv: kernel_elf_parser::get_auxv_vector(&elf, elf_offset),
-    }
+    })
 }
@@ -1,5 +1,5 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 #![doc = include_str!("../README.md")]
 
 #[macro_use]
@@ -11,19 +11,27 @@ extern crate axstd;
 mod config {
     include!(concat!(env!("OUT_DIR"), "/uspace_config.rs"));
 }
+mod file;
+mod fs;
+mod futex;
 mod loader;
 mod mm;
+mod resource;
+mod signal;
 mod syscall_imp;
 mod task;
+mod tty;
+mod uaccess;
 
 use alloc::sync::Arc;
 
 use axhal::arch::UspaceContext;
 use axsync::Mutex;
 
-#[no_mangle]
+#[cfg_attr(not(test), no_mangle)]
 fn main() {
     loader::list_apps();
+    loader::install_apps();
     let testcases = option_env!("AX_TESTCASES_LIST")
         .unwrap_or_else(|| "Please specify the testcases list by making user_apps")
         .split(',')
@@ -31,11 +39,11 @@ fn main() {
     for testcase in testcases {
         info!("Running testcase: {}", testcase);
         let (entry_vaddr, ustack_top, uspace) = mm::load_user_app(testcase).unwrap();
-        let user_task = task::spawn_user_task(
+        let process = task::spawn_user_task(
             Arc::new(Mutex::new(uspace)),
             UspaceContext::new(entry_vaddr.into(), ustack_top, 2333),
         );
-        let exit_code = user_task.join();
-        info!("User task {} exited with code: {:?}", testcase, exit_code);
+        let status = task::wait_exit(&process);
+        info!("User task {} exited with status: {:?}", testcase, status);
     }
 }
@@ -0,0 +1,64 @@
+//! Physical page frames backing user memory.
+//!
+//! A frame may be mapped into several address spaces at once (e.g. after
+//! `fork`), so frames are reference counted with [`Arc`], and a frame is
+//! only freed when the last mapping of it goes away.
+
+use alloc::sync::Arc;
+
+use axerrno::{AxError, AxResult};
+use axhal::mem::{phys_to_virt, virt_to_phys};
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+/// A 4K physical page frame owned by the kernel.
+pub struct Frame {
+    paddr: PhysAddr,
+}
+
+impl Frame {
+    /// Allocates a zero-filled frame.
+    pub fn new_zeroed() -> AxResult<Arc<Self>> {
+        let vaddr = axalloc::global_allocator()
+            .alloc_pages(1, PAGE_SIZE_4K)
+            .map_err(|_| AxError::NoMemory)?;
+        let frame = Self {
+            paddr: virt_to_phys(VirtAddr::from(vaddr)),
+        };
+        frame.as_mut_slice().fill(0);
+        Ok(Arc::new(frame))
+    }
+
+    /// Allocates a frame with the same content as `self`.
+    pub fn duplicate(&self) -> AxResult<Arc<Self>> {
+        let frame = Self::new_zeroed()?;
+        frame.as_mut_slice().copy_from_slice(self.as_slice());
+        Ok(frame)
+    }
+
+    /// Returns the physical address of the frame.
+    pub const fn paddr(&self) -> PhysAddr {
+        self.paddr
+    }
+
+    /// Returns the content of the frame.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(phys_to_virt(self.paddr).as_ptr(), PAGE_SIZE_4K) }
+    }
+
+    /// Returns the content of the frame for writing.
+    ///
+    /// The frame may be mapped into user space and written concurrently, so
+    /// like user memory, its content is not protected by the borrow checker.
+    #[allow(clippy::mut_from_ref)]
+    pub fn as_mut_slice(&self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(phys_to_virt(self.paddr).as_mut_ptr(), PAGE_SIZE_4K)
+        }
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        axalloc::global_allocator().dealloc_pages(phys_to_virt(self.paddr).as_usize(), 1);
+    }
+}
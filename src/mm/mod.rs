@@ -0,0 +1,166 @@
+mod frame;
+mod space;
+
+use alloc::string::{String, ToString};
+
+use axerrno::{AxError, AxResult};
+use axhal::{
+    paging::MappingFlags,
+    trap::{register_trap_handler, PAGE_FAULT},
+};
+use axtask::TaskExtRef;
+use memory_addr::{VirtAddr, PAGE_SIZE_4K};
+
+pub use self::{frame::Frame, space::UserSpace};
+use crate::{
+    config,
+    loader::{self, ELFInfo},
+    signal::{self, SigInfo, SEGV_ACCERR, SEGV_MAPERR, SIGSEGV},
+};
+
+/// Load a user app.
+///
+/// # Returns
+/// - The first return value is the entry point of the user app.
+/// - The second return value is the top of the user stack.
+/// - The third return value is the address space of the user app.
+pub fn load_user_app(app_name: &str) -> AxResult<(VirtAddr, VirtAddr, UserSpace)> {
+    let mut uspace = UserSpace::new()?;
+    let data = loader::get_app_data_by_name(app_name).ok_or(AxError::NotFound)?;
+    let elf_info = loader::load_elf(data, uspace.base())?;
+    let (entry, ustack_top) = map_elf(&mut uspace, &elf_info, &[app_name.to_string()], &[])?;
+    Ok((entry, ustack_top, uspace))
+}
+
+/// Map the segments of a loaded ELF file and the user stack into `uspace`,
+/// which should contain no user areas.
+///
+/// `args` and `envs` are the arguments and environment variables passed to
+/// the app on its stack.
+///
+/// # Returns
+/// - The first return value is the entry point of the user app.
+/// - The second return value is the top of the user stack.
+pub fn map_elf(
+    uspace: &mut UserSpace,
+    elf_info: &ELFInfo,
+    args: &[String],
+    envs: &[String],
+) -> AxResult<(VirtAddr, VirtAddr)> {
+    for segement in &elf_info.segments {
+        debug!(
+            "Mapping ELF segment: [{:#x?}, {:#x?}) flags: {:#x?}",
+            segement.start_vaddr,
+            segement.start_vaddr + segement.size,
+            segement.flags
+        );
+        uspace.map(
+            segement.start_vaddr,
+            segement.size,
+            segement.flags,
+            false,
+            true,
+        )?;
+
+        if segement.data.is_empty() {
+            continue;
+        }
+
+        uspace.write(segement.start_vaddr + segement.offset, segement.data)?;
+
+        // TDOO: flush the I-cache
+    }
+
+    // The heap starts empty just after the highest segment.
+    let heap_start = elf_info
+        .segments
+        .iter()
+        .map(|segment| segment.start_vaddr + segment.size)
+        .max()
+        .unwrap_or(uspace.base());
+    uspace.init_heap(heap_start);
+
+    // The user stack is divided into two parts:
+    // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
+    // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
+    //  When the app starts running, the stack pointer points to `ustack_pointer`.
+    let ustack_end = VirtAddr::from_usize(config::USER_STACK_TOP);
+    let ustack_size = config::USER_STACK_SIZE;
+    let ustack_start = ustack_end - ustack_size;
+    debug!(
+        "Mapping user stack: {:#x?} -> {:#x?}",
+        ustack_start, ustack_end
+    );
+    let (stack_data, ustack_pointer) = kernel_elf_parser::get_app_stack_region(
+        args,
+        envs,
+        &elf_info.auxv,
+        ustack_start,
+        ustack_size,
+    );
+    uspace.map(
+        ustack_start,
+        ustack_size,
+        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+        false,
+        true,
+    )?;
+
+    uspace.write(VirtAddr::from_usize(ustack_pointer), stack_data.as_slice())?;
+
+    // The code returning from signal handlers without `SA_RESTORER`.
+    let trampoline = VirtAddr::from_usize(config::SIGNAL_TRAMPOLINE);
+    uspace.map(
+        trampoline,
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+        false,
+        true,
+    )?;
+    uspace.write(trampoline, signal::TRAMPOLINE)?;
+    Ok((elf_info.entry, VirtAddr::from(ustack_pointer)))
+}
+
+/// Whether `vaddr` is in the user part of the address space.
+fn is_user_vaddr(vaddr: VirtAddr) -> bool {
+    (config::USER_SPACE_BASE..config::USER_SPACE_BASE + config::USER_SPACE_SIZE)
+        .contains(&vaddr.as_usize())
+}
+
+#[register_trap_handler(PAGE_FAULT)]
+fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
+    // Faults in the kernel on user addresses come from system calls accessing
+    // user memory, e.g. writing to a copy-on-write page. They are resolved the
+    // same way as if user space had accessed the page.
+    if !is_user && !is_user_vaddr(vaddr) {
+        return false;
+    }
+    let curr = axtask::current();
+    let aspace = curr.task_ext().process.aspace();
+    let mut aspace = aspace.lock();
+    if !aspace.handle_page_fault(vaddr, access_flags) {
+        if !is_user {
+            return false;
+        }
+        let code = if aspace.is_mapped(vaddr) {
+            SEGV_ACCERR
+        } else {
+            SEGV_MAPERR
+        };
+        drop(aspace);
+        info!("{}: segmentation fault at {:#x}", curr.id_name(), vaddr);
+        // Only page faults are forwarded by axhal, so other user exceptions
+        // (e.g. illegal instructions) cannot raise their signals yet.
+        signal::handle_fault_signal(SigInfo::new(SIGSEGV, code).with_addr(vaddr.as_usize()));
+    } else {
+        drop(aspace);
+    }
+    if is_user {
+        // As at the end of system calls, a killed thread must not return to
+        // user space, and the pending signals are handled.
+        crate::task::exit_if_killed();
+        crate::task::apply_priority();
+        signal::handle_trap_signals();
+    }
+    true
+}
@@ -0,0 +1,885 @@
+//! User address spaces with copy-on-write sharing.
+//!
+//! [`axmm::AddrSpace`] owns the page table, but it frees the frames of an
+//! area when the area is unmapped, so its frames cannot be shared between
+//! address spaces. [`UserSpace`] therefore keeps track of the user areas and
+//! the [`Frame`]s behind them by itself, and only uses the [`AddrSpace`] to
+//! map each populated page linearly to its frame.
+//!
+//! The pages of a file-backed area are those of the page cache of the file,
+//! which are shared by its shared mappings, and copied on write by its
+//! private ones.
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+
+use axerrno::{ax_err, AxResult};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::frame::Frame;
+use crate::{config, file::FileLike, fs::File};
+
+/// The file mapped in an area.
+#[derive(Clone)]
+struct Backing {
+    /// The file, which is a regular [`File`].
+    file: Arc<dyn FileLike>,
+    /// The offset in the file of the start of the area.
+    offset: u64,
+}
+
+impl Backing {
+    fn file(&self) -> &File {
+        self.file
+            .as_any()
+            .downcast_ref()
+            .expect("mapped file is not a regular file")
+    }
+}
+
+/// A contiguous range of user virtual memory with the same permissions.
+#[derive(Clone)]
+struct VmArea {
+    /// The end address (exclusive) of the area.
+    end: VirtAddr,
+    /// The permissions of the area.
+    flags: MappingFlags,
+    /// Whether the pages are shared with forked address spaces, and with
+    /// the other mappings of the file of the area, instead of being copied
+    /// on write.
+    shared: bool,
+    /// The file mapped in the area, or `None` for anonymous memory.
+    backing: Option<Backing>,
+}
+
+impl VmArea {
+    /// Returns the part of the area starting `offset` bytes into it.
+    fn tail(&self, offset: usize) -> Self {
+        let mut tail = self.clone();
+        if let Some(backing) = &mut tail.backing {
+            backing.offset += offset as u64;
+        }
+        tail
+    }
+
+    /// Whether `next`, starting where this area starting at `start` ends,
+    /// continues it with the same permissions, sharing and file.
+    fn is_continued_by(&self, start: VirtAddr, next: &Self) -> bool {
+        let same_backing = match (&self.backing, &next.backing) {
+            (None, None) => true,
+            (Some(backing), Some(next_backing)) => {
+                Arc::ptr_eq(&backing.file, &next_backing.file)
+                    && backing.offset + (self.end - start) as u64 == next_backing.offset
+            }
+            _ => false,
+        };
+        self.flags == next.flags && self.shared == next.shared && same_backing
+    }
+
+    /// Whether the area cannot be made writable, as it shares the pages of a
+    /// file not opened for writing.
+    fn is_write_denied(&self) -> bool {
+        self.shared
+            && self
+                .backing
+                .as_ref()
+                .is_some_and(|backing| !backing.file.status_flags().get().writable())
+    }
+
+    /// Returns the flags to map `frame` with in this area.
+    ///
+    /// A private frame that is also referenced elsewhere is mapped read-only,
+    /// so that the first write to it faults and copies it.
+    fn page_flags(&self, frame: &Arc<Frame>) -> MappingFlags {
+        if !self.shared && Arc::strong_count(frame) > 1 {
+            self.flags - MappingFlags::WRITE
+        } else {
+            self.flags
+        }
+    }
+}
+
+/// Whether the pages of an area with `flags` are mapped in the page table.
+///
+/// The pages of an area without any access permission are not, as a page
+/// mapped without permission would still be readable.
+fn is_accessible(flags: MappingFlags) -> bool {
+    flags.intersects(MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE)
+}
+
+/// Returns the area of `areas` containing `vaddr`, with its start address.
+fn area_containing(
+    areas: &BTreeMap<VirtAddr, VmArea>,
+    vaddr: VirtAddr,
+) -> Option<(VirtAddr, &VmArea)> {
+    areas
+        .range(..=vaddr)
+        .next_back()
+        .filter(|(_, area)| vaddr < area.end)
+        .map(|(&start, area)| (start, area))
+}
+
+/// Splits the area of `areas` containing `vaddr`, if any, so that an area
+/// starts at `vaddr`.
+fn split_at(areas: &mut BTreeMap<VirtAddr, VmArea>, vaddr: VirtAddr) {
+    let Some((start, area)) = area_containing(areas, vaddr) else {
+        return;
+    };
+    if start != vaddr {
+        let tail = area.tail(vaddr - start);
+        areas.get_mut(&start).unwrap().end = vaddr;
+        areas.insert(vaddr, tail);
+    }
+}
+
+/// Merges the area of `areas` starting at `vaddr`, if any, into the
+/// previous one if they are contiguous and have the same permissions and
+/// sharing.
+fn merge_at(areas: &mut BTreeMap<VirtAddr, VmArea>, vaddr: VirtAddr) {
+    let Some(area) = areas.get(&vaddr) else {
+        return;
+    };
+    let Some((&prev_start, prev)) = areas.range(..vaddr).next_back() else {
+        return;
+    };
+    if prev.end == vaddr && prev.is_continued_by(prev_start, area) {
+        let end = area.end;
+        areas.remove(&vaddr);
+        areas.get_mut(&prev_start).unwrap().end = end;
+    }
+}
+
+/// The address space of a user process.
+pub struct UserSpace {
+    aspace: AddrSpace,
+    /// The user areas, indexed by their start addresses.
+    areas: BTreeMap<VirtAddr, VmArea>,
+    /// The populated pages, indexed by their virtual addresses.
+    pages: BTreeMap<VirtAddr, Arc<Frame>>,
+    /// The start of the heap, just after the highest ELF segment.
+    heap_start: VirtAddr,
+    /// The program break, i.e. the end of the heap.
+    brk: VirtAddr,
+}
+
+fn map_page(
+    aspace: &mut AddrSpace,
+    vaddr: VirtAddr,
+    frame: &Frame,
+    flags: MappingFlags,
+) -> AxResult {
+    aspace.map_linear(vaddr, frame.paddr(), PAGE_SIZE_4K, flags)
+}
+
+fn remap_page(
+    aspace: &mut AddrSpace,
+    vaddr: VirtAddr,
+    frame: &Frame,
+    flags: MappingFlags,
+) -> AxResult {
+    aspace.unmap(vaddr, PAGE_SIZE_4K)?;
+    map_page(aspace, vaddr, frame, flags)
+}
+
+impl Drop for UserSpace {
+    fn drop(&mut self) {
+        // Writes back the shared file mappings.
+        self.clear();
+    }
+}
+
+impl UserSpace {
+    /// Creates an empty user address space.
+    pub fn new() -> AxResult<Self> {
+        let base = VirtAddr::from_usize(config::USER_SPACE_BASE);
+        Ok(Self {
+            aspace: axmm::new_user_aspace(base, config::USER_SPACE_SIZE)?,
+            areas: BTreeMap::new(),
+            pages: BTreeMap::new(),
+            heap_start: base,
+            brk: base,
+        })
+    }
+
+    /// Removes all user areas, e.g. before loading a new program.
+    pub fn clear(&mut self) {
+        if let Err(e) = self.write_back(self.base(), self.end()) {
+            warn!("failed to write back the shared file mappings: {:?}", e);
+        }
+        self.aspace.clear();
+        self.areas.clear();
+        self.pages.clear();
+        self.heap_start = self.base();
+        self.brk = self.base();
+    }
+
+    /// Returns the lowest user address.
+    pub const fn base(&self) -> VirtAddr {
+        self.aspace.base()
+    }
+
+    /// Returns the highest user address (exclusive).
+    pub const fn end(&self) -> VirtAddr {
+        self.aspace.end()
+    }
+
+    /// Returns the physical address of the root page table.
+    pub const fn page_table_root(&self) -> PhysAddr {
+        self.aspace.page_table_root()
+    }
+
+    /// Returns the physical address that `vaddr` maps to, if it is in a
+    /// shared area and its page is populated.
+    pub fn shared_paddr(&self, vaddr: VirtAddr) -> Option<PhysAddr> {
+        let (_, area) = self.find_area(vaddr)?;
+        if !area.shared {
+            return None;
+        }
+        let frame = self.pages.get(&vaddr.align_down_4k())?;
+        Some(frame.paddr() + vaddr.align_offset_4k())
+    }
+
+    fn find_area(&self, vaddr: VirtAddr) -> Option<(VirtAddr, &VmArea)> {
+        area_containing(&self.areas, vaddr)
+    }
+
+    fn overlaps(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        self.areas
+            .range(..end)
+            .next_back()
+            .is_some_and(|(_, area)| area.end > start)
+    }
+
+    /// Whether `[start, end)` is entirely mapped.
+    fn is_covered(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        let mut vaddr = start;
+        while vaddr < end {
+            match self.find_area(vaddr) {
+                Some((_, area)) => vaddr = area.end,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Checks that `[start, start + size)` is page-aligned and in the user
+    /// address space, and returns its end.
+    pub fn check_range(&self, start: VirtAddr, size: usize) -> AxResult<VirtAddr> {
+        if !start.is_aligned_4k() || !memory_addr::is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "unaligned user range");
+        }
+        match start.as_usize().checked_add(size).map(VirtAddr::from) {
+            Some(end) if start >= self.base() && end <= self.end() => Ok(end),
+            _ => ax_err!(InvalidInput, "user range out of the address space"),
+        }
+    }
+
+    /// Removes the populated pages in `[start, end)`, all in an area with
+    /// `flags`, from the page table, and returns them.
+    fn take_pages(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        flags: MappingFlags,
+    ) -> AxResult<BTreeMap<VirtAddr, Arc<Frame>>> {
+        let mut taken = self.pages.split_off(&start);
+        self.pages.append(&mut taken.split_off(&end));
+        if is_accessible(flags) {
+            for &vaddr in taken.keys() {
+                self.aspace.unmap(vaddr, PAGE_SIZE_4K)?;
+            }
+        }
+        Ok(taken)
+    }
+
+    /// Adds `frame` as the page at `vaddr` of `area`.
+    fn put_page(&mut self, vaddr: VirtAddr, frame: Arc<Frame>, area: &VmArea) -> AxResult {
+        if is_accessible(area.flags) {
+            map_page(&mut self.aspace, vaddr, &frame, area.page_flags(&frame))?;
+        }
+        self.pages.insert(vaddr, frame);
+        Ok(())
+    }
+
+    /// Whether `vaddr` is in a mapped area.
+    pub fn is_mapped(&self, vaddr: VirtAddr) -> bool {
+        self.find_area(vaddr).is_some()
+    }
+
+    /// Finds a free range of `size` bytes, preferring one at or above `hint`.
+    pub fn find_free_area(&self, hint: VirtAddr, size: usize) -> Option<VirtAddr> {
+        let lowest = hint.max(self.base()).align_up_4k();
+        let mut start = lowest;
+        for (&area_start, area) in self.areas.range(..) {
+            if area.end <= start {
+                continue;
+            }
+            if start
+                .as_usize()
+                .checked_add(size)
+                .is_none_or(|end| area_start.as_usize() >= end)
+            {
+                break;
+            }
+            start = area.end;
+        }
+        if start
+            .as_usize()
+            .checked_add(size)
+            .is_some_and(|end| end <= self.end().as_usize())
+        {
+            Some(start)
+        } else if lowest > self.base() {
+            self.find_free_area(self.base(), size)
+        } else {
+            None
+        }
+    }
+
+    /// Adds a user area of `size` bytes at `start`.
+    ///
+    /// Pages are allocated on the first access unless `populate` is set.
+    /// Pages of a `shared` area remain shared with the forked address spaces
+    /// instead of being copied on write.
+    pub fn map(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        shared: bool,
+        populate: bool,
+    ) -> AxResult {
+        let end = self.check_range(start, size)?;
+        if size == 0 {
+            return ax_err!(InvalidInput, "empty user area");
+        }
+        if self.overlaps(start, end) {
+            return ax_err!(AlreadyExists, "user area overlaps");
+        }
+        self.areas.insert(
+            start,
+            VmArea {
+                end,
+                flags,
+                shared,
+                backing: None,
+            },
+        );
+        if populate {
+            let mut vaddr = start;
+            while vaddr < end {
+                self.populate_page(vaddr)?;
+                vaddr += PAGE_SIZE_4K;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a user area of `size` bytes at `start` mapping the regular
+    /// [`File`] `file` from `offset`, which must be page-aligned.
+    ///
+    /// The pages of a `shared` area are those of the page cache of the file,
+    /// while those of a private area are copied from them on write.
+    pub fn map_file(
+        &mut self,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        shared: bool,
+        file: Arc<dyn FileLike>,
+        offset: u64,
+    ) -> AxResult {
+        if !memory_addr::is_aligned_4k(offset as usize) {
+            return ax_err!(InvalidInput, "unaligned file offset");
+        }
+        self.map(start, size, flags, shared, false)?;
+        self.areas.get_mut(&start).unwrap().backing = Some(Backing { file, offset });
+        Ok(())
+    }
+
+    /// Writes the pages of the shared file-backed areas in `[start, end)`
+    /// back to their files.
+    fn write_back(&self, start: VirtAddr, end: VirtAddr) -> AxResult {
+        let first = self
+            .find_area(start)
+            .map_or(start, |(area_start, _)| area_start);
+        for (&area_start, area) in self.areas.range(first..end) {
+            let Some(backing) = area.backing.as_ref().filter(|_| area.shared) else {
+                continue;
+            };
+            let from = start.max(area_start);
+            let to = end.min(area.end);
+            let file_start = backing.offset + (from - area_start) as u64;
+            backing
+                .file()
+                .write_back(file_start, file_start + (to - from) as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pages of the shared file-backed areas in `[start, start +
+    /// size)` back to their files, as `msync` does.
+    ///
+    /// Fails with `NoMemory` if part of the range is not mapped.
+    pub fn sync(&self, start: VirtAddr, size: usize) -> AxResult {
+        let end = self.check_range(start, size)?;
+        if !self.is_covered(start, end) {
+            return ax_err!(NoMemory, "synchronized range not mapped");
+        }
+        self.write_back(start, end)
+    }
+
+    /// Removes the user areas in `[start, start + size)`, splitting those
+    /// which are only partly in it, and frees their pages.
+    ///
+    /// The pages of shared file-backed areas are written back to their files
+    /// first.
+    pub fn unmap(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        let end = self.check_range(start, size)?;
+        if let Err(e) = self.write_back(start, end) {
+            warn!("failed to write back the shared file mappings: {:?}", e);
+        }
+        split_at(&mut self.areas, start);
+        split_at(&mut self.areas, end);
+        let starts: Vec<_> = self.areas.range(start..end).map(|(&s, _)| s).collect();
+        for area_start in starts {
+            let area = self.areas.remove(&area_start).unwrap();
+            self.take_pages(area_start, area.end, area.flags)?;
+        }
+        Ok(())
+    }
+
+    /// Changes the permissions of `[start, start + size)` to `flags`,
+    /// splitting the areas which are only partly in it.
+    ///
+    /// Fails with `NoMemory` if part of the range is not mapped, and with
+    /// `PermissionDenied` if `flags` allow writing to the pages of a file not
+    /// opened for writing.
+    pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
+        let end = self.check_range(start, size)?;
+        if !self.is_covered(start, end) {
+            return ax_err!(NoMemory, "protected range not mapped");
+        }
+        if flags.contains(MappingFlags::WRITE) {
+            let first = self.find_area(start).unwrap().0;
+            if self
+                .areas
+                .range(first..end)
+                .any(|(_, area)| area.is_write_denied())
+            {
+                return ax_err!(PermissionDenied, "file not opened for writing");
+            }
+        }
+        split_at(&mut self.areas, start);
+        split_at(&mut self.areas, end);
+        let starts: Vec<_> = self.areas.range(start..end).map(|(&s, _)| s).collect();
+        for &area_start in &starts {
+            let area = self.areas.get_mut(&area_start).unwrap();
+            let old_flags = core::mem::replace(&mut area.flags, flags);
+            let area = area.clone();
+            for (vaddr, frame) in self.take_pages(area_start, area.end, old_flags)? {
+                self.put_page(vaddr, frame, &area)?;
+            }
+        }
+        merge_at(&mut self.areas, end);
+        for &area_start in starts.iter().rev() {
+            merge_at(&mut self.areas, area_start);
+        }
+        Ok(())
+    }
+
+    /// Resizes the part `[old_start, old_start + old_size)` of an area to
+    /// `new_size` bytes, as `mremap` does, and returns its new start.
+    ///
+    /// The part is resized in place if it can be. Otherwise, if `may_move`
+    /// is set, it is moved with its pages to a free range, or to `new_start`
+    /// if it is given, replacing the areas there. A part which grows is
+    /// extended with the permissions of its area.
+    pub fn remap(
+        &mut self,
+        old_start: VirtAddr,
+        old_size: usize,
+        new_size: usize,
+        may_move: bool,
+        new_start: Option<VirtAddr>,
+    ) -> AxResult<VirtAddr> {
+        let old_end = self.check_range(old_start, old_size)?;
+        let Some((area_start, area)) = self
+            .find_area(old_start)
+            .filter(|(_, area)| old_end <= area.end)
+        else {
+            return ax_err!(BadAddress, "remapped range not in a single area");
+        };
+        let area = area.clone();
+        if let Some(new_start) = new_start {
+            let new_end = self.check_range(new_start, new_size)?;
+            if new_start < old_end && old_start < new_end {
+                return ax_err!(InvalidInput, "remapped range overlaps its target");
+            }
+            self.unmap(new_start, new_size)?;
+            let part = area.tail(old_start - area_start);
+            return self.move_part(old_start, old_end, new_start, new_size, part);
+        }
+        if new_size <= old_size {
+            self.unmap(old_start + new_size, old_size - new_size)?;
+            return Ok(old_start);
+        }
+        let new_end = old_start
+            .as_usize()
+            .checked_add(new_size)
+            .map(VirtAddr::from);
+        if let Some(new_end) = new_end.filter(|&end| end <= self.end()) {
+            if old_end == area.end && !self.overlaps(old_end, new_end) {
+                self.areas.get_mut(&area_start).unwrap().end = new_end;
+                return Ok(old_start);
+            }
+        }
+        if !may_move {
+            return ax_err!(NoMemory, "remapped range cannot grow in place");
+        }
+        let Some(new_start) = self.find_free_area(old_start, new_size) else {
+            return ax_err!(NoMemory, "no room for the remapped range");
+        };
+        let part = area.tail(old_start - area_start);
+        self.move_part(old_start, old_end, new_start, new_size, part)
+    }
+
+    /// Moves `[old_start, old_end)`, the start of which is described by
+    /// `area`, to a new area of `new_size` bytes at `new_start`, with the
+    /// pages which fit in it.
+    fn move_part(
+        &mut self,
+        old_start: VirtAddr,
+        old_end: VirtAddr,
+        new_start: VirtAddr,
+        new_size: usize,
+        area: VmArea,
+    ) -> AxResult<VirtAddr> {
+        split_at(&mut self.areas, old_start);
+        split_at(&mut self.areas, old_end);
+        self.areas.remove(&old_start);
+        let pages = self.take_pages(old_start, old_end, area.flags)?;
+        let area = VmArea {
+            end: new_start + new_size,
+            ..area
+        };
+        for (vaddr, frame) in pages {
+            let offset = vaddr - old_start;
+            if offset < new_size {
+                self.put_page(new_start + offset, frame, &area)?;
+            }
+        }
+        self.areas.insert(new_start, area);
+        Ok(new_start)
+    }
+
+    /// Makes the heap start at `start`, empty.
+    pub fn init_heap(&mut self, start: VirtAddr) {
+        self.heap_start = start;
+        self.brk = start;
+    }
+
+    /// Returns the start of the heap.
+    pub const fn heap_start(&self) -> VirtAddr {
+        self.heap_start
+    }
+
+    /// Returns the program break.
+    pub const fn brk(&self) -> VirtAddr {
+        self.brk
+    }
+
+    /// Moves the program break to `brk`, growing the heap with pages
+    /// allocated on the first access, or shrinking it.
+    pub fn set_brk(&mut self, brk: VirtAddr) -> AxResult {
+        if brk < self.heap_start || brk > self.end() {
+            return ax_err!(InvalidInput, "program break out of the heap");
+        }
+        let old_end = self.brk.align_up_4k();
+        let new_end = brk.align_up_4k();
+        if new_end > old_end {
+            let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+            self.map(old_end, new_end - old_end, flags, false, false)?;
+            merge_at(&mut self.areas, old_end);
+        } else {
+            self.unmap(new_end, old_end - new_end)?;
+        }
+        self.brk = brk;
+        Ok(())
+    }
+
+    /// Allocates the page at `vaddr` if it is not populated yet.
+    fn populate_page(&mut self, vaddr: VirtAddr) -> AxResult {
+        if self.pages.contains_key(&vaddr) {
+            return Ok(());
+        }
+        let Some((area_start, area)) = self.find_area(vaddr) else {
+            return ax_err!(BadAddress);
+        };
+        let area = area.clone();
+        let frame = match &area.backing {
+            Some(backing) => {
+                let offset = backing.offset + (vaddr - area_start) as u64;
+                backing.file().page(offset / PAGE_SIZE_4K as u64)?
+            }
+            None => Frame::new_zeroed()?,
+        };
+        self.put_page(vaddr, frame, &area)
+    }
+
+    /// Gives the page at `vaddr` a private, writable frame, copying the
+    /// content if the frame is still shared.
+    fn break_cow(&mut self, vaddr: VirtAddr) -> AxResult {
+        let Some((_, area)) = self.find_area(vaddr) else {
+            return ax_err!(BadAddress);
+        };
+        let flags = area.flags;
+        let Some(frame) = self.pages.get_mut(&vaddr) else {
+            return ax_err!(BadAddress);
+        };
+        if Arc::strong_count(frame) > 1 {
+            *frame = frame.duplicate()?;
+        }
+        remap_page(&mut self.aspace, vaddr, frame, flags)
+    }
+
+    /// Writes `data` to the user memory starting at `start`, populating the
+    /// pages as needed regardless of their permissions.
+    pub fn write(&mut self, start: VirtAddr, data: &[u8]) -> AxResult {
+        let mut vaddr = start;
+        let mut data = data;
+        while !data.is_empty() {
+            let page = vaddr.align_down_4k();
+            let offset = vaddr.align_offset_4k();
+            let len = data.len().min(PAGE_SIZE_4K - offset);
+            self.populate_page(page)?;
+            if self.find_area(page).is_some_and(|(_, area)| !area.shared) {
+                self.break_cow(page)?;
+            }
+            self.pages[&page].as_mut_slice()[offset..offset + len].copy_from_slice(&data[..len]);
+            vaddr += len;
+            data = &data[len..];
+        }
+        Ok(())
+    }
+
+    /// Makes `[start, start + size)` accessible with `access_flags` from the
+    /// kernel, as if user space had accessed every page of it.
+    ///
+    /// Returns `false` if some page is not accessible with `access_flags`.
+    pub fn populate(&mut self, start: VirtAddr, size: usize, access_flags: MappingFlags) -> bool {
+        let end = start + size;
+        let mut vaddr = start.align_down_4k();
+        while vaddr < end {
+            if !self.handle_page_fault(vaddr, access_flags) {
+                return false;
+            }
+            vaddr += PAGE_SIZE_4K;
+        }
+        true
+    }
+
+    /// Handles a page fault at `vaddr` caused by an access with
+    /// `access_flags`.
+    ///
+    /// Returns `true` if the fault is resolved, i.e. the access is allowed
+    /// and the page is now mapped with sufficient permissions.
+    pub fn handle_page_fault(&mut self, vaddr: VirtAddr, access_flags: MappingFlags) -> bool {
+        let Some((_, area)) = self.find_area(vaddr) else {
+            return false;
+        };
+        if !area.flags.contains(access_flags) {
+            return false;
+        }
+        let page = vaddr.align_down_4k();
+        if !self.pages.contains_key(&page) {
+            return self.populate_page(page).is_ok();
+        }
+        if access_flags.contains(MappingFlags::WRITE) {
+            let writable = self
+                .aspace
+                .page_table()
+                .query(page)
+                .is_ok_and(|(_, flags, _)| flags.contains(MappingFlags::WRITE));
+            if !writable {
+                return self.break_cow(page).is_ok();
+            }
+        }
+        true
+    }
+
+    /// Duplicates the address space for a forked process.
+    ///
+    /// Private pages are shared read-only by both address spaces until one
+    /// of them writes to the page, at which time it gets its own copy.
+    pub fn fork(&mut self) -> AxResult<Self> {
+        let mut child = Self::new()?;
+        child.areas = self.areas.clone();
+        child.init_heap(self.heap_start);
+        child.brk = self.brk;
+        let pages: Vec<_> = self.pages.iter().map(|(&v, f)| (v, f.clone())).collect();
+        for (vaddr, frame) in pages {
+            let Some((_, area)) = self.find_area(vaddr) else {
+                continue;
+            };
+            let area = area.clone();
+            child.put_page(vaddr, frame.clone(), &area)?;
+            if !area.shared && area.flags.contains(MappingFlags::WRITE) {
+                remap_page(&mut self.aspace, vaddr, &frame, area.page_flags(&frame))?;
+            }
+        }
+        Ok(child)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::any::Any;
+
+    use axerrno::{LinuxError, LinuxResult};
+
+    use super::*;
+    use crate::{
+        file::{Kstat, StatusFlags},
+        fs::OpenFlags,
+    };
+
+    struct DummyFile(StatusFlags);
+
+    impl FileLike for DummyFile {
+        fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+            Ok(0)
+        }
+
+        fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+            Ok(buf.len())
+        }
+
+        fn stat(&self) -> LinuxResult<Kstat> {
+            Err(LinuxError::ENOSYS)
+        }
+
+        fn status_flags(&self) -> &StatusFlags {
+            &self.0
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    const RW: MappingFlags = MappingFlags::READ.union(MappingFlags::WRITE);
+
+    fn area(end: usize, flags: MappingFlags, backing: Option<Backing>) -> VmArea {
+        VmArea {
+            end: VirtAddr::from(end),
+            flags,
+            shared: false,
+            backing,
+        }
+    }
+
+    fn file_backing(file: &Arc<dyn FileLike>, offset: u64) -> Option<Backing> {
+        Some(Backing {
+            file: file.clone(),
+            offset,
+        })
+    }
+
+    /// Returns the start and end addresses of `areas`.
+    fn ranges(areas: &BTreeMap<VirtAddr, VmArea>) -> Vec<(usize, usize)> {
+        areas
+            .iter()
+            .map(|(start, area)| (start.as_usize(), area.end.as_usize()))
+            .collect()
+    }
+
+    #[test]
+    fn split_area() {
+        let mut areas = BTreeMap::new();
+        areas.insert(VirtAddr::from(0x1000), area(0x5000, RW, None));
+        split_at(&mut areas, VirtAddr::from(0x3000));
+        assert_eq!(ranges(&areas), [(0x1000, 0x3000), (0x3000, 0x5000)]);
+        assert!(areas.values().all(|area| area.flags == RW));
+
+        // Splitting at the start of an area, or outside of any area, does
+        // nothing.
+        split_at(&mut areas, VirtAddr::from(0x3000));
+        split_at(&mut areas, VirtAddr::from(0x1000));
+        split_at(&mut areas, VirtAddr::from(0x8000));
+        assert_eq!(ranges(&areas), [(0x1000, 0x3000), (0x3000, 0x5000)]);
+    }
+
+    #[test]
+    fn split_file_area() {
+        let file: Arc<dyn FileLike> = Arc::new(DummyFile(StatusFlags::new(OpenFlags::empty())));
+        let mut areas = BTreeMap::new();
+        areas.insert(
+            VirtAddr::from(0x1000),
+            area(0x5000, RW, file_backing(&file, 0x10000)),
+        );
+        split_at(&mut areas, VirtAddr::from(0x4000));
+        let offsets: Vec<_> = areas
+            .values()
+            .map(|area| area.backing.as_ref().unwrap().offset)
+            .collect();
+        assert_eq!(offsets, [0x10000, 0x13000]);
+
+        merge_at(&mut areas, VirtAddr::from(0x4000));
+        assert_eq!(ranges(&areas), [(0x1000, 0x5000)]);
+        assert_eq!(
+            areas[&VirtAddr::from(0x1000)]
+                .backing
+                .as_ref()
+                .unwrap()
+                .offset,
+            0x10000
+        );
+    }
+
+    #[test]
+    fn merge_areas() {
+        let mut areas = BTreeMap::new();
+        areas.insert(VirtAddr::from(0x1000), area(0x3000, RW, None));
+        areas.insert(VirtAddr::from(0x3000), area(0x5000, RW, None));
+        merge_at(&mut areas, VirtAddr::from(0x3000));
+        assert_eq!(ranges(&areas), [(0x1000, 0x5000)]);
+    }
+
+    #[test]
+    fn merge_incompatible_areas() {
+        let file: Arc<dyn FileLike> = Arc::new(DummyFile(StatusFlags::new(OpenFlags::empty())));
+        let mut shared = area(0x7000, RW, None);
+        shared.shared = true;
+        let mut areas = BTreeMap::new();
+        // Different permissions.
+        areas.insert(VirtAddr::from(0x1000), area(0x2000, RW, None));
+        areas.insert(
+            VirtAddr::from(0x2000),
+            area(0x3000, MappingFlags::READ, None),
+        );
+        // Not contiguous.
+        areas.insert(
+            VirtAddr::from(0x4000),
+            area(0x5000, MappingFlags::READ, None),
+        );
+        // Different sharing.
+        areas.insert(VirtAddr::from(0x5000), area(0x6000, RW, None));
+        areas.insert(VirtAddr::from(0x6000), shared);
+        // Anonymous and file-backed, then discontiguous file offsets.
+        areas.insert(
+            VirtAddr::from(0x7000),
+            area(0x8000, RW, file_backing(&file, 0)),
+        );
+        areas.insert(
+            VirtAddr::from(0x8000),
+            area(0x9000, RW, file_backing(&file, 0x2000)),
+        );
+        let before = ranges(&areas);
+        for &(start, _) in &before {
+            merge_at(&mut areas, VirtAddr::from(start));
+        }
+        assert_eq!(ranges(&areas), before);
+    }
+}
@@ -0,0 +1,53 @@
+//! Resource limits of processes.
+
+use crate::file::NR_OPEN;
+
+/// The highest size of the heap in bytes.
+pub const RLIMIT_DATA: u32 = 2;
+/// The highest number of file descriptors plus 1.
+pub const RLIMIT_NOFILE: u32 = 7;
+/// The number of resources.
+pub const RLIM_NLIMITS: u32 = 16;
+/// No limit.
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// A resource limit, in the layout of `struct rlimit`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RLimit {
+    /// The soft limit, which is enforced.
+    pub cur: u64,
+    /// The hard limit, up to which the soft limit may be raised.
+    pub max: u64,
+}
+
+impl RLimit {
+    pub const INFINITY: Self = Self {
+        cur: RLIM_INFINITY,
+        max: RLIM_INFINITY,
+    };
+}
+
+/// The resource limits of a process, which are inherited by its children.
+#[derive(Clone)]
+pub struct RLimits([RLimit; RLIM_NLIMITS as usize]);
+
+impl RLimits {
+    /// Returns the limits of the processes spawned by the kernel.
+    pub const fn new() -> Self {
+        let mut limits = [RLimit::INFINITY; RLIM_NLIMITS as usize];
+        limits[RLIMIT_NOFILE as usize] = RLimit {
+            cur: 1024,
+            max: NR_OPEN as u64,
+        };
+        Self(limits)
+    }
+
+    pub fn get(&self, resource: u32) -> RLimit {
+        self.0[resource as usize]
+    }
+
+    pub fn set(&mut self, resource: u32, limit: RLimit) {
+        self.0[resource as usize] = limit;
+    }
+}
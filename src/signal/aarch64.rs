@@ -0,0 +1,314 @@
+//! Signal frames of aarch64.
+//!
+//! See <https://github.com/torvalds/linux/blob/master/arch/arm64/include/uapi/asm/sigcontext.h>
+
+use core::{
+    arch::asm,
+    mem::{offset_of, size_of},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+
+use super::{SigInfo, SigSet, SigStack};
+use crate::uaccess::{get_user, put_user};
+
+/// `mov x8, #139; svc #0`, i.e. `rt_sigreturn()`.
+pub const TRAMPOLINE: &[u8] = &[0x68, 0x11, 0x80, 0xd2, 0x01, 0x00, 0x00, 0xd4];
+
+/// The minimum size of an alternate signal stack.
+pub const MINSIGSTKSZ: usize = 5120;
+
+/// The condition flags of `pstate`, which user space may change by
+/// `rt_sigreturn`.
+const PSTATE_NZCV: u64 = 0xf000_0000;
+
+/// The magic of the `fpsimd_context` record.
+const FPSIMD_MAGIC: u32 = 0x4650_8001;
+
+/// The space of the records of extra state (e.g. `fpsimd_context`),
+/// terminated by an empty record.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct Reserved([u8; 4096]);
+
+impl Reserved {
+    /// Returns the records holding `fpsimd`.
+    fn new(fpsimd: FpsimdContext) -> Self {
+        let mut reserved = Self([0; 4096]);
+        // The zeroed space after it is the terminating record.
+        unsafe { (reserved.0.as_mut_ptr() as *mut FpsimdContext).write(fpsimd) };
+        reserved
+    }
+
+    /// Returns the `fpsimd_context` record, which is mandatory.
+    fn fpsimd(&self) -> LinuxResult<FpsimdContext> {
+        let mut offset = 0;
+        while offset + size_of::<RecordHeader>() <= self.0.len() {
+            // SAFETY: the header is in bounds, and `offset` is 16-aligned.
+            let head = unsafe { (self.0.as_ptr().add(offset) as *const RecordHeader).read() };
+            let size = head.size as usize;
+            if head.magic == 0 && size == 0 {
+                break;
+            }
+            if size < size_of::<RecordHeader>()
+                || !size.is_multiple_of(16)
+                || offset + size > self.0.len()
+            {
+                break;
+            }
+            if head.magic == FPSIMD_MAGIC {
+                if size != size_of::<FpsimdContext>() {
+                    break;
+                }
+                // SAFETY: the record is in bounds and 16-aligned, and any
+                // bytes are a valid `FpsimdContext`.
+                return Ok(unsafe { (self.0.as_ptr().add(offset) as *const FpsimdContext).read() });
+            }
+            offset += size;
+        }
+        Err(LinuxError::EINVAL)
+    }
+}
+
+/// `struct _aarch64_ctx`, the header of a record of extra state.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RecordHeader {
+    magic: u32,
+    size: u32,
+}
+
+/// `struct fpsimd_context`
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct FpsimdContext {
+    head: RecordHeader,
+    fpsr: u32,
+    fpcr: u32,
+    vregs: [u128; 32],
+}
+
+/// Returns whether the FP/SIMD registers are accessible, i.e. `CPACR_EL1.FPEN`
+/// does not trap them. Otherwise user space cannot use them either.
+fn fp_enabled() -> bool {
+    let cpacr: u64;
+    unsafe { asm!("mrs {}, cpacr_el1", out(reg) cpacr) };
+    (cpacr >> 20) & 0b11 == 0b11
+}
+
+impl FpsimdContext {
+    /// Saves the FP/SIMD state of user space, which the kernel does not use.
+    fn save() -> Self {
+        let mut state = Self {
+            head: RecordHeader {
+                magic: FPSIMD_MAGIC,
+                size: size_of::<Self>() as u32,
+            },
+            fpsr: 0,
+            fpcr: 0,
+            vregs: [0; 32],
+        };
+        if fp_enabled() {
+            let (fpsr, fpcr): (u64, u64);
+            unsafe {
+                asm!(
+                    ".arch_extension fp",
+                    "stp q0, q1, [{vregs}, #0x000]",
+                    "stp q2, q3, [{vregs}, #0x020]",
+                    "stp q4, q5, [{vregs}, #0x040]",
+                    "stp q6, q7, [{vregs}, #0x060]",
+                    "stp q8, q9, [{vregs}, #0x080]",
+                    "stp q10, q11, [{vregs}, #0x0a0]",
+                    "stp q12, q13, [{vregs}, #0x0c0]",
+                    "stp q14, q15, [{vregs}, #0x0e0]",
+                    "stp q16, q17, [{vregs}, #0x100]",
+                    "stp q18, q19, [{vregs}, #0x120]",
+                    "stp q20, q21, [{vregs}, #0x140]",
+                    "stp q22, q23, [{vregs}, #0x160]",
+                    "stp q24, q25, [{vregs}, #0x180]",
+                    "stp q26, q27, [{vregs}, #0x1a0]",
+                    "stp q28, q29, [{vregs}, #0x1c0]",
+                    "stp q30, q31, [{vregs}, #0x1e0]",
+                    "mrs {fpsr}, fpsr",
+                    "mrs {fpcr}, fpcr",
+                    vregs = in(reg) state.vregs.as_mut_ptr(),
+                    fpsr = out(reg) fpsr,
+                    fpcr = out(reg) fpcr,
+                    options(nostack),
+                )
+            };
+            state.fpsr = fpsr as u32;
+            state.fpcr = fpcr as u32;
+        }
+        state
+    }
+
+    /// Restores the FP/SIMD state.
+    fn restore(&self) {
+        if !fp_enabled() {
+            return;
+        }
+        unsafe {
+            asm!(
+                ".arch_extension fp",
+                "ldp q0, q1, [{vregs}, #0x000]",
+                "ldp q2, q3, [{vregs}, #0x020]",
+                "ldp q4, q5, [{vregs}, #0x040]",
+                "ldp q6, q7, [{vregs}, #0x060]",
+                "ldp q8, q9, [{vregs}, #0x080]",
+                "ldp q10, q11, [{vregs}, #0x0a0]",
+                "ldp q12, q13, [{vregs}, #0x0c0]",
+                "ldp q14, q15, [{vregs}, #0x0e0]",
+                "ldp q16, q17, [{vregs}, #0x100]",
+                "ldp q18, q19, [{vregs}, #0x120]",
+                "ldp q20, q21, [{vregs}, #0x140]",
+                "ldp q22, q23, [{vregs}, #0x160]",
+                "ldp q24, q25, [{vregs}, #0x180]",
+                "ldp q26, q27, [{vregs}, #0x1a0]",
+                "ldp q28, q29, [{vregs}, #0x1c0]",
+                "ldp q30, q31, [{vregs}, #0x1e0]",
+                "msr fpsr, {fpsr}",
+                "msr fpcr, {fpcr}",
+                vregs = in(reg) self.vregs.as_ptr(),
+                fpsr = in(reg) self.fpsr as u64,
+                fpcr = in(reg) self.fpcr as u64,
+                options(nostack, readonly),
+            )
+        };
+    }
+}
+
+/// `struct sigcontext`
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct MContext {
+    fault_address: u64,
+    regs: [u64; 31],
+    sp: u64,
+    pc: u64,
+    pstate: u64,
+    reserved: Reserved,
+}
+
+/// `struct ucontext`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UContext {
+    flags: u64,
+    link: u64,
+    stack: SigStack,
+    sigmask: SigSet,
+    unused: [u8; 120],
+    mcontext: MContext,
+}
+
+/// `struct rt_sigframe`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SigFrame {
+    info: SigInfo,
+    uc: UContext,
+}
+
+/// `struct frame_record`, linking the frame of the handler to the
+/// interrupted code for unwinders.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FrameRecord {
+    fp: u64,
+    lr: u64,
+}
+
+/// Returns the user stack pointer of `ctx`.
+pub fn stack_pointer(ctx: &TrapFrame) -> usize {
+    ctx.usp as usize
+}
+
+/// Returns the user context resumed after the system call of `tf` returns
+/// `ret`.
+pub fn syscall_return_context(tf: &TrapFrame, ret: isize) -> TrapFrame {
+    let mut ctx = *tf;
+    ctx.r[0] = ret as u64;
+    ctx
+}
+
+/// Returns the user context executing the system call of `tf` again.
+pub fn syscall_restart_context(tf: &TrapFrame) -> TrapFrame {
+    let mut ctx = *tf;
+    // Back to the `svc` instruction, with the first argument still in `x0`.
+    ctx.elr -= 4;
+    ctx
+}
+
+/// Adjusts `tf`, holding the user context to resume, for the return from
+/// the system call handler, and returns the value to return from it.
+pub fn prepare_syscall_return(tf: &mut TrapFrame) -> isize {
+    tf.r[0] as isize
+}
+
+/// Pushes the signal frame saving `ctx` below `sp`, and returns the context
+/// calling `handler(signo, &info, &uc)`, which returns to `restorer`.
+pub fn setup_frame(
+    ctx: &TrapFrame,
+    sp: usize,
+    stack: SigStack,
+    info: &SigInfo,
+    handler: usize,
+    restorer: usize,
+    mask: SigSet,
+) -> LinuxResult<TrapFrame> {
+    let record_addr = sp.wrapping_sub(size_of::<FrameRecord>()) & !15;
+    put_user(
+        record_addr as *mut FrameRecord,
+        FrameRecord {
+            fp: ctx.r[29],
+            lr: ctx.r[30],
+        },
+    )?;
+    let frame_addr = record_addr.wrapping_sub(size_of::<SigFrame>()) & !15;
+    let frame = SigFrame {
+        info: *info,
+        uc: UContext {
+            flags: 0,
+            link: 0,
+            stack,
+            sigmask: mask,
+            unused: [0; 120],
+            mcontext: MContext {
+                fault_address: 0,
+                regs: ctx.r,
+                sp: ctx.usp,
+                pc: ctx.elr,
+                pstate: ctx.spsr,
+                reserved: Reserved::new(FpsimdContext::save()),
+            },
+        },
+    };
+    put_user(frame_addr as *mut SigFrame, frame)?;
+
+    let mut handler_ctx = *ctx;
+    handler_ctx.elr = handler as u64;
+    handler_ctx.usp = frame_addr as u64;
+    handler_ctx.r[0] = info.signo() as u64;
+    handler_ctx.r[1] = (frame_addr + offset_of!(SigFrame, info)) as u64;
+    handler_ctx.r[2] = (frame_addr + offset_of!(SigFrame, uc)) as u64;
+    handler_ctx.r[29] = record_addr as u64;
+    handler_ctx.r[30] = restorer as u64;
+    Ok(handler_ctx)
+}
+
+/// Restores the user context and the signal mask saved in the signal frame,
+/// when `rt_sigreturn` is called with the trap frame `tf`.
+pub fn restore_frame(tf: &TrapFrame) -> LinuxResult<(TrapFrame, SigSet)> {
+    let frame_addr = tf.usp as usize;
+    let uc: UContext = get_user((frame_addr + offset_of!(SigFrame, uc)) as *const UContext)?;
+    let mc = &uc.mcontext;
+    mc.reserved.fpsimd()?.restore();
+    let mut ctx = *tf;
+    ctx.r = mc.regs;
+    ctx.usp = mc.sp;
+    ctx.elr = mc.pc;
+    ctx.spsr = (tf.spsr & !PSTATE_NZCV) | (mc.pstate & PSTATE_NZCV);
+    Ok((ctx, uc.sigmask))
+}
@@ -0,0 +1,838 @@
+//! POSIX signals.
+//!
+//! The signal handlers ([`SignalActions`]) are shared by the threads of a
+//! process, while each thread has its own signal mask ([`ThreadSignals`]). A
+//! signal is sent either to a thread, or to a process, in which case any of
+//! its threads not blocking the signal may handle it.
+//!
+//! Pending signals are handled when a thread returns to user space from a
+//! system call or a page fault. A handler is invoked by pushing a signal
+//! frame on the user stack, which holds the interrupted user context and is
+//! restored by `rt_sigreturn` when the handler returns.
+//!
+//! Signals are not delivered on the return from interrupts, so a thread
+//! spinning in user space only handles its signals at its next system call
+//! or page fault, and cannot be interrupted by e.g. `SIGINT` or `SIGALRM`
+//! until then. This needs axhal to call back on the return from interrupts
+//! to user space with the trap frame: its only IRQ handler, owned by
+//! axruntime, is given neither, so it cannot be done here.
+//!
+//! A thread may set an alternate signal stack, on which the handlers with
+//! `SA_ONSTACK` run, e.g. to handle a stack overflow.
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "riscv64")]
+mod riscv64;
+mod signalfd;
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+
+#[cfg(target_arch = "aarch64")]
+use self::aarch64 as arch;
+#[cfg(target_arch = "riscv64")]
+use self::riscv64 as arch;
+#[cfg(target_arch = "x86_64")]
+use self::x86_64 as arch;
+
+use alloc::collections::vec_deque::VecDeque;
+use core::{
+    mem::size_of,
+    ops::{BitAnd, BitOr, Not},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axsync::Mutex;
+use axtask::{current, AxTaskRef, TaskExtRef};
+
+pub use self::arch::TRAMPOLINE;
+pub use self::signalfd::SignalFd;
+use crate::{
+    config,
+    file::PollEvents,
+    task::{exit_current, exit_if_killed, Pid, Process, WaitStatus},
+};
+
+pub const SIGHUP: u32 = 1;
+pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
+pub const SIGILL: u32 = 4;
+pub const SIGTRAP: u32 = 5;
+pub const SIGABRT: u32 = 6;
+pub const SIGBUS: u32 = 7;
+pub const SIGFPE: u32 = 8;
+/// The signal that kills a process unconditionally.
+pub const SIGKILL: u32 = 9;
+pub const SIGUSR1: u32 = 10;
+/// The signal sent on an invalid memory access.
+pub const SIGSEGV: u32 = 11;
+pub const SIGUSR2: u32 = 12;
+pub const SIGPIPE: u32 = 13;
+pub const SIGALRM: u32 = 14;
+pub const SIGTERM: u32 = 15;
+pub const SIGSTKFLT: u32 = 16;
+/// The signal sent to the parent by default when a child process terminates.
+pub const SIGCHLD: u32 = 17;
+/// The signal that resumes a stopped process.
+pub const SIGCONT: u32 = 18;
+/// The signal that stops a process unconditionally.
+pub const SIGSTOP: u32 = 19;
+pub const SIGTSTP: u32 = 20;
+pub const SIGTTIN: u32 = 21;
+pub const SIGTTOU: u32 = 22;
+pub const SIGURG: u32 = 23;
+pub const SIGXCPU: u32 = 24;
+pub const SIGXFSZ: u32 = 25;
+pub const SIGVTALRM: u32 = 26;
+pub const SIGPROF: u32 = 27;
+pub const SIGWINCH: u32 = 28;
+pub const SIGIO: u32 = 29;
+pub const SIGPWR: u32 = 30;
+pub const SIGSYS: u32 = 31;
+/// The first real-time signal. Unlike standard signals, multiple instances
+/// of a real-time signal can be pending.
+pub const SIGRTMIN: u32 = 32;
+/// The number of signals, which is also the highest signal number.
+pub const NSIG: u32 = 64;
+
+/// The `si_code` values
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/siginfo.h>
+pub const SI_USER: i32 = 0;
+pub const SI_KERNEL: i32 = 0x80;
+pub const SI_TKILL: i32 = -6;
+pub const SEGV_MAPERR: i32 = 1;
+pub const SEGV_ACCERR: i32 = 2;
+pub const CLD_EXITED: i32 = 1;
+pub const CLD_KILLED: i32 = 2;
+pub const CLD_DUMPED: i32 = 3;
+pub const CLD_STOPPED: i32 = 5;
+pub const CLD_CONTINUED: i32 = 6;
+
+/// The handler of the default action.
+pub const SIG_DFL: usize = 0;
+/// The handler ignoring the signal.
+pub const SIG_IGN: usize = 1;
+
+/// What happens to a process receiving a signal without a handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DefaultAction {
+    /// Terminate the process. Core dumps are not supported, so signals
+    /// which dump a core on Linux just terminate the process.
+    Terminate,
+    /// Ignore the signal.
+    Ignore,
+    /// Stop the process.
+    Stop,
+    /// Resume the process if it is stopped, which is done when the signal
+    /// is sent.
+    Continue,
+}
+
+fn default_action(signo: u32) -> DefaultAction {
+    match signo {
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        SIGHUP | SIGINT | SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGKILL
+        | SIGUSR1 | SIGSEGV | SIGUSR2 | SIGPIPE | SIGALRM | SIGTERM | SIGSTKFLT | SIGXCPU
+        | SIGXFSZ | SIGVTALRM | SIGPROF | SIGIO | SIGPWR | SIGSYS => DefaultAction::Terminate,
+        // Real-time signals
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// A set of signals, in the layout of the kernel `sigset_t`.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SigSet(pub u64);
+
+impl SigSet {
+    pub const EMPTY: Self = Self(0);
+    /// `SIGKILL` and `SIGSTOP`, which cannot be blocked, ignored or caught.
+    pub const UNCATCHABLE: Self = Self(Self::single(SIGKILL).0 | Self::single(SIGSTOP).0);
+    /// The signals that stop a process by default.
+    const STOP: Self = Self(
+        Self::single(SIGSTOP).0
+            | Self::single(SIGTSTP).0
+            | Self::single(SIGTTIN).0
+            | Self::single(SIGTTOU).0,
+    );
+
+    /// Returns the set containing only `signo`.
+    pub const fn single(signo: u32) -> Self {
+        Self(1 << (signo - 1))
+    }
+
+    /// Whether `signo` is in the set.
+    pub const fn contains(self, signo: u32) -> bool {
+        self.0 & Self::single(signo).0 != 0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for SigSet {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for SigSet {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl Not for SigSet {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+/// Information about a signal, in the layout of `siginfo_t`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    _pad: i32,
+    /// The union of the fields depending on the signal and `code`.
+    fields: [u64; 14],
+}
+
+impl SigInfo {
+    /// Creates the information of the signal `signo` with `si_code` `code`.
+    pub const fn new(signo: u32, code: i32) -> Self {
+        Self {
+            signo: signo as i32,
+            errno: 0,
+            code,
+            _pad: 0,
+            fields: [0; 14],
+        }
+    }
+
+    /// The information reported when no signal is available.
+    pub const fn empty() -> Self {
+        Self::new(0, 0)
+    }
+
+    /// Creates the information of `signo` sent to the parent of the process
+    /// `pid` when it changes its state to `status`.
+    pub const fn child(signo: u32, pid: Pid, status: WaitStatus) -> Self {
+        let (code, status) = match status {
+            WaitStatus::Exited(code) => (CLD_EXITED, code),
+            WaitStatus::Signaled(signo, false) => (CLD_KILLED, signo as i32),
+            WaitStatus::Signaled(signo, true) => (CLD_DUMPED, signo as i32),
+            WaitStatus::Stopped(signo) => (CLD_STOPPED, signo as i32),
+            WaitStatus::Continued => (CLD_CONTINUED, SIGCONT as i32),
+        };
+        Self::new(signo, code).with_sender(pid).with_status(status)
+    }
+
+    /// Sets the process that sent the signal (`si_pid`), whose user ID
+    /// (`si_uid`) is always 0.
+    pub const fn with_sender(mut self, pid: Pid) -> Self {
+        self.fields[0] = pid as u32 as u64;
+        self
+    }
+
+    /// Sets the faulting address (`si_addr`).
+    pub const fn with_addr(mut self, addr: usize) -> Self {
+        self.fields[0] = addr as u64;
+        self
+    }
+
+    /// Sets the exit code or signal of a child (`si_status`).
+    pub const fn with_status(mut self, status: i32) -> Self {
+        self.fields[1] = status as u32 as u64;
+        self
+    }
+
+    /// Returns the signal number.
+    pub const fn signo(&self) -> u32 {
+        self.signo as u32
+    }
+}
+
+/// An alternate signal stack, in the layout of `stack_t`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SigStack {
+    pub sp: usize,
+    pub flags: i32,
+    pub size: usize,
+}
+
+/// The thread is running on the alternate signal stack.
+pub const SS_ONSTACK: i32 = 1;
+/// The alternate signal stack is disabled.
+pub const SS_DISABLE: i32 = 2;
+
+impl SigStack {
+    /// Returns the stack reported when there is no alternate signal stack.
+    pub const fn disabled() -> Self {
+        Self {
+            sp: 0,
+            flags: SS_DISABLE,
+            size: 0,
+        }
+    }
+
+    pub const fn is_disabled(&self) -> bool {
+        self.flags & SS_DISABLE != 0
+    }
+
+    /// Whether the stack pointer `sp` is on this stack, which grows down.
+    pub const fn contains(&self, sp: usize) -> bool {
+        sp > self.sp && sp - self.sp <= self.size
+    }
+
+    /// Returns the stack as reported to user space when the user stack
+    /// pointer is `sp`, with `SS_ONSTACK` if `sp` is on it.
+    fn report(&self, sp: usize) -> Self {
+        if self.is_disabled() {
+            Self::disabled()
+        } else if self.contains(sp) {
+            Self {
+                flags: SS_ONSTACK,
+                ..*self
+            }
+        } else {
+            *self
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of `sigaction`
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/signal-defs.h>
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SigActionFlags: u64 {
+        /// Do not send `SIGCHLD` when a child stops or resumes.
+        const SA_NOCLDSTOP = 0x0000_0001;
+        /// Do not turn terminated children into zombies.
+        const SA_NOCLDWAIT = 0x0000_0002;
+        /// The handler takes the `siginfo_t` and `ucontext_t` arguments.
+        const SA_SIGINFO = 0x0000_0004;
+        /// The handler returns to the given restorer.
+        const SA_RESTORER = 0x0400_0000;
+        /// The handler runs on the alternate signal stack.
+        const SA_ONSTACK = 0x0800_0000;
+        /// Restart the system calls interrupted by the handler.
+        const SA_RESTART = 0x1000_0000;
+        /// Do not block the signal while its handler runs.
+        const SA_NODEFER = 0x4000_0000;
+        /// Reset the handler to the default action once it is invoked.
+        const SA_RESETHAND = 0x8000_0000;
+    }
+}
+
+/// How a signal is handled.
+#[derive(Debug, Clone, Copy)]
+pub struct SigAction {
+    /// The handler, or [`SIG_DFL`] or [`SIG_IGN`].
+    pub handler: usize,
+    pub flags: SigActionFlags,
+    /// The code the handler returns to, calling `rt_sigreturn`, if
+    /// `SA_RESTORER` is set.
+    pub restorer: usize,
+    /// The signals blocked while the handler runs.
+    pub mask: SigSet,
+}
+
+impl SigAction {
+    /// The default action.
+    pub const DEFAULT: Self = Self {
+        handler: SIG_DFL,
+        flags: SigActionFlags::empty(),
+        restorer: 0,
+        mask: SigSet::EMPTY,
+    };
+
+    /// Whether the signal `signo` is discarded by this action.
+    pub fn is_ignored(&self, signo: u32) -> bool {
+        match self.handler {
+            SIG_IGN => true,
+            SIG_DFL => default_action(signo) == DefaultAction::Ignore,
+            _ => false,
+        }
+    }
+}
+
+/// The signal handlers of a process.
+///
+/// They may be shared with other processes created by `clone` with
+/// `CLONE_SIGHAND`.
+#[derive(Clone)]
+pub struct SignalActions([SigAction; NSIG as usize]);
+
+impl SignalActions {
+    pub const fn new() -> Self {
+        Self([SigAction::DEFAULT; NSIG as usize])
+    }
+
+    pub fn get(&self, signo: u32) -> SigAction {
+        self.0[signo as usize - 1]
+    }
+
+    pub fn set(&mut self, signo: u32, action: SigAction) {
+        self.0[signo as usize - 1] = action;
+    }
+
+    /// Resets the caught signals to their default action, as `execve` does.
+    /// Ignored signals stay ignored.
+    pub fn reset_handlers(&mut self) {
+        for action in &mut self.0 {
+            if action.handler != SIG_IGN {
+                *action = SigAction::DEFAULT;
+            }
+        }
+    }
+}
+
+/// The pending signals of a thread or a process.
+pub struct PendingSignals {
+    /// The set of pending signals, which can be read without locking the
+    /// queue, e.g. in the condition of a wait.
+    set: AtomicU64,
+    queue: Mutex<VecDeque<SigInfo>>,
+}
+
+impl PendingSignals {
+    pub const fn new() -> Self {
+        Self {
+            set: AtomicU64::new(0),
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the set of pending signals.
+    pub fn set(&self) -> SigSet {
+        SigSet(self.set.load(Ordering::Acquire))
+    }
+
+    /// Adds a pending signal.
+    ///
+    /// A standard signal is pending at most once, so it is discarded if it is
+    /// already pending. Every instance of a real-time signal is queued.
+    pub fn push(&self, info: SigInfo) {
+        let signo = info.signo();
+        let mut queue = self.queue.lock();
+        if signo < SIGRTMIN && self.set().contains(signo) {
+            return;
+        }
+        queue.push_back(info);
+        self.set.fetch_or(SigSet::single(signo).0, Ordering::AcqRel);
+    }
+
+    /// Removes the first pending signal not in `blocked`.
+    pub fn pop(&self, blocked: SigSet) -> Option<SigInfo> {
+        if (self.set() & !blocked).is_empty() {
+            return None;
+        }
+        let mut queue = self.queue.lock();
+        let index = queue
+            .iter()
+            .position(|info| !blocked.contains(info.signo()))?;
+        let info = queue.remove(index).unwrap();
+        let signo = info.signo();
+        if !queue.iter().any(|info| info.signo() == signo) {
+            self.set
+                .fetch_and(!SigSet::single(signo).0, Ordering::AcqRel);
+        }
+        Some(info)
+    }
+
+    /// Discards the pending signals in `set`.
+    pub fn discard(&self, set: SigSet) {
+        let mut queue = self.queue.lock();
+        queue.retain(|info| !set.contains(info.signo()));
+        self.set.fetch_and(!set.0, Ordering::AcqRel);
+    }
+}
+
+/// The value of [`ThreadSignals::saved_mask`] when no mask is saved, which
+/// is not a valid mask as `SIGKILL` cannot be blocked.
+const NO_SAVED_MASK: u64 = u64::MAX;
+
+/// The signal state of a thread.
+pub struct ThreadSignals {
+    /// The blocked signals.
+    mask: AtomicU64,
+    /// The mask to restore once a signal is handled, replaced by a temporary
+    /// one by `rt_sigsuspend`, or [`NO_SAVED_MASK`].
+    saved_mask: AtomicU64,
+    /// The alternate signal stack.
+    stack: Mutex<SigStack>,
+    /// The signals sent to the thread.
+    pub pending: PendingSignals,
+}
+
+impl ThreadSignals {
+    pub const fn new() -> Self {
+        Self {
+            mask: AtomicU64::new(0),
+            saved_mask: AtomicU64::new(NO_SAVED_MASK),
+            stack: Mutex::new(SigStack::disabled()),
+            pending: PendingSignals::new(),
+        }
+    }
+
+    /// Returns the blocked signals.
+    pub fn mask(&self) -> SigSet {
+        SigSet(self.mask.load(Ordering::Acquire))
+    }
+
+    /// Sets the blocked signals, except the ones which cannot be blocked.
+    pub fn set_mask(&self, mask: SigSet) {
+        self.mask
+            .store((mask & !SigSet::UNCATCHABLE).0, Ordering::Release);
+    }
+
+    /// Blocks the signals in `mask` instead of the current ones until the
+    /// next signal is handled, as `rt_sigsuspend` does.
+    pub fn set_temporary_mask(&self, mask: SigSet) {
+        self.saved_mask.store(self.mask().0, Ordering::Release);
+        self.set_mask(mask);
+    }
+
+    fn has_saved_mask(&self) -> bool {
+        self.saved_mask.load(Ordering::Acquire) != NO_SAVED_MASK
+    }
+
+    /// Takes the mask replaced by [`ThreadSignals::set_temporary_mask`].
+    fn take_saved_mask(&self) -> Option<SigSet> {
+        let mask = self.saved_mask.swap(NO_SAVED_MASK, Ordering::AcqRel);
+        (mask != NO_SAVED_MASK).then_some(SigSet(mask))
+    }
+
+    /// Returns the alternate signal stack.
+    pub fn stack(&self) -> SigStack {
+        *self.stack.lock()
+    }
+
+    pub fn set_stack(&self, stack: SigStack) {
+        *self.stack.lock() = stack;
+    }
+}
+
+/// Discards the signals in `set` pending for `process` or any of its
+/// threads.
+pub fn discard_pending(process: &Process, set: SigSet) {
+    process.pending_signals.discard(set);
+    for thread in process.threads() {
+        thread.task_ext().signals.pending.discard(set);
+    }
+}
+
+/// Applies the effects of sending the signal `signo` to `process` which
+/// take place immediately, and returns whether the signal should be made
+/// pending.
+///
+/// `blocked` tells whether the signal is blocked by the target thread, in
+/// which case it is kept even if it is ignored, as the action may change
+/// before it is unblocked.
+fn prepare_signal(process: &Process, signo: u32, blocked: bool) -> bool {
+    if process.thread_count() == 0 {
+        // A zombie, or the init process standing for the kernel.
+        return false;
+    }
+    match signo {
+        SIGKILL => {
+            process.terminate(WaitStatus::Signaled(SIGKILL, false));
+            return false;
+        }
+        SIGCONT => {
+            discard_pending(process, SigSet::STOP);
+            process.resume();
+        }
+        _ if SigSet::STOP.contains(signo) => {
+            discard_pending(process, SigSet::single(SIGCONT));
+        }
+        _ => {}
+    }
+    blocked || !process.signal_actions().lock().get(signo).is_ignored(signo)
+}
+
+/// Sends a signal to `process`, to be handled by any of its threads which
+/// does not block it.
+pub fn send_process_signal(process: &Process, info: SigInfo) {
+    let signo = info.signo();
+    if !prepare_signal(process, signo, false) {
+        return;
+    }
+    process.pending_signals.push(info);
+    process.signalfd_wq.notify_all(false);
+    process.signalfd_poll.wake(PollEvents::READABLE);
+    let threads = process.threads();
+    if let Some(thread) = threads
+        .iter()
+        .find(|thread| !thread.task_ext().signals.mask().contains(signo))
+    {
+        thread.task_ext().interrupt(thread);
+    }
+}
+
+/// Sends a signal to the thread `thread`.
+pub fn send_thread_signal(thread: &AxTaskRef, info: SigInfo) {
+    let ext = thread.task_ext();
+    let signo = info.signo();
+    if !prepare_signal(&ext.process, signo, ext.signals.mask().contains(signo)) {
+        return;
+    }
+    ext.signals.pending.push(info);
+    ext.process.signalfd_wq.notify_all(false);
+    ext.process.signalfd_poll.wake(PollEvents::READABLE);
+    ext.interrupt(thread);
+}
+
+/// Removes a pending signal of the current thread in `set`, even if it is
+/// blocked, as `rt_sigtimedwait` does.
+pub fn dequeue_signal(set: SigSet) -> Option<SigInfo> {
+    let curr = current();
+    let ext = curr.task_ext();
+    ext.signals
+        .pending
+        .pop(!set)
+        .or_else(|| ext.process.pending_signals.pop(!set))
+}
+
+/// Returns the trap frame saved on the kernel stack when the current thread
+/// entered the kernel from user space.
+///
+/// # Safety
+///
+/// The current thread must be a user thread in a trap from user space, and
+/// the returned reference must not outlive the trap.
+unsafe fn current_trap_frame() -> &'static mut TrapFrame {
+    let kstack_top = current().kernel_stack_top().unwrap();
+    unsafe { &mut *((kstack_top.as_usize() - size_of::<TrapFrame>()) as *mut TrapFrame) }
+}
+
+/// Handles the pending signals of the current thread before it returns to
+/// user space from a system call returning `ret`.
+///
+/// If the system call was interrupted and is `restartable`, it is executed
+/// again after a handler with `SA_RESTART`, or after signals without
+/// handlers.
+///
+/// Returns the value to return from the system call handler.
+pub fn handle_syscall_signals(ret: isize, restartable: bool) -> isize {
+    let interrupted = restartable && ret == -(LinuxError::EINTR.code() as isize);
+    let curr = current();
+    let ext = curr.task_ext();
+    if !interrupted && !ext.is_interrupted() && !ext.signals.has_saved_mask() {
+        return ret;
+    }
+    let tf = unsafe { current_trap_frame() };
+    let mut ctx = arch::syscall_return_context(tf, ret);
+    let restart = interrupted.then(|| arch::syscall_restart_context(tf));
+    handle_signals(&mut ctx, restart);
+    *tf = ctx;
+    arch::prepare_syscall_return(tf)
+}
+
+/// Handles the pending signals of the current thread before it returns to
+/// user space from a trap other than a system call, e.g. a page fault.
+pub fn handle_trap_signals() {
+    if !current().task_ext().is_interrupted() {
+        return;
+    }
+    let tf = unsafe { current_trap_frame() };
+    let mut ctx = *tf;
+    handle_signals(&mut ctx, None);
+    *tf = ctx;
+}
+
+/// Terminates the current process, killed by `signo`.
+fn terminate(signo: u32) -> ! {
+    let status = WaitStatus::Signaled(signo, false);
+    current().task_ext().process.exit_group(status);
+    exit_current(status);
+}
+
+/// Handles the pending signals of the current thread, which is about to
+/// resume the user context `ctx`.
+///
+/// If a handler is invoked, `ctx` is changed to run the handler. If the
+/// resumed code is an interrupted system call which can be restarted,
+/// `restart` is the context executing it again.
+fn handle_signals(ctx: &mut TrapFrame, restart: Option<TrapFrame>) {
+    let curr = current();
+    let ext = curr.task_ext();
+    let process = &ext.process;
+    loop {
+        process.wait_while_stopped();
+        exit_if_killed();
+        let mask = ext.signals.mask();
+        let Some(info) = ext
+            .signals
+            .pending
+            .pop(mask)
+            .or_else(|| process.pending_signals.pop(mask))
+        else {
+            break;
+        };
+        let signo = info.signo();
+        let action = process.signal_actions().lock().get(signo);
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL => match default_action(signo) {
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+                DefaultAction::Stop => process.stop(signo),
+                DefaultAction::Terminate => terminate(signo),
+            },
+            _ => {
+                let resumed = match restart {
+                    Some(restart) if action.flags.contains(SigActionFlags::SA_RESTART) => restart,
+                    _ => *ctx,
+                };
+                *ctx = invoke_handler(&resumed, &info, &action);
+                return;
+            }
+        }
+    }
+    // No handler has run: the system call is restarted, and the mask
+    // replaced by `rt_sigsuspend` is restored right away.
+    if let Some(restart) = restart {
+        *ctx = restart;
+    }
+    if let Some(mask) = ext.signals.take_saved_mask() {
+        ext.signals.set_mask(mask);
+    }
+}
+
+/// Handles the signal of `info` caused by a fault of the current thread in
+/// user space, before it returns to the faulting instruction.
+///
+/// Like `force_sig` of Linux, a blocked or ignored fault signal is unblocked
+/// and reset to its default action, as the instruction would fault again
+/// forever otherwise.
+pub fn handle_fault_signal(info: SigInfo) {
+    let curr = current();
+    let ext = curr.task_ext();
+    let signo = info.signo();
+    let mask = ext.signals.mask();
+    let signal_actions = ext.process.signal_actions();
+    let mut actions = signal_actions.lock();
+    let mut action = actions.get(signo);
+    if action.handler == SIG_IGN || mask.contains(signo) {
+        action = SigAction::DEFAULT;
+        actions.set(signo, action);
+        ext.signals.set_mask(mask & !SigSet::single(signo));
+    }
+    drop(actions);
+    if action.handler == SIG_DFL {
+        // Fault signals terminate the process by default.
+        terminate(signo);
+    }
+    let tf = unsafe { current_trap_frame() };
+    *tf = invoke_handler(tf, &info, &action);
+}
+
+/// Pushes the signal frame saving the user context `ctx` on the user stack,
+/// and returns the user context running the handler of `action`.
+///
+/// The frame is pushed on the alternate signal stack instead if the action
+/// has `SA_ONSTACK` and the thread is not already running on it. The process
+/// is killed by `SIGSEGV` if the frame cannot be written.
+fn invoke_handler(ctx: &TrapFrame, info: &SigInfo, action: &SigAction) -> TrapFrame {
+    let curr = current();
+    let ext = curr.task_ext();
+    let signo = info.signo();
+    let mask = ext.signals.mask();
+    // The mask replaced by `rt_sigsuspend` is restored when the handler
+    // returns.
+    let saved_mask = ext.signals.take_saved_mask().unwrap_or(mask);
+    let restorer = if action.flags.contains(SigActionFlags::SA_RESTORER) && action.restorer != 0 {
+        action.restorer
+    } else {
+        config::SIGNAL_TRAMPOLINE
+    };
+    let user_sp = arch::stack_pointer(ctx);
+    let stack = ext.signals.stack();
+    let sp = if action.flags.contains(SigActionFlags::SA_ONSTACK)
+        && !stack.is_disabled()
+        && !stack.contains(user_sp)
+    {
+        stack.sp + stack.size
+    } else {
+        user_sp
+    };
+    let Ok(handler_ctx) = arch::setup_frame(
+        ctx,
+        sp,
+        stack.report(user_sp),
+        info,
+        action.handler,
+        restorer,
+        saved_mask,
+    ) else {
+        terminate(SIGSEGV);
+    };
+
+    let mut handler_mask = mask | action.mask;
+    if !action.flags.contains(SigActionFlags::SA_NODEFER) {
+        handler_mask = handler_mask | SigSet::single(signo);
+    }
+    ext.signals.set_mask(handler_mask);
+    if action.flags.contains(SigActionFlags::SA_RESETHAND) {
+        ext.process
+            .signal_actions()
+            .lock()
+            .set(signo, SigAction::DEFAULT);
+    }
+    handler_ctx
+}
+
+/// Gets the alternate signal stack of the current thread, and replaces it
+/// with `new` if it is not `None`, as `sigaltstack` does.
+pub fn sigaltstack(new: Option<SigStack>) -> LinuxResult<SigStack> {
+    let curr = current();
+    let signals = &curr.task_ext().signals;
+    let sp = arch::stack_pointer(unsafe { current_trap_frame() });
+    let old = signals.stack().report(sp);
+    if let Some(new) = new {
+        // The stack cannot change while a handler runs on it.
+        if old.flags == SS_ONSTACK {
+            return Err(LinuxError::EPERM);
+        }
+        let new = match new.flags {
+            SS_DISABLE => SigStack::disabled(),
+            0 | SS_ONSTACK if new.size < arch::MINSIGSTKSZ => return Err(LinuxError::ENOMEM),
+            0 | SS_ONSTACK => SigStack { flags: 0, ..new },
+            _ => return Err(LinuxError::EINVAL),
+        };
+        signals.set_stack(new);
+    }
+    Ok(old)
+}
+
+/// Restores the user context and the signal mask saved in the signal frame
+/// on the user stack, when a handler returns by `rt_sigreturn`.
+///
+/// Returns the value to return from the system call handler. The process is
+/// killed by `SIGSEGV` if the frame is invalid.
+pub fn sigreturn() -> isize {
+    let tf = unsafe { current_trap_frame() };
+    let Ok((ctx, mask)) = arch::restore_frame(tf) else {
+        terminate(SIGSEGV);
+    };
+    current().task_ext().signals.set_mask(mask);
+    *tf = ctx;
+    arch::prepare_syscall_return(tf)
+}
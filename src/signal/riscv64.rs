@@ -0,0 +1,269 @@
+//! Signal frames of riscv64.
+//!
+//! See <https://github.com/torvalds/linux/blob/master/arch/riscv/include/uapi/asm/sigcontext.h>
+
+use core::{
+    arch::asm,
+    mem::{offset_of, size_of},
+};
+
+use axerrno::LinuxResult;
+use axhal::arch::TrapFrame;
+
+use super::{SigInfo, SigSet, SigStack};
+use crate::uaccess::{get_user, put_user};
+
+/// `li a7, 139; ecall`, i.e. `rt_sigreturn()`.
+pub const TRAMPOLINE: &[u8] = &[0x93, 0x08, 0xb0, 0x08, 0x73, 0x00, 0x00, 0x00];
+
+/// The minimum size of an alternate signal stack.
+pub const MINSIGSTKSZ: usize = 2048;
+
+/// The `FS` field of `sstatus`, which is `Off` (0) if the floating-point
+/// registers are not accessible.
+const SSTATUS_FS: usize = 0b11 << 13;
+
+/// `struct __riscv_d_ext_state`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DExtState {
+    f: [u64; 32],
+    fcsr: u32,
+}
+
+impl DExtState {
+    /// Saves the floating-point state of user space, which the kernel does
+    /// not use.
+    fn save() -> Self {
+        let mut state = Self {
+            f: [0; 32],
+            fcsr: 0,
+        };
+        if fp_enabled() {
+            let fcsr: usize;
+            unsafe {
+                asm!(
+                    "fsd f0, 0({f})",
+                    "fsd f1, 8({f})",
+                    "fsd f2, 16({f})",
+                    "fsd f3, 24({f})",
+                    "fsd f4, 32({f})",
+                    "fsd f5, 40({f})",
+                    "fsd f6, 48({f})",
+                    "fsd f7, 56({f})",
+                    "fsd f8, 64({f})",
+                    "fsd f9, 72({f})",
+                    "fsd f10, 80({f})",
+                    "fsd f11, 88({f})",
+                    "fsd f12, 96({f})",
+                    "fsd f13, 104({f})",
+                    "fsd f14, 112({f})",
+                    "fsd f15, 120({f})",
+                    "fsd f16, 128({f})",
+                    "fsd f17, 136({f})",
+                    "fsd f18, 144({f})",
+                    "fsd f19, 152({f})",
+                    "fsd f20, 160({f})",
+                    "fsd f21, 168({f})",
+                    "fsd f22, 176({f})",
+                    "fsd f23, 184({f})",
+                    "fsd f24, 192({f})",
+                    "fsd f25, 200({f})",
+                    "fsd f26, 208({f})",
+                    "fsd f27, 216({f})",
+                    "fsd f28, 224({f})",
+                    "fsd f29, 232({f})",
+                    "fsd f30, 240({f})",
+                    "fsd f31, 248({f})",
+                    "frcsr {fcsr}",
+                    f = in(reg) state.f.as_mut_ptr(),
+                    fcsr = out(reg) fcsr,
+                    options(nostack),
+                )
+            };
+            state.fcsr = fcsr as u32;
+        }
+        state
+    }
+
+    /// Restores the floating-point state.
+    fn restore(&self) {
+        if !fp_enabled() {
+            return;
+        }
+        unsafe {
+            asm!(
+                "fld f0, 0({f})",
+                "fld f1, 8({f})",
+                "fld f2, 16({f})",
+                "fld f3, 24({f})",
+                "fld f4, 32({f})",
+                "fld f5, 40({f})",
+                "fld f6, 48({f})",
+                "fld f7, 56({f})",
+                "fld f8, 64({f})",
+                "fld f9, 72({f})",
+                "fld f10, 80({f})",
+                "fld f11, 88({f})",
+                "fld f12, 96({f})",
+                "fld f13, 104({f})",
+                "fld f14, 112({f})",
+                "fld f15, 120({f})",
+                "fld f16, 128({f})",
+                "fld f17, 136({f})",
+                "fld f18, 144({f})",
+                "fld f19, 152({f})",
+                "fld f20, 160({f})",
+                "fld f21, 168({f})",
+                "fld f22, 176({f})",
+                "fld f23, 184({f})",
+                "fld f24, 192({f})",
+                "fld f25, 200({f})",
+                "fld f26, 208({f})",
+                "fld f27, 216({f})",
+                "fld f28, 224({f})",
+                "fld f29, 232({f})",
+                "fld f30, 240({f})",
+                "fld f31, 248({f})",
+                "fscsr {fcsr}",
+                f = in(reg) self.f.as_ptr(),
+                fcsr = in(reg) self.fcsr as usize,
+                options(nostack, readonly),
+            )
+        };
+    }
+}
+
+/// Returns whether the floating-point registers are accessible. Otherwise
+/// user space cannot use them either.
+fn fp_enabled() -> bool {
+    let sstatus: usize;
+    unsafe { asm!("csrr {}, sstatus", out(reg) sstatus) };
+    sstatus & SSTATUS_FS != 0
+}
+
+/// `union __riscv_fp_state`, of which only the D extension is supported.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct FpState {
+    d: DExtState,
+    reserved: [u8; 528 - size_of::<DExtState>()],
+}
+
+/// `struct sigcontext`
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct MContext {
+    /// `pc` followed by the general registers from `ra` to `t6`.
+    regs: [usize; 32],
+    fpstate: FpState,
+}
+
+/// `struct ucontext`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UContext {
+    flags: u64,
+    link: u64,
+    stack: SigStack,
+    sigmask: SigSet,
+    unused: [u8; 120],
+    mcontext: MContext,
+}
+
+/// `struct rt_sigframe`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SigFrame {
+    info: SigInfo,
+    uc: UContext,
+}
+
+/// Returns the user stack pointer of `ctx`.
+pub fn stack_pointer(ctx: &TrapFrame) -> usize {
+    ctx.regs.sp
+}
+
+/// Returns the user context resumed after the system call of `tf` returns
+/// `ret`.
+pub fn syscall_return_context(tf: &TrapFrame, ret: isize) -> TrapFrame {
+    let mut ctx = *tf;
+    ctx.regs.a0 = ret as usize;
+    // Skip the `ecall` instruction.
+    ctx.sepc += 4;
+    ctx
+}
+
+/// Returns the user context executing the system call of `tf` again.
+pub fn syscall_restart_context(tf: &TrapFrame) -> TrapFrame {
+    // `sepc` still points to the `ecall` instruction, and `a0` holds the
+    // first argument.
+    *tf
+}
+
+/// Adjusts `tf`, holding the user context to resume, for the return from
+/// the system call handler, and returns the value to return from it.
+pub fn prepare_syscall_return(tf: &mut TrapFrame) -> isize {
+    // `sepc` is advanced past `ecall` after the handler returns.
+    tf.sepc -= 4;
+    tf.regs.a0 as isize
+}
+
+/// Pushes the signal frame saving `ctx` below `sp`, and returns the context
+/// calling `handler(signo, &info, &uc)`, which returns to `restorer`.
+pub fn setup_frame(
+    ctx: &TrapFrame,
+    sp: usize,
+    stack: SigStack,
+    info: &SigInfo,
+    handler: usize,
+    restorer: usize,
+    mask: SigSet,
+) -> LinuxResult<TrapFrame> {
+    let frame_addr = sp.wrapping_sub(size_of::<SigFrame>()) & !15;
+    // The general registers are laid out in the same order, with `zero`
+    // replaced by `pc`.
+    let mut regs: [usize; 32] = unsafe { core::mem::transmute(ctx.regs) };
+    regs[0] = ctx.sepc;
+    let frame = SigFrame {
+        info: *info,
+        uc: UContext {
+            flags: 0,
+            link: 0,
+            stack,
+            sigmask: mask,
+            unused: [0; 120],
+            mcontext: MContext {
+                regs,
+                fpstate: FpState {
+                    d: DExtState::save(),
+                    reserved: [0; 528 - size_of::<DExtState>()],
+                },
+            },
+        },
+    };
+    put_user(frame_addr as *mut SigFrame, frame)?;
+
+    let mut handler_ctx = *ctx;
+    handler_ctx.sepc = handler;
+    handler_ctx.regs.ra = restorer;
+    handler_ctx.regs.sp = frame_addr;
+    handler_ctx.regs.a0 = info.signo() as usize;
+    handler_ctx.regs.a1 = frame_addr + offset_of!(SigFrame, info);
+    handler_ctx.regs.a2 = frame_addr + offset_of!(SigFrame, uc);
+    Ok(handler_ctx)
+}
+
+/// Restores the user context and the signal mask saved in the signal frame,
+/// when `rt_sigreturn` is called with the trap frame `tf`.
+pub fn restore_frame(tf: &TrapFrame) -> LinuxResult<(TrapFrame, SigSet)> {
+    let frame_addr = tf.regs.sp;
+    let uc: UContext = get_user((frame_addr + offset_of!(SigFrame, uc)) as *const UContext)?;
+    uc.mcontext.fpstate.d.restore();
+    let mut regs = uc.mcontext.regs;
+    let mut ctx = *tf;
+    ctx.sepc = regs[0];
+    regs[0] = 0;
+    ctx.regs = unsafe { core::mem::transmute::<[usize; 32], _>(regs) };
+    Ok((ctx, uc.sigmask))
+}
@@ -0,0 +1,200 @@
+//! Receiving signals by reading a file (`signalfd`).
+
+use alloc::sync::Arc;
+use core::{
+    any::Any,
+    mem::size_of,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+
+use super::{dequeue_signal, SigInfo, SigSet, SIGBUS, SIGCHLD, SIGFPE, SIGILL, SIGSEGV, SIGTRAP};
+use crate::{
+    file::{FileLike, Kstat, PollEvents, PollSet, StatusFlags},
+    fs::OpenFlags,
+    task::wait_interruptible,
+};
+
+/// The `si_code` of the signals sent by the kernel, above which the codes
+/// are sent by user space.
+const SI_KERNEL: i32 = 0x80;
+
+/// `struct signalfd_siginfo`, the record read for each signal.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalfdSigInfo {
+    signo: u32,
+    errno: i32,
+    code: i32,
+    pid: u32,
+    uid: u32,
+    fd: i32,
+    tid: u32,
+    band: u32,
+    overrun: u32,
+    trapno: u32,
+    status: i32,
+    int: i32,
+    ptr: u64,
+    utime: u64,
+    stime: u64,
+    addr: u64,
+    addr_lsb: u16,
+    _pad2: u16,
+    syscall: i32,
+    call_addr: u64,
+    arch: u32,
+    _pad: [u8; 28],
+}
+
+impl From<&SigInfo> for SignalfdSigInfo {
+    /// Picks the fields of the union of `siginfo_t` which are valid for the
+    /// signal and its code.
+    fn from(info: &SigInfo) -> Self {
+        let mut ssi: Self = unsafe { core::mem::zeroed() };
+        ssi.signo = info.signo();
+        ssi.errno = info.errno;
+        ssi.code = info.code;
+        let fields = &info.fields;
+        let from_kernel = info.code > 0 && info.code < SI_KERNEL;
+        match info.signo() {
+            SIGSEGV | SIGBUS | SIGILL | SIGFPE | SIGTRAP if from_kernel => {
+                ssi.addr = fields[0];
+            }
+            SIGCHLD if from_kernel => {
+                ssi.pid = fields[0] as u32;
+                ssi.uid = (fields[0] >> 32) as u32;
+                ssi.status = fields[1] as i32;
+                ssi.utime = fields[2];
+                ssi.stime = fields[3];
+            }
+            _ => {
+                ssi.pid = fields[0] as u32;
+                ssi.uid = (fields[0] >> 32) as u32;
+                // The value of a queued signal.
+                if info.code < 0 {
+                    ssi.ptr = fields[1];
+                    ssi.int = fields[1] as i32;
+                }
+            }
+        }
+        ssi
+    }
+}
+
+/// A file reading the pending signals in its mask, which are removed as if
+/// they were handled. The signals are usually blocked so that they are not
+/// handled otherwise.
+///
+/// As on Linux, a thread reads its own signals and the ones of its process,
+/// whichever process created the file.
+pub struct SignalFd {
+    mask: AtomicU64,
+    flags: StatusFlags,
+}
+
+impl SignalFd {
+    pub fn new(mask: SigSet, nonblocking: bool) -> Self {
+        let mut flags = OpenFlags::O_RDWR;
+        flags.set(OpenFlags::O_NONBLOCK, nonblocking);
+        Self {
+            mask: AtomicU64::new(mask.0),
+            flags: StatusFlags::new(flags),
+        }
+    }
+
+    /// Replaces the signals read from the file.
+    pub fn set_mask(&self, mask: SigSet) {
+        self.mask.store(mask.0, Ordering::Release);
+    }
+
+    fn mask(&self) -> SigSet {
+        SigSet(self.mask.load(Ordering::Acquire)) & !SigSet::UNCATCHABLE
+    }
+
+    /// Reads as many pending signals as fit in `buf`, blocking until there is
+    /// one unless `nonblocking` is set.
+    fn do_read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        const SSI_SIZE: usize = size_of::<SignalfdSigInfo>();
+        if buf.len() < SSI_SIZE {
+            return Err(LinuxError::EINVAL);
+        }
+        let mask = self.mask();
+        let mut len = 0;
+        while len + SSI_SIZE <= buf.len() {
+            let Some(info) = dequeue_signal(mask) else {
+                if len > 0 {
+                    break;
+                }
+                if nonblocking {
+                    return Err(LinuxError::EAGAIN);
+                }
+                let curr = current();
+                let ext = curr.task_ext();
+                wait_interruptible(&ext.process.signalfd_wq, || {
+                    let pending = ext.signals.pending.set() | ext.process.pending_signals.set();
+                    !(pending & mask).is_empty()
+                })?;
+                continue;
+            };
+            let ssi = SignalfdSigInfo::from(&info);
+            let bytes: [u8; SSI_SIZE] = unsafe { core::mem::transmute(ssi) };
+            buf[len..len + SSI_SIZE].copy_from_slice(&bytes);
+            len += SSI_SIZE;
+        }
+        Ok(len)
+    }
+}
+
+impl FileLike for SignalFd {
+    /// Reads as many pending signals as fit in `buf`, blocking until there is
+    /// one unless the file is non-blocking.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, self.flags.nonblocking())
+    }
+
+    fn read_nowait(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, true)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        // An anonymous inode.
+        Ok(Kstat {
+            mode: 0o600,
+            nlink: 1,
+            blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    /// The file is readable while the current thread has one of its signals
+    /// pending.
+    fn poll(&self) -> PollEvents {
+        let curr = current();
+        let ext = curr.task_ext();
+        let pending = ext.signals.pending.set() | ext.process.pending_signals.set();
+        if (pending & self.mask()).is_empty() {
+            PollEvents::empty()
+        } else {
+            PollEvents::READABLE
+        }
+    }
+
+    fn poll_set(&self) -> Option<Arc<PollSet>> {
+        Some(current().task_ext().process.signalfd_poll.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
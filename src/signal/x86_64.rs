@@ -0,0 +1,249 @@
+//! Signal frames of x86_64.
+//!
+//! See <https://github.com/torvalds/linux/blob/master/arch/x86/include/uapi/asm/sigcontext.h>
+
+use core::{
+    arch::asm,
+    mem::{offset_of, size_of},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+
+use super::{SigInfo, SigSet, SigStack};
+use crate::uaccess::{get_user, put_user};
+
+/// `mov $15, %eax; syscall`, i.e. `rt_sigreturn()`.
+pub const TRAMPOLINE: &[u8] = &[0xb8, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05];
+
+/// The minimum size of an alternate signal stack.
+pub const MINSIGSTKSZ: usize = 2048;
+
+/// The area below the stack pointer which may be used by leaf functions,
+/// and so must be skipped by the signal frame.
+const RED_ZONE_SIZE: usize = 128;
+
+/// The trap flag (single-step) and the direction flag of `rflags`.
+const FLAGS_TF: u64 = 1 << 8;
+const FLAGS_DF: u64 = 1 << 10;
+/// The flags of `rflags` which user space may change by `rt_sigreturn`:
+/// CF, PF, AF, ZF, SF, TF, DF, OF, RF and AC.
+const USER_FLAGS: u64 = 0x5_0dd5;
+
+/// The reserved bits of MXCSR, which raise a fault if set by `fxrstor`.
+const MXCSR_RESERVED: u32 = 0xffff_0000;
+
+/// `struct sigcontext`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MContext {
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rdi: u64,
+    rsi: u64,
+    rbp: u64,
+    rbx: u64,
+    rdx: u64,
+    rax: u64,
+    rcx: u64,
+    rsp: u64,
+    rip: u64,
+    eflags: u64,
+    cs: u16,
+    gs: u16,
+    fs: u16,
+    ss: u16,
+    err: u64,
+    trapno: u64,
+    oldmask: u64,
+    cr2: u64,
+    /// The address of the [`FpState`], or 0 if not saved.
+    fpstate: u64,
+    reserved: [u64; 8],
+}
+
+/// `struct ucontext`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UContext {
+    flags: u64,
+    link: u64,
+    stack: SigStack,
+    mcontext: MContext,
+    sigmask: SigSet,
+}
+
+/// `struct rt_sigframe`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SigFrame {
+    /// The return address of the handler.
+    pretcode: u64,
+    uc: UContext,
+    info: SigInfo,
+}
+
+/// The x87, MMX and SSE state in the format of `fxsave`.
+#[repr(C, align(64))]
+#[derive(Clone, Copy)]
+struct FpState([u8; 512]);
+
+impl FpState {
+    /// Saves the floating-point state of user space, which the kernel does
+    /// not use.
+    fn save() -> Self {
+        let mut state = Self([0; 512]);
+        unsafe { asm!("fxsave64 [{}]", in(reg) &mut state) };
+        state
+    }
+
+    /// Restores the floating-point state, after clearing the reserved bits
+    /// which would make `fxrstor` fault.
+    fn restore(mut self) {
+        let mxcsr = u32::from_le_bytes(self.0[24..28].try_into().unwrap());
+        self.0[24..28].copy_from_slice(&(mxcsr & !MXCSR_RESERVED).to_le_bytes());
+        unsafe { asm!("fxrstor64 [{}]", in(reg) &self) };
+    }
+}
+
+/// Returns the user stack pointer of `ctx`.
+pub fn stack_pointer(ctx: &TrapFrame) -> usize {
+    ctx.rsp as usize
+}
+
+/// Returns the user context resumed after the system call of `tf` returns
+/// `ret`.
+pub fn syscall_return_context(tf: &TrapFrame, ret: isize) -> TrapFrame {
+    let mut ctx = *tf;
+    ctx.rax = ret as u64;
+    ctx
+}
+
+/// Returns the user context executing the system call of `tf` again.
+pub fn syscall_restart_context(tf: &TrapFrame) -> TrapFrame {
+    let mut ctx = *tf;
+    // Back to the `syscall` instruction, with the syscall number still in
+    // `rax`.
+    ctx.rip -= 2;
+    ctx
+}
+
+/// Adjusts `tf`, holding the user context to resume, for the return from
+/// the system call handler, and returns the value to return from it.
+pub fn prepare_syscall_return(tf: &mut TrapFrame) -> isize {
+    tf.rax as isize
+}
+
+/// Pushes the signal frame saving `ctx` below `sp`, and returns the context
+/// calling `handler(signo, &info, &uc)`, which returns to `restorer`.
+pub fn setup_frame(
+    ctx: &TrapFrame,
+    sp: usize,
+    stack: SigStack,
+    info: &SigInfo,
+    handler: usize,
+    restorer: usize,
+    mask: SigSet,
+) -> LinuxResult<TrapFrame> {
+    let fpstate_addr = sp.wrapping_sub(RED_ZONE_SIZE + size_of::<FpState>()) & !63;
+    put_user(fpstate_addr as *mut FpState, FpState::save())?;
+    // The stack is aligned as right after a call: `rsp + 8` is 16-aligned.
+    let frame_addr = (fpstate_addr.wrapping_sub(size_of::<SigFrame>()) & !15).wrapping_sub(8);
+    let frame = SigFrame {
+        pretcode: restorer as u64,
+        uc: UContext {
+            flags: 0,
+            link: 0,
+            stack,
+            mcontext: MContext {
+                r8: ctx.r8,
+                r9: ctx.r9,
+                r10: ctx.r10,
+                r11: ctx.r11,
+                r12: ctx.r12,
+                r13: ctx.r13,
+                r14: ctx.r14,
+                r15: ctx.r15,
+                rdi: ctx.rdi,
+                rsi: ctx.rsi,
+                rbp: ctx.rbp,
+                rbx: ctx.rbx,
+                rdx: ctx.rdx,
+                rax: ctx.rax,
+                rcx: ctx.rcx,
+                rsp: ctx.rsp,
+                rip: ctx.rip,
+                eflags: ctx.rflags,
+                cs: ctx.cs as u16,
+                gs: 0,
+                fs: 0,
+                ss: ctx.ss as u16,
+                err: 0,
+                trapno: 0,
+                oldmask: mask.0,
+                cr2: 0,
+                fpstate: fpstate_addr as u64,
+                reserved: [0; 8],
+            },
+            sigmask: mask,
+        },
+        info: *info,
+    };
+    put_user(frame_addr as *mut SigFrame, frame)?;
+
+    let mut handler_ctx = *ctx;
+    handler_ctx.rip = handler as u64;
+    handler_ctx.rsp = frame_addr as u64;
+    handler_ctx.rdi = info.signo() as u64;
+    handler_ctx.rsi = (frame_addr + offset_of!(SigFrame, info)) as u64;
+    handler_ctx.rdx = (frame_addr + offset_of!(SigFrame, uc)) as u64;
+    handler_ctx.rax = 0;
+    handler_ctx.rflags &= !(FLAGS_TF | FLAGS_DF);
+    Ok(handler_ctx)
+}
+
+/// Restores the user context and the signal mask saved in the signal frame,
+/// when `rt_sigreturn` is called with the trap frame `tf`.
+///
+/// The system call returns by `sysretq`, which clobbers `rcx` and `r11`, so
+/// they are not restored if the frame was pushed on a fault.
+pub fn restore_frame(tf: &TrapFrame) -> LinuxResult<(TrapFrame, SigSet)> {
+    // `pretcode` has been popped by the return of the handler.
+    let frame_addr = (tf.rsp as usize).wrapping_sub(8);
+    let uc: UContext = get_user((frame_addr + offset_of!(SigFrame, uc)) as *const UContext)?;
+    let mc = &uc.mcontext;
+    // `sysretq` to a non-canonical address faults in the kernel.
+    if mc.rip >= 1 << 47 {
+        return Err(LinuxError::EFAULT);
+    }
+    if mc.fpstate != 0 {
+        get_user(mc.fpstate as *const FpState)?.restore();
+    }
+
+    let mut ctx = *tf;
+    ctx.r8 = mc.r8;
+    ctx.r9 = mc.r9;
+    ctx.r10 = mc.r10;
+    ctx.r11 = mc.r11;
+    ctx.r12 = mc.r12;
+    ctx.r13 = mc.r13;
+    ctx.r14 = mc.r14;
+    ctx.r15 = mc.r15;
+    ctx.rdi = mc.rdi;
+    ctx.rsi = mc.rsi;
+    ctx.rbp = mc.rbp;
+    ctx.rbx = mc.rbx;
+    ctx.rdx = mc.rdx;
+    ctx.rax = mc.rax;
+    ctx.rcx = mc.rcx;
+    ctx.rsp = mc.rsp;
+    ctx.rip = mc.rip;
+    ctx.rflags = (tf.rflags & !USER_FLAGS) | (mc.eflags & USER_FLAGS);
+    Ok((ctx, uc.sigmask))
+}
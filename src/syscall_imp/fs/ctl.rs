@@ -1,18 +1,43 @@
-use core::ffi::c_void;
+use axtask::{current, TaskExtRef};
 
-use crate::syscall_body;
+use crate::{file::get_file, fs::OpenFlags, syscall_body, uaccess::get_user};
+
+/// The `ioctl` commands of all files
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/ioctls.h>
+const FIONBIO: u32 = 0x5421;
+const FIONCLEX: u32 = 0x5450;
+const FIOCLEX: u32 = 0x5451;
 
 /// The ioctl() system call manipulates the underlying device parameters
 /// of special files.
 ///
+/// The commands of all files are handled here, and the others by the file
+/// itself, which fails with `ENOTTY` if it does not know them.
+///
 /// # Arguments
 /// * `fd` - The file descriptor
 /// * `op` - The request code. It is of type unsigned long in glibc and BSD,
-/// and of type int in musl and other UNIX systems.
-/// * `argp` - The argument to the request. It is a pointer to a memory location
-pub(crate) fn sys_ioctl(_fd: i32, _op: usize, _argp: *mut c_void) -> i32 {
+///   and of type int in musl and other UNIX systems.
+/// * `arg` - The argument to the request, usually a pointer
+pub(crate) fn sys_ioctl(fd: i32, op: usize, arg: usize) -> isize {
     syscall_body!(sys_ioctl, {
-        warn!("Unimplemented syscall: SYS_IOCTL");
-        Ok(0)
+        // The request codes fit in 32 bits, whatever their type.
+        let op = op as u32;
+        let file = get_file(fd)?;
+        match op {
+            FIONBIO => {
+                let mut flags = file.status_flags().get();
+                flags.set(OpenFlags::O_NONBLOCK, get_user(arg as *const i32)? != 0);
+                file.status_flags().set(flags);
+                Ok(0)
+            }
+            FIOCLEX | FIONCLEX => {
+                let fd_table = current().task_ext().process.fd_table();
+                fd_table.set_cloexec(fd, op == FIOCLEX)?;
+                Ok(0)
+            }
+            _ => file.ioctl(op, arg),
+        }
     })
 }
@@ -0,0 +1,90 @@
+use alloc::string::String;
+use core::ffi::{c_char, c_void};
+
+use axerrno::LinuxError;
+
+use crate::{
+    file::{get_file, S_IFDIR, S_IFMT},
+    fs::{self, Directory},
+    syscall_body,
+    uaccess::{copy_to_user, get_user_str, read_to_user},
+};
+
+/// List the entries of the directory `fd` into `dirp` as `struct
+/// linux_dirent64`, from the offset of `fd` on, within `count` bytes.
+pub(crate) fn sys_getdents64(fd: i32, dirp: *mut c_void, count: usize) -> isize {
+    syscall_body!(sys_getdents64, {
+        let file = get_file(fd)?;
+        let dir = file
+            .as_any()
+            .downcast_ref::<Directory>()
+            .ok_or(LinuxError::ENOTDIR)?;
+        Ok(read_to_user(dirp as *mut u8, count, |buf| dir.read_entries(buf))? as isize)
+    })
+}
+
+/// Create a directory at `path`, relative to the directory `dirfd` if it is
+/// relative. The `mode` is not stored.
+pub(crate) fn sys_mkdirat(dirfd: i32, path: *const c_char, _mode: u32) -> isize {
+    syscall_body!(sys_mkdirat, {
+        let path = get_user_str(path)?;
+        fs::mkdir(&fs::resolve_path(dirfd, &path, false)?)?;
+        Ok(0)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_mkdir(path: *const c_char, mode: u32) -> isize {
+    sys_mkdirat(fs::AT_FDCWD, path, mode)
+}
+
+/// Change the current directory of the calling process to `path`.
+pub(crate) fn sys_chdir(path: *const c_char) -> isize {
+    syscall_body!(sys_chdir, {
+        let path = fs::resolve_dir(&get_user_str(path)?)?;
+        fs::fs_context().set_cwd(path);
+        Ok(0)
+    })
+}
+
+/// Change the current directory of the calling process to the directory
+/// `fd`.
+pub(crate) fn sys_fchdir(fd: i32) -> isize {
+    syscall_body!(sys_fchdir, {
+        let file = get_file(fd)?;
+        if file.stat()?.mode & S_IFMT != S_IFDIR {
+            return Err(LinuxError::ENOTDIR);
+        }
+        let path = file.path().ok_or(LinuxError::ENOTDIR)?;
+        fs::fs_context().set_cwd(String::from(path));
+        Ok(0)
+    })
+}
+
+/// Get the current directory of the calling process, relative to its root
+/// directory, as a null-terminated string in `buf` of `size` bytes.
+///
+/// Returns the length of the string including the null byte.
+pub(crate) fn sys_getcwd(buf: *mut c_char, size: usize) -> isize {
+    syscall_body!(sys_getcwd, {
+        let cwd = fs::fs_context().cwd_in_root();
+        let len = cwd.len() + 1;
+        if size < len {
+            return Err(LinuxError::ERANGE);
+        }
+        let mut cwd = cwd.into_bytes();
+        cwd.push(0);
+        copy_to_user(buf as *mut u8, &cwd)?;
+        Ok(len as isize)
+    })
+}
+
+/// Change the root directory of the calling process to `path`, below which
+/// its paths are resolved. The current directory is not changed.
+pub(crate) fn sys_chroot(path: *const c_char) -> isize {
+    syscall_body!(sys_chroot, {
+        let path = fs::resolve_dir(&get_user_str(path)?)?;
+        fs::fs_context().set_root(path);
+        Ok(0)
+    })
+}
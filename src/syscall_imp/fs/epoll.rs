@@ -0,0 +1,139 @@
+use alloc::sync::Arc;
+use core::{mem::size_of, time::Duration};
+
+use axerrno::LinuxError;
+use axhal::paging::MappingFlags;
+
+use crate::{
+    file::{add_file, get_file, Epoll, EpollEvent},
+    fs::OpenFlags,
+    signal::SigSet,
+    syscall_body,
+    syscall_imp::signal::set_temporary_mask,
+    uaccess::{check_region, get_user, put_user},
+};
+
+/// The operations of `epoll_ctl`
+const EPOLL_CTL_ADD: i32 = 1;
+const EPOLL_CTL_DEL: i32 = 2;
+const EPOLL_CTL_MOD: i32 = 3;
+
+/// The highest number of events returned by `epoll_wait`, as on Linux.
+const EP_MAX_EVENTS: i32 = i32::MAX / size_of::<EpollEvent>() as i32;
+
+/// Create an epoll instance.
+///
+/// # Arguments
+/// * `flags` - `EPOLL_CLOEXEC`, the same as `O_CLOEXEC`, or 0
+pub(crate) fn sys_epoll_create1(flags: u32) -> isize {
+    syscall_body!(sys_epoll_create1, {
+        let flags = OpenFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
+        if !OpenFlags::O_CLOEXEC.contains(flags) {
+            return Err(LinuxError::EINVAL);
+        }
+        add_file(Arc::new(Epoll::new()), flags.contains(OpenFlags::O_CLOEXEC))
+    })
+}
+
+/// Like `epoll_create1` without flags. `size` is ignored but must be
+/// positive.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_epoll_create(size: i32) -> isize {
+    syscall_body!(sys_epoll_create, {
+        if size <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        add_file(Arc::new(Epoll::new()), false)
+    })
+}
+
+/// Add, change or remove the entry of an epoll instance watching a file.
+///
+/// # Arguments
+/// * `epfd` - The epoll instance
+/// * `op` - `EPOLL_CTL_ADD`, `EPOLL_CTL_MOD` or `EPOLL_CTL_DEL`
+/// * `fd` - The watched file, which cannot be a regular file or directory
+/// * `event` - The events to report and the data to report them with,
+///   ignored by `EPOLL_CTL_DEL`
+pub(crate) fn sys_epoll_ctl(epfd: i32, op: i32, fd: i32, event: *const EpollEvent) -> isize {
+    syscall_body!(sys_epoll_ctl, {
+        let event = if op == EPOLL_CTL_DEL {
+            None
+        } else {
+            Some(get_user(event)?)
+        };
+        let epoll_file = get_file(epfd)?;
+        let file = get_file(fd)?;
+        let epoll = epoll_file
+            .as_any()
+            .downcast_ref::<Epoll>()
+            .ok_or(LinuxError::EINVAL)?;
+        if fd == epfd {
+            return Err(LinuxError::EINVAL);
+        }
+        match (op, event) {
+            (EPOLL_CTL_ADD, Some(event)) => epoll.add(fd, &file, event)?,
+            (EPOLL_CTL_MOD, Some(event)) => epoll.modify(fd, &file, event)?,
+            (EPOLL_CTL_DEL, _) => epoll.delete(fd, &file)?,
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(0)
+    })
+}
+
+/// Wait for the events of the files watched by an epoll instance.
+///
+/// # Arguments
+/// * `epfd` - The epoll instance
+/// * `events` - Where to store the events
+/// * `maxevents` - The highest number of events to store, which must be
+///   positive
+/// * `timeout` - The longest time to wait in milliseconds, or -1 to wait
+///   indefinitely
+/// * `sigmask` - The signals to block while waiting, may be null to keep the
+///   blocked signals
+/// * `sigsetsize` - The size of `sigset_t`, which must be 8
+///
+/// Returns the number of events stored, or 0 if the timeout expired.
+pub(crate) fn sys_epoll_pwait(
+    epfd: i32,
+    events: *mut EpollEvent,
+    maxevents: i32,
+    timeout: i32,
+    sigmask: *const SigSet,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_epoll_pwait, {
+        if !(1..=EP_MAX_EVENTS).contains(&maxevents) {
+            return Err(LinuxError::EINVAL);
+        }
+        check_region(
+            events as usize,
+            maxevents as usize * size_of::<EpollEvent>(),
+            MappingFlags::WRITE,
+        )?;
+        let file = get_file(epfd)?;
+        let epoll = file
+            .as_any()
+            .downcast_ref::<Epoll>()
+            .ok_or(LinuxError::EINVAL)?;
+        set_temporary_mask(sigmask, sigsetsize)?;
+        let timeout = u64::try_from(timeout).ok().map(Duration::from_millis);
+        let ready = epoll.wait(maxevents as usize, timeout)?;
+        for (i, event) in ready.iter().enumerate() {
+            put_user(events.wrapping_add(i), *event)?;
+        }
+        Ok(ready.len() as isize)
+    })
+}
+
+/// Like `epoll_pwait` without signal mask.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_epoll_wait(
+    epfd: i32,
+    events: *mut EpollEvent,
+    maxevents: i32,
+    timeout: i32,
+) -> isize {
+    sys_epoll_pwait(epfd, events, maxevents, timeout, core::ptr::null(), 0)
+}
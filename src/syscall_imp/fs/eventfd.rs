@@ -0,0 +1,40 @@
+use alloc::sync::Arc;
+
+use axerrno::LinuxError;
+
+use crate::{
+    file::{add_file, EventFd},
+    syscall_body,
+};
+
+/// The flags of `eventfd2`, `EFD_NONBLOCK` and `EFD_CLOEXEC` being the same
+/// as `O_NONBLOCK` and `O_CLOEXEC`.
+const EFD_SEMAPHORE: u32 = 1;
+const EFD_NONBLOCK: u32 = 0o4000;
+const EFD_CLOEXEC: u32 = 0o200_0000;
+
+/// Create a file holding an event counter.
+///
+/// # Arguments
+/// * `initval` - The initial value of the counter
+/// * `flags` - `EFD_SEMAPHORE` for reads to decrement the counter rather
+///   than reset it, `EFD_NONBLOCK` and `EFD_CLOEXEC`
+pub(crate) fn sys_eventfd2(initval: u32, flags: u32) -> isize {
+    syscall_body!(sys_eventfd2, {
+        if flags & !(EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let eventfd = EventFd::new(
+            initval as u64,
+            flags & EFD_SEMAPHORE != 0,
+            flags & EFD_NONBLOCK != 0,
+        );
+        Ok(add_file(Arc::new(eventfd), flags & EFD_CLOEXEC != 0)? as isize)
+    })
+}
+
+/// Like `eventfd2` without flags.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_eventfd(initval: u32) -> isize {
+    sys_eventfd2(initval, 0)
+}
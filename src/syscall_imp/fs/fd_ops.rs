@@ -0,0 +1,177 @@
+use core::ffi::c_char;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    file::{add_file, close_file, get_file, nofile_limit, PipeEnd, SeekFrom},
+    fs::{self, OpenFlags},
+    syscall_body,
+    uaccess::get_user_str,
+};
+
+const SEEK_SET: u32 = 0;
+const SEEK_CUR: u32 = 1;
+const SEEK_END: u32 = 2;
+
+/// The `fcntl` commands
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/fcntl.h>
+const F_DUPFD: u32 = 0;
+const F_GETFD: u32 = 1;
+const F_SETFD: u32 = 2;
+const F_GETFL: u32 = 3;
+const F_SETFL: u32 = 4;
+const F_DUPFD_CLOEXEC: u32 = 1030;
+const F_SETPIPE_SZ: u32 = 1031;
+const F_GETPIPE_SZ: u32 = 1032;
+
+/// The descriptor flag of `F_GETFD` and `F_SETFD`.
+const FD_CLOEXEC: usize = 1;
+
+/// Open the file at `path`, relative to the directory `dirfd` if it is
+/// relative.
+///
+/// # Arguments
+/// * `dirfd` - The directory, or `AT_FDCWD` for the current directory
+/// * `path` - The path of the file
+/// * `flags` - The access mode and the `O_*` flags
+/// * `mode` - The permissions of a new file, which are not stored
+pub(crate) fn sys_openat(dirfd: i32, path: *const c_char, flags: u32, _mode: u32) -> isize {
+    syscall_body!(sys_openat, {
+        let path = get_user_str(path)?;
+        let mut flags = OpenFlags::from_bits_truncate(flags);
+        // A trailing slash requires a directory.
+        if path.ends_with('/') {
+            flags |= OpenFlags::O_DIRECTORY;
+        }
+        // A symbolic link is not followed to create a file with `O_EXCL`.
+        let follow = !flags.contains(OpenFlags::O_NOFOLLOW)
+            && !flags.contains(OpenFlags::O_CREAT | OpenFlags::O_EXCL);
+        let file = fs::open(&fs::resolve_path(dirfd, &path, follow)?, flags)?;
+        Ok(add_file(file, flags.contains(OpenFlags::O_CLOEXEC))? as isize)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_open(path: *const c_char, flags: u32, mode: u32) -> isize {
+    sys_openat(fs::AT_FDCWD, path, flags, mode)
+}
+
+/// Close the file descriptor `fd`.
+pub(crate) fn sys_close(fd: i32) -> isize {
+    syscall_body!(sys_close, {
+        close_file(fd)?;
+        Ok(0)
+    })
+}
+
+/// Move the offset of the file `fd` to `offset`, relative to the start of
+/// the file, its current offset or its end according to `whence`.
+pub(crate) fn sys_lseek(fd: i32, offset: i64, whence: u32) -> isize {
+    syscall_body!(sys_lseek, {
+        let file = get_file(fd)?;
+        let pos = match whence {
+            SEEK_SET => SeekFrom::Start(u64::try_from(offset).map_err(|_| LinuxError::EINVAL)?),
+            SEEK_CUR => SeekFrom::Current(offset),
+            SEEK_END => SeekFrom::End(offset),
+            _ => return Err(LinuxError::EINVAL),
+        };
+        Ok(file.seek(pos)? as isize)
+    })
+}
+
+/// Duplicates the descriptor `fd` to the lowest free descriptor not lower
+/// than `min`.
+fn dup_from(fd: i32, min: usize, cloexec: bool) -> LinuxResult<isize> {
+    let limit = nofile_limit();
+    if min >= limit {
+        return Err(LinuxError::EINVAL);
+    }
+    let fd_table = current().task_ext().process.fd_table();
+    let file = fd_table.get(fd)?;
+    Ok(fd_table.add(file, cloexec, min, limit)? as isize)
+}
+
+/// Duplicate the descriptor `oldfd` to the lowest free descriptor, without
+/// `FD_CLOEXEC`.
+pub(crate) fn sys_dup(oldfd: i32) -> isize {
+    syscall_body!(sys_dup, dup_from(oldfd, 0, false))
+}
+
+/// Duplicate the descriptor `oldfd` to `newfd`, closing the file at `newfd`
+/// first if any.
+///
+/// # Arguments
+/// * `oldfd` - The descriptor to duplicate
+/// * `newfd` - The new descriptor, which must differ from `oldfd`
+/// * `flags` - `O_CLOEXEC` to set `FD_CLOEXEC` on `newfd`
+pub(crate) fn sys_dup3(oldfd: i32, newfd: i32, flags: u32) -> isize {
+    syscall_body!(sys_dup3, {
+        if flags & !OpenFlags::O_CLOEXEC.bits() != 0 || oldfd == newfd {
+            return Err(LinuxError::EINVAL);
+        }
+        let fd_table = current().task_ext().process.fd_table();
+        let file = fd_table.get(oldfd)?;
+        fd_table.set(newfd, file, flags != 0, nofile_limit())?;
+        Ok(newfd as isize)
+    })
+}
+
+/// Like [`sys_dup3`] without flags, but `oldfd` may be `newfd`, which is
+/// then only checked.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_dup2(oldfd: i32, newfd: i32) -> isize {
+    if oldfd == newfd {
+        return syscall_body!(sys_dup2, get_file(oldfd).map(|_| newfd as isize));
+    }
+    sys_dup3(oldfd, newfd, 0)
+}
+
+/// Manipulate the file descriptor `fd` with the command `cmd`.
+///
+/// # Arguments
+/// * `fd` - The file descriptor
+/// * `cmd` - `F_DUPFD`, `F_DUPFD_CLOEXEC`, `F_GETFD`, `F_SETFD`, `F_GETFL`,
+///   `F_SETFL`, `F_GETPIPE_SZ` or `F_SETPIPE_SZ`
+/// * `arg` - The argument of the command, if it has one
+pub(crate) fn sys_fcntl(fd: i32, cmd: u32, arg: usize) -> isize {
+    syscall_body!(sys_fcntl, {
+        match cmd {
+            F_DUPFD | F_DUPFD_CLOEXEC => dup_from(fd, arg, cmd == F_DUPFD_CLOEXEC),
+            F_GETFD => {
+                let fd_table = current().task_ext().process.fd_table();
+                let flags = if fd_table.cloexec(fd)? { FD_CLOEXEC } else { 0 };
+                Ok(flags as isize)
+            }
+            F_SETFD => {
+                let fd_table = current().task_ext().process.fd_table();
+                fd_table.set_cloexec(fd, arg & FD_CLOEXEC != 0)?;
+                Ok(0)
+            }
+            F_GETFL => Ok(get_file(fd)?.status_flags().get().bits() as isize),
+            F_SETFL => {
+                let flags = OpenFlags::from_bits_truncate(arg as u32);
+                get_file(fd)?.status_flags().set(flags);
+                Ok(0)
+            }
+            F_GETPIPE_SZ | F_SETPIPE_SZ => {
+                let file = get_file(fd)?;
+                let pipe = file
+                    .as_any()
+                    .downcast_ref::<PipeEnd>()
+                    .ok_or(LinuxError::EBADF)?;
+                let capacity = if cmd == F_SETPIPE_SZ {
+                    pipe.set_capacity(arg)?
+                } else {
+                    pipe.capacity()
+                };
+                Ok(capacity as isize)
+            }
+            _ => {
+                warn!("Unimplemented fcntl command: {}", cmd);
+                Err(LinuxError::EINVAL)
+            }
+        }
+    })
+}
@@ -1,15 +1,309 @@
-use core::ffi::c_void;
+use alloc::{sync::Arc, vec::Vec};
+use core::{ffi::c_void, ops::Deref};
 
 use arceos_posix_api as api;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
 
+use crate::{
+    file::{get_file, FileLike, SeekFrom},
+    syscall_body,
+    uaccess::{check_region, copy_from_user, copy_to_user, get_user, kernel_buf, read_to_user},
+};
+
+/// The highest number of buffers of `readv` and `writev`.
+const IOV_MAX: i32 = 1024;
+
+/// The flags of `preadv2` and `pwritev2`
+const RWF_HIPRI: u32 = 0x1;
+const RWF_DSYNC: u32 = 0x2;
+const RWF_SYNC: u32 = 0x4;
+const RWF_NOWAIT: u32 = 0x8;
+const RWF_APPEND: u32 = 0x10;
+/// The flags which are supported. The writes being synchronous and the
+/// reads not polled, `RWF_HIPRI`, `RWF_DSYNC` and `RWF_SYNC` change nothing.
+const RWF_SUPPORTED: u32 = RWF_HIPRI | RWF_DSYNC | RWF_SYNC | RWF_NOWAIT | RWF_APPEND;
+
+/// Read up to `count` bytes from the file `fd` into `buf`.
 pub(crate) fn sys_read(fd: i32, buf: *mut c_void, count: usize) -> isize {
-    api::sys_read(fd, buf, count)
+    syscall_body!(sys_read, {
+        let file = get_file(fd)?;
+        Ok(read_to_user(buf as *mut u8, count, |buf| file.read(buf))? as isize)
+    })
 }
 
+/// Write `count` bytes from `buf` to the file `fd`.
 pub(crate) fn sys_write(fd: i32, buf: *const c_void, count: usize) -> isize {
-    api::sys_write(fd, buf, count)
+    syscall_body!(sys_write, {
+        let file = get_file(fd)?;
+        let buf = copy_from_user(buf as *const u8, count)?;
+        Ok(file.write(&buf)? as isize)
+    })
+}
+
+/// Read up to `count` bytes from the file `fd` at `offset` into `buf`,
+/// without using or moving the offset of the file.
+pub(crate) fn sys_pread64(fd: i32, buf: *mut c_void, count: usize, offset: i64) -> isize {
+    syscall_body!(sys_pread64, {
+        let offset = check_offset(offset)?;
+        let file = get_file(fd)?;
+        Ok(read_to_user(buf as *mut u8, count, |buf| file.read_at(offset, buf))? as isize)
+    })
+}
+
+/// Write `count` bytes from `buf` to the file `fd` at `offset`, without using
+/// or moving the offset of the file.
+pub(crate) fn sys_pwrite64(fd: i32, buf: *const c_void, count: usize, offset: i64) -> isize {
+    syscall_body!(sys_pwrite64, {
+        let offset = check_offset(offset)?;
+        let file = get_file(fd)?;
+        let buf = copy_from_user(buf as *const u8, count)?;
+        Ok(file.write_at(offset, &buf)? as isize)
+    })
+}
+
+/// Read from the file `fd` into the `iocnt` buffers of `iov` in order, as a
+/// single read.
+pub(crate) fn sys_readv(fd: i32, iov: *const api::ctypes::iovec, iocnt: i32) -> isize {
+    syscall_body!(sys_readv, {
+        let file = get_file(fd)?;
+        Ok(read_to_iovecs(iov, iocnt, |bufs| file.read_vectored(bufs))? as isize)
+    })
 }
 
+/// Write the `iocnt` buffers of `iov` in order to the file `fd`.
+///
+/// All buffers are checked before writing. The writes stop at the first
+/// short write, and an error is only returned if nothing was written.
 pub(crate) fn sys_writev(fd: i32, iov: *const api::ctypes::iovec, iocnt: i32) -> isize {
-    unsafe { api::sys_writev(fd, iov, iocnt) }
+    syscall_body!(sys_writev, {
+        let file = get_file(fd)?;
+        let bufs = write_bufs(iov, iocnt)?;
+        Ok(transfer(bufs, |buf, _| file.write(&buf))? as isize)
+    })
+}
+
+/// Read from the file `fd` at `offset` into the `iocnt` buffers of `iov` in
+/// order, without using or moving the offset of the file.
+///
+/// The offset is split in two arguments for 32-bit architectures, and
+/// `offset` holds all of it on 64-bit ones.
+pub(crate) fn sys_preadv(
+    fd: i32,
+    iov: *const api::ctypes::iovec,
+    iocnt: i32,
+    offset: i64,
+    _offset_high: i64,
+) -> isize {
+    syscall_body!(sys_preadv, {
+        let offset = check_offset(offset)?;
+        let file = get_file(fd)?;
+        Ok(read_to_iovecs(iov, iocnt, |bufs| {
+            transfer(bufs.iter_mut().map(|buf| &mut **buf), |buf, done| {
+                file.read_at(offset + done as u64, buf)
+            })
+        })? as isize)
+    })
+}
+
+/// Write the `iocnt` buffers of `iov` in order to the file `fd` at `offset`,
+/// without using or moving the offset of the file.
+pub(crate) fn sys_pwritev(
+    fd: i32,
+    iov: *const api::ctypes::iovec,
+    iocnt: i32,
+    offset: i64,
+    _offset_high: i64,
+) -> isize {
+    syscall_body!(sys_pwritev, {
+        let offset = check_offset(offset)?;
+        let file = get_file(fd)?;
+        let bufs = write_bufs(iov, iocnt)?;
+        Ok(transfer(bufs, |buf, done| file.write_at(offset + done as u64, &buf))? as isize)
+    })
+}
+
+/// Like `preadv`, with an offset of -1 for the offset of the file, as
+/// `readv`, and the `RWF_*` flags.
+///
+/// With `RWF_NOWAIT`, the read fails with `EAGAIN` instead of blocking, as if
+/// the file were non-blocking.
+pub(crate) fn sys_preadv2(
+    fd: i32,
+    iov: *const api::ctypes::iovec,
+    iocnt: i32,
+    offset: i64,
+    _offset_high: i64,
+    flags: u32,
+) -> isize {
+    syscall_body!(sys_preadv2, {
+        let file = get_file(fd)?;
+        check_rwf_flags(flags)?;
+        if offset == -1 {
+            return Ok(read_to_iovecs(iov, iocnt, |bufs| {
+                if flags & RWF_NOWAIT != 0 {
+                    read_vectored_nowait(&file, bufs)
+                } else {
+                    file.read_vectored(bufs)
+                }
+            })? as isize);
+        }
+        let offset = check_offset(offset)?;
+        Ok(read_to_iovecs(iov, iocnt, |bufs| {
+            transfer(bufs.iter_mut().map(|buf| &mut **buf), |buf, done| {
+                file.read_at(offset + done as u64, buf)
+            })
+        })? as isize)
+    })
+}
+
+/// Like `pwritev`, with an offset of -1 for the offset of the file, as
+/// `writev`, and the `RWF_*` flags.
+///
+/// With `RWF_APPEND`, the buffers are written at the end of the file whatever
+/// the offset, as with `O_APPEND`. With `RWF_NOWAIT`, the write fails with
+/// `EAGAIN` instead of blocking.
+pub(crate) fn sys_pwritev2(
+    fd: i32,
+    iov: *const api::ctypes::iovec,
+    iocnt: i32,
+    offset: i64,
+    _offset_high: i64,
+    flags: u32,
+) -> isize {
+    syscall_body!(sys_pwritev2, {
+        let file = get_file(fd)?;
+        check_rwf_flags(flags)?;
+        let bufs = write_bufs(iov, iocnt)?;
+        let append = flags & RWF_APPEND != 0;
+        if offset == -1 {
+            if append {
+                // The files which cannot seek have no end to write at.
+                match file.seek(SeekFrom::End(0)) {
+                    Ok(_) | Err(LinuxError::ESPIPE) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if flags & RWF_NOWAIT != 0 {
+                return Ok(transfer(bufs, |buf, _| file.write_nowait(&buf))? as isize);
+            }
+            return Ok(transfer(bufs, |buf, _| file.write(&buf))? as isize);
+        }
+        let mut offset = check_offset(offset)?;
+        if append {
+            offset = file.stat()?.size;
+        }
+        Ok(transfer(bufs, |buf, done| file.write_at(offset + done as u64, &buf))? as isize)
+    })
+}
+
+/// Returns `offset` if it is not negative.
+fn check_offset(offset: i64) -> LinuxResult<u64> {
+    u64::try_from(offset).map_err(|_| LinuxError::EINVAL)
+}
+
+/// Checks the `RWF_*` flags of `preadv2` and `pwritev2`.
+fn check_rwf_flags(flags: u32) -> LinuxResult {
+    if flags & !RWF_SUPPORTED != 0 {
+        return Err(LinuxError::EOPNOTSUPP);
+    }
+    Ok(())
+}
+
+/// Reads from `file` into `bufs` as [`FileLike::read_vectored`], but fails
+/// with `EAGAIN` instead of blocking, for `RWF_NOWAIT`.
+///
+/// The files with an offset never block, and the others only read into the
+/// first non-empty buffer, as by default.
+fn read_vectored_nowait(file: &Arc<dyn FileLike>, bufs: &mut [&mut [u8]]) -> LinuxResult<usize> {
+    if file.seek(SeekFrom::Current(0)).is_ok() {
+        return file.read_vectored(bufs);
+    }
+    match bufs.iter_mut().find(|buf| !buf.is_empty()) {
+        Some(buf) => file.read_nowait(buf),
+        None => Ok(0),
+    }
+}
+
+/// Returns the `iocnt` entries of the array `iov`.
+///
+/// Fails with `EINVAL` if there are more than `IOV_MAX` entries, or if their
+/// total length does not fit in an `isize`.
+fn user_iovecs(iov: *const api::ctypes::iovec, iocnt: i32) -> LinuxResult<Vec<api::ctypes::iovec>> {
+    if !(0..=IOV_MAX).contains(&iocnt) {
+        return Err(LinuxError::EINVAL);
+    }
+    let iovs = (0..iocnt as usize)
+        .map(|i| get_user(iov.wrapping_add(i)))
+        .collect::<LinuxResult<Vec<_>>>()?;
+    iovs.iter()
+        .try_fold(0usize, |total, iov| {
+            total
+                .checked_add(iov.iov_len)
+                .filter(|&total| total <= isize::MAX as usize)
+        })
+        .ok_or(LinuxError::EINVAL)?;
+    Ok(iovs)
+}
+
+/// Reads with `read` into kernel buffers as large as the buffers of `iov`,
+/// which are all checked to be writable first, and copies the bytes read to
+/// them in order.
+fn read_to_iovecs(
+    iov: *const api::ctypes::iovec,
+    iocnt: i32,
+    read: impl FnOnce(&mut [&mut [u8]]) -> LinuxResult<usize>,
+) -> LinuxResult<usize> {
+    let iovs = user_iovecs(iov, iocnt)?;
+    for iov in &iovs {
+        check_region(iov.iov_base as usize, iov.iov_len, MappingFlags::WRITE)?;
+    }
+    let mut bufs = iovs
+        .iter()
+        .map(|iov| kernel_buf(iov.iov_len))
+        .collect::<LinuxResult<Vec<_>>>()?;
+    let mut slices = bufs.iter_mut().map(Vec::as_mut_slice).collect::<Vec<_>>();
+    let read = read(&mut slices)?;
+    let mut left = read;
+    for (iov, buf) in iovs.iter().zip(&bufs) {
+        let len = left.min(buf.len());
+        copy_to_user(iov.iov_base as *mut u8, &buf[..len])?;
+        left -= len;
+    }
+    Ok(read)
+}
+
+/// Copies the buffers of `iov` to write into the kernel.
+fn write_bufs(iov: *const api::ctypes::iovec, iocnt: i32) -> LinuxResult<Vec<Vec<u8>>> {
+    user_iovecs(iov, iocnt)?
+        .iter()
+        .map(|iov| copy_from_user(iov.iov_base as *const u8, iov.iov_len))
+        .collect()
+}
+
+/// Reads or writes `bufs` in order with `op`, which is given each buffer and
+/// the number of bytes transferred before it, and returns the total number
+/// of bytes transferred.
+///
+/// It stops at the first short transfer, and an error is only returned if
+/// nothing was transferred.
+fn transfer<B: Deref<Target = [u8]>>(
+    bufs: impl IntoIterator<Item = B>,
+    mut op: impl FnMut(B, usize) -> LinuxResult<usize>,
+) -> LinuxResult<usize> {
+    let mut done = 0;
+    for buf in bufs {
+        let len = buf.len();
+        match op(buf, done) {
+            Ok(transferred) => {
+                done += transferred;
+                if transferred < len {
+                    break;
+                }
+            }
+            Err(_) if done > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(done)
 }
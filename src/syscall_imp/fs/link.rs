@@ -0,0 +1,189 @@
+use alloc::string::String;
+use core::ffi::c_char;
+
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::{
+    file::get_file,
+    fs::{self, AT_EMPTY_PATH, AT_SYMLINK_FOLLOW},
+    syscall_body,
+    uaccess::{copy_to_user, get_user_str},
+};
+
+/// Remove a directory instead of a file.
+const AT_REMOVEDIR: u32 = 0x200;
+
+/// Fail if the new path exists.
+const RENAME_NOREPLACE: u32 = 1 << 0;
+/// Exchange the old and new paths.
+const RENAME_EXCHANGE: u32 = 1 << 1;
+
+/// Returns the path of the file `dirfd` itself, for `AT_EMPTY_PATH`.
+fn fd_path(dirfd: i32) -> LinuxResult<String> {
+    if dirfd == fs::AT_FDCWD {
+        return Ok(fs::current_dir());
+    }
+    let file = get_file(dirfd)?;
+    file.path().map(String::from).ok_or(LinuxError::ENOENT)
+}
+
+/// Remove the file or link at `path`, or the empty directory at `path` with
+/// `AT_REMOVEDIR`, relative to the directory `dirfd` if it is relative.
+pub(crate) fn sys_unlinkat(dirfd: i32, path: *const c_char, flags: u32) -> isize {
+    syscall_body!(sys_unlinkat, {
+        if flags & !AT_REMOVEDIR != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let path = get_user_str(path)?;
+        let path = fs::resolve_path(dirfd, &path, false)?;
+        fs::unlink(&path, flags & AT_REMOVEDIR != 0)?;
+        Ok(0)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_unlink(path: *const c_char) -> isize {
+    sys_unlinkat(fs::AT_FDCWD, path, 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_rmdir(path: *const c_char) -> isize {
+    sys_unlinkat(fs::AT_FDCWD, path, AT_REMOVEDIR)
+}
+
+/// Rename the file, directory or link at `oldpath` to `newpath`, each
+/// relative to its directory if it is relative.
+///
+/// # Arguments
+/// * `olddirfd` - The directory of `oldpath`, or `AT_FDCWD`
+/// * `oldpath` - The path to rename
+/// * `newdirfd` - The directory of `newpath`, or `AT_FDCWD`
+/// * `newpath` - The new path, which is replaced if it exists
+/// * `flags` - `RENAME_NOREPLACE` or `RENAME_EXCHANGE`
+pub(crate) fn sys_renameat2(
+    olddirfd: i32,
+    oldpath: *const c_char,
+    newdirfd: i32,
+    newpath: *const c_char,
+    flags: u32,
+) -> isize {
+    syscall_body!(sys_renameat2, {
+        if flags & !(RENAME_NOREPLACE | RENAME_EXCHANGE) != 0
+            || flags == RENAME_NOREPLACE | RENAME_EXCHANGE
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let oldpath = fs::resolve_path(olddirfd, &get_user_str(oldpath)?, false)?;
+        let newpath = fs::resolve_path(newdirfd, &get_user_str(newpath)?, false)?;
+        if flags & RENAME_EXCHANGE != 0 {
+            fs::exchange(&oldpath, &newpath)?;
+        } else {
+            fs::rename(&oldpath, &newpath, flags & RENAME_NOREPLACE != 0)?;
+        }
+        Ok(0)
+    })
+}
+
+#[cfg(not(target_arch = "riscv64"))]
+pub(crate) fn sys_renameat(
+    olddirfd: i32,
+    oldpath: *const c_char,
+    newdirfd: i32,
+    newpath: *const c_char,
+) -> isize {
+    sys_renameat2(olddirfd, oldpath, newdirfd, newpath, 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_rename(oldpath: *const c_char, newpath: *const c_char) -> isize {
+    sys_renameat2(fs::AT_FDCWD, oldpath, fs::AT_FDCWD, newpath, 0)
+}
+
+/// Create a hard link at `newpath` to the file at `oldpath`, each relative to
+/// its directory if it is relative.
+///
+/// # Arguments
+/// * `olddirfd` - The directory of `oldpath`, or `AT_FDCWD`
+/// * `oldpath` - The file to link to
+/// * `newdirfd` - The directory of `newpath`, or `AT_FDCWD`
+/// * `newpath` - The new link, which must not exist
+/// * `flags` - `AT_SYMLINK_FOLLOW` or `AT_EMPTY_PATH`
+pub(crate) fn sys_linkat(
+    olddirfd: i32,
+    oldpath: *const c_char,
+    newdirfd: i32,
+    newpath: *const c_char,
+    flags: u32,
+) -> isize {
+    syscall_body!(sys_linkat, {
+        if flags & !(AT_SYMLINK_FOLLOW | AT_EMPTY_PATH) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let oldpath = get_user_str(oldpath)?;
+        let oldpath = if oldpath.is_empty() && flags & AT_EMPTY_PATH != 0 {
+            fd_path(olddirfd)?
+        } else {
+            fs::resolve_path(olddirfd, &oldpath, flags & AT_SYMLINK_FOLLOW != 0)?
+        };
+        let newpath = fs::resolve_path(newdirfd, &get_user_str(newpath)?, false)?;
+        fs::link(&oldpath, &newpath)?;
+        Ok(0)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_link(oldpath: *const c_char, newpath: *const c_char) -> isize {
+    sys_linkat(fs::AT_FDCWD, oldpath, fs::AT_FDCWD, newpath, 0)
+}
+
+/// Create a symbolic link at `linkpath` to `target`, relative to the
+/// directory `newdirfd` if it is relative.
+pub(crate) fn sys_symlinkat(
+    target: *const c_char,
+    newdirfd: i32,
+    linkpath: *const c_char,
+) -> isize {
+    syscall_body!(sys_symlinkat, {
+        let target = get_user_str(target)?;
+        let linkpath = fs::resolve_path(newdirfd, &get_user_str(linkpath)?, false)?;
+        fs::symlink(&target, &linkpath)?;
+        Ok(0)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_symlink(target: *const c_char, linkpath: *const c_char) -> isize {
+    sys_symlinkat(target, fs::AT_FDCWD, linkpath)
+}
+
+/// Read the target of the symbolic link at `path`, relative to the directory
+/// `dirfd` if it is relative, into `buf`. The target is truncated to
+/// `bufsiz` bytes and is not null-terminated.
+pub(crate) fn sys_readlinkat(
+    dirfd: i32,
+    path: *const c_char,
+    buf: *mut c_char,
+    bufsiz: i32,
+) -> isize {
+    syscall_body!(sys_readlinkat, {
+        if bufsiz <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let path = get_user_str(path)?;
+        // An empty path refers to `dirfd`, opened with `O_PATH | O_NOFOLLOW`.
+        let path = if path.is_empty() {
+            fd_path(dirfd)?
+        } else {
+            fs::resolve_path(dirfd, &path, false)?
+        };
+        let target = fs::readlink(&path)?;
+        let len = target.len().min(bufsiz as usize);
+        copy_to_user(buf as *mut u8, &target.as_bytes()[..len])?;
+        Ok(len as isize)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_readlink(path: *const c_char, buf: *mut c_char, bufsiz: i32) -> isize {
+    sys_readlinkat(fs::AT_FDCWD, path, buf, bufsiz)
+}
@@ -1,5 +1,23 @@
 mod ctl;
+mod dir;
+mod epoll;
+mod eventfd;
+mod fd_ops;
 mod io;
+mod link;
+mod pipe;
+mod poll;
+mod stat;
+mod timerfd;
 
 pub(crate) use self::ctl::*;
+pub(crate) use self::dir::*;
+pub(crate) use self::epoll::*;
+pub(crate) use self::eventfd::*;
+pub(crate) use self::fd_ops::*;
 pub(crate) use self::io::*;
+pub(crate) use self::link::*;
+pub(crate) use self::pipe::*;
+pub(crate) use self::poll::*;
+pub(crate) use self::stat::*;
+pub(crate) use self::timerfd::*;
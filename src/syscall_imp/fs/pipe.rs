@@ -0,0 +1,46 @@
+use alloc::sync::Arc;
+
+use axerrno::LinuxError;
+
+use crate::{
+    file::{add_file, close_file, PipeEnd},
+    fs::OpenFlags,
+    syscall_body,
+    uaccess::put_user,
+};
+
+/// Create a pipe, storing the descriptors of its read and write ends in
+/// `fds`.
+///
+/// # Arguments
+/// * `fds` - Where to store the two descriptors
+/// * `flags` - `O_CLOEXEC` or `O_NONBLOCK`, which apply to both ends
+pub(crate) fn sys_pipe2(fds: *mut [i32; 2], flags: u32) -> isize {
+    syscall_body!(sys_pipe2, {
+        let flags = OpenFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
+        if !(OpenFlags::O_CLOEXEC | OpenFlags::O_NONBLOCK).contains(flags) {
+            return Err(LinuxError::EINVAL);
+        }
+        let cloexec = flags.contains(OpenFlags::O_CLOEXEC);
+        let (reader, writer) = PipeEnd::new_pair(flags.contains(OpenFlags::O_NONBLOCK));
+        let read_fd = add_file(Arc::new(reader), cloexec)?;
+        let write_fd = match add_file(Arc::new(writer), cloexec) {
+            Ok(fd) => fd,
+            Err(e) => {
+                close_file(read_fd)?;
+                return Err(e);
+            }
+        };
+        if let Err(e) = put_user(fds, [read_fd, write_fd]) {
+            close_file(read_fd)?;
+            close_file(write_fd)?;
+            return Err(e);
+        }
+        Ok(0)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_pipe(fds: *mut [i32; 2]) -> isize {
+    sys_pipe2(fds, 0)
+}
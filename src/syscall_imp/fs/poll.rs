@@ -0,0 +1,303 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::time::Duration;
+
+use arceos_posix_api as api;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+
+use crate::{
+    file::{get_file, nofile_limit, FileLike, PollEvents, Poller},
+    signal::SigSet,
+    syscall_body,
+    syscall_imp::{
+        signal::set_temporary_mask,
+        time::{duration_to_timespec, timespec_to_duration},
+    },
+    uaccess::{get_user, put_user},
+};
+
+/// `struct pollfd`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+/// The events of `select` for the readable, writable and exceptional file
+/// descriptors
+const SELECT_READ: PollEvents = PollEvents::READABLE
+    .union(PollEvents::POLLRDBAND)
+    .union(PollEvents::ALWAYS);
+const SELECT_WRITE: PollEvents = PollEvents::WRITABLE
+    .union(PollEvents::POLLWRBAND)
+    .union(PollEvents::POLLERR);
+const SELECT_EXCEPT: PollEvents = PollEvents::POLLPRI;
+
+/// The number of descriptors in a word of `fd_set`.
+const FD_SET_BITS: usize = usize::BITS as usize;
+
+/// The argument of `pselect6` with its signal mask.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct SigSetArg {
+    ss: *const SigSet,
+    ss_len: usize,
+}
+
+/// Stores the time left of `timeout` at `ptr`, if it is not `None`, as Linux
+/// does for `ppoll` and `pselect6`.
+///
+/// As on Linux, a failure to store it is ignored, so that a read-only
+/// timeout does not fail the call.
+fn update_timeout<T: Copy>(
+    ptr: *mut T,
+    timeout: Option<Duration>,
+    start: Duration,
+    convert: fn(Duration) -> T,
+) {
+    if let Some(timeout) = timeout {
+        let left = timeout.saturating_sub(monotonic_time() - start);
+        let _ = put_user(ptr, convert(left));
+    }
+}
+
+/// Waits for the events of the `nfds` entries of `fds`, storing the events
+/// which occurred in them, and returns the number of entries with events.
+fn poll_fds(fds: *mut PollFd, nfds: usize, timeout: Option<Duration>) -> LinuxResult<usize> {
+    if nfds > nofile_limit() {
+        return Err(LinuxError::EINVAL);
+    }
+    let mut pollfds = (0..nfds)
+        .map(|i| get_user(fds.wrapping_add(i)))
+        .collect::<LinuxResult<Vec<_>>>()?;
+    // The negative descriptors are ignored.
+    let files: Vec<_> = pollfds
+        .iter()
+        .map(|pollfd| (pollfd.fd >= 0).then(|| get_file(pollfd.fd).ok()))
+        .collect();
+    let poller = Poller::new();
+    for file in files.iter().flatten().flatten() {
+        if let Some(set) = file.poll_set() {
+            poller.watch(&set);
+        }
+    }
+    let count = poller.wait(timeout, || {
+        let mut count = 0;
+        for (pollfd, file) in pollfds.iter_mut().zip(&files) {
+            let revents = match file {
+                None => PollEvents::empty(),
+                Some(None) => PollEvents::POLLNVAL,
+                Some(Some(file)) => {
+                    let events = PollEvents::from_bits_truncate(pollfd.events as u16 as u32);
+                    file.poll() & (events | PollEvents::ALWAYS)
+                }
+            };
+            pollfd.revents = revents.bits() as i16;
+            if !revents.is_empty() {
+                count += 1;
+            }
+        }
+        Ok((count > 0).then_some(count))
+    })?;
+    for (i, pollfd) in pollfds.into_iter().enumerate() {
+        put_user(fds.wrapping_add(i), pollfd)?;
+    }
+    Ok(count.unwrap_or(0))
+}
+
+/// Wait for some events of the `nfds` file descriptors of `fds`.
+///
+/// # Arguments
+/// * `fds` - The descriptors with their requested events, whose `revents`
+///   are set to the events which occurred, a negative descriptor is ignored
+/// * `nfds` - The number of descriptors
+/// * `timeout` - The longest time to wait, or null to wait indefinitely,
+///   which is updated with the time left
+/// * `sigmask` - The signals to block while waiting, may be null to keep the
+///   blocked signals
+/// * `sigsetsize` - The size of `sigset_t`, which must be 8
+///
+/// Returns the number of descriptors with events, or 0 if the timeout
+/// expired.
+pub(crate) fn sys_ppoll(
+    fds: *mut PollFd,
+    nfds: usize,
+    timeout: *mut api::ctypes::timespec,
+    sigmask: *const SigSet,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_ppoll, {
+        let timeout_dur = if timeout.is_null() {
+            None
+        } else {
+            Some(timespec_to_duration(get_user(timeout)?)?)
+        };
+        set_temporary_mask(sigmask, sigsetsize)?;
+        let start = monotonic_time();
+        let res = poll_fds(fds, nfds, timeout_dur);
+        update_timeout(timeout, timeout_dur, start, duration_to_timespec);
+        Ok(res? as isize)
+    })
+}
+
+/// Like `ppoll`, with a timeout in milliseconds, negative to wait
+/// indefinitely, and without signal mask.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_poll(fds: *mut PollFd, nfds: usize, timeout: i32) -> isize {
+    syscall_body!(sys_poll, {
+        let timeout = u64::try_from(timeout).ok().map(Duration::from_millis);
+        Ok(poll_fds(fds, nfds, timeout)? as isize)
+    })
+}
+
+/// Reads the `fd_set` at `ptr` for the `nfds` first descriptors, with no
+/// descriptor if `ptr` is null.
+fn get_fd_set(ptr: *const usize, nfds: usize) -> LinuxResult<Vec<usize>> {
+    let words = nfds.div_ceil(FD_SET_BITS);
+    if ptr.is_null() {
+        return Ok(vec![0; words]);
+    }
+    (0..words).map(|i| get_user(ptr.wrapping_add(i))).collect()
+}
+
+/// Stores `set` at `ptr`, if it is not null.
+fn put_fd_set(ptr: *mut usize, set: &[usize]) -> LinuxResult {
+    if ptr.is_null() {
+        return Ok(());
+    }
+    for (i, &word) in set.iter().enumerate() {
+        put_user(ptr.wrapping_add(i), word)?;
+    }
+    Ok(())
+}
+
+/// Waits for the descriptors of the `fd_set`s at `readfds`, `writefds` and
+/// `exceptfds` to be readable, writable or to have an exceptional condition,
+/// keeping in the sets only those which are, and returns their number.
+fn select_fds(
+    nfds: i32,
+    readfds: *mut usize,
+    writefds: *mut usize,
+    exceptfds: *mut usize,
+    timeout: Option<Duration>,
+) -> LinuxResult<usize> {
+    let nfds = usize::try_from(nfds).map_err(|_| LinuxError::EINVAL)?;
+    // The descriptors above the limit cannot be open.
+    let nfds = nfds.min(nofile_limit());
+    let sets = [
+        get_fd_set(readfds, nfds)?,
+        get_fd_set(writefds, nfds)?,
+        get_fd_set(exceptfds, nfds)?,
+    ];
+    let mut files: Vec<(usize, Arc<dyn FileLike>)> = Vec::new();
+    for fd in 0..nfds {
+        let (word, bit) = (fd / FD_SET_BITS, 1 << (fd % FD_SET_BITS));
+        if sets.iter().any(|set| set[word] & bit != 0) {
+            files.push((fd, get_file(fd as i32)?));
+        }
+    }
+    let poller = Poller::new();
+    for (_, file) in &files {
+        if let Some(set) = file.poll_set() {
+            poller.watch(&set);
+        }
+    }
+    let words = nfds.div_ceil(FD_SET_BITS);
+    let mut ready = [vec![0; words], vec![0; words], vec![0; words]];
+    let count = poller.wait(timeout, || {
+        let mut count = 0;
+        for set in &mut ready {
+            set.fill(0);
+        }
+        for (fd, file) in &files {
+            let (word, bit) = (fd / FD_SET_BITS, 1 << (fd % FD_SET_BITS));
+            let events = file.poll();
+            for ((wanted, ready), select) in
+                sets.iter()
+                    .zip(&mut ready)
+                    .zip([SELECT_READ, SELECT_WRITE, SELECT_EXCEPT])
+            {
+                if wanted[word] & bit != 0 && events.intersects(select) {
+                    ready[word] |= bit;
+                    count += 1;
+                }
+            }
+        }
+        Ok((count > 0).then_some(count))
+    })?;
+    put_fd_set(readfds, &ready[0])?;
+    put_fd_set(writefds, &ready[1])?;
+    put_fd_set(exceptfds, &ready[2])?;
+    Ok(count.unwrap_or(0))
+}
+
+/// Wait for some of the `nfds` first file descriptors to be readable,
+/// writable, or to have an exceptional condition.
+///
+/// # Arguments
+/// * `nfds` - The highest descriptor of the sets plus one
+/// * `readfds`, `writefds`, `exceptfds` - The `fd_set`s of the descriptors
+///   to wait for, each may be null, in which only the descriptors ready are
+///   kept
+/// * `timeout` - The longest time to wait, or null to wait indefinitely,
+///   which is updated with the time left
+/// * `sigmask` - The signals to block while waiting and the size of
+///   `sigset_t`, may be null to keep the blocked signals
+///
+/// Returns the number of descriptors ready in the sets, or 0 if the timeout
+/// expired.
+pub(crate) fn sys_pselect6(
+    nfds: i32,
+    readfds: *mut usize,
+    writefds: *mut usize,
+    exceptfds: *mut usize,
+    timeout: *mut api::ctypes::timespec,
+    sigmask: *const SigSetArg,
+) -> isize {
+    syscall_body!(sys_pselect6, {
+        let timeout_dur = if timeout.is_null() {
+            None
+        } else {
+            Some(timespec_to_duration(get_user(timeout)?)?)
+        };
+        if !sigmask.is_null() {
+            let arg = get_user(sigmask)?;
+            set_temporary_mask(arg.ss, arg.ss_len)?;
+        }
+        let start = monotonic_time();
+        let res = select_fds(nfds, readfds, writefds, exceptfds, timeout_dur);
+        update_timeout(timeout, timeout_dur, start, duration_to_timespec);
+        Ok(res? as isize)
+    })
+}
+
+/// Like `pselect6`, with a `timeval` timeout and without signal mask.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_select(
+    nfds: i32,
+    readfds: *mut usize,
+    writefds: *mut usize,
+    exceptfds: *mut usize,
+    timeout: *mut api::ctypes::timeval,
+) -> isize {
+    syscall_body!(sys_select, {
+        let timeout_dur = if timeout.is_null() {
+            None
+        } else {
+            let tv = get_user(timeout)?;
+            if tv.tv_sec < 0 || !(0..1_000_000).contains(&tv.tv_usec) {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000))
+        };
+        let start = monotonic_time();
+        let res = select_fds(nfds, readfds, writefds, exceptfds, timeout_dur);
+        update_timeout(timeout, timeout_dur, start, |left| api::ctypes::timeval {
+            tv_sec: left.as_secs() as _,
+            tv_usec: left.subsec_micros() as _,
+        });
+        Ok(res? as isize)
+    })
+}
@@ -0,0 +1,270 @@
+use core::ffi::c_char;
+
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::{
+    file::{get_file, Kstat, S_IFDIR, S_IFMT},
+    fs::{self, AT_EMPTY_PATH, AT_SYMLINK_NOFOLLOW},
+    syscall_body,
+    uaccess::{get_user_str, put_user},
+};
+
+/// Do not mount an automounted directory at the end of the path.
+const AT_NO_AUTOMOUNT: u32 = 0x800;
+/// Check the access with the effective IDs instead of the real IDs.
+const AT_EACCESS: u32 = 0x200;
+/// How `statx` synchronizes with a remote filesystem.
+const AT_STATX_SYNC_TYPE: u32 = 0x6000;
+
+const R_OK: u32 = 4;
+const W_OK: u32 = 2;
+const X_OK: u32 = 1;
+
+/// The fields of `struct statx` which are filled, i.e. `STATX_BASIC_STATS`
+/// without the timestamps, which are not tracked.
+const STATX_FILLED: u32 = 0x71f;
+
+/// `struct stat` of x86_64.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Stat {
+    dev: u64,
+    ino: u64,
+    nlink: u64,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    _pad0: u32,
+    rdev: u64,
+    size: i64,
+    blksize: i64,
+    blocks: i64,
+    atime: [i64; 2],
+    mtime: [i64; 2],
+    ctime: [i64; 2],
+    _unused: [i64; 3],
+}
+
+/// `struct stat` of the generic syscall ABI, used by aarch64 and riscv64.
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Stat {
+    dev: u64,
+    ino: u64,
+    mode: u32,
+    nlink: u32,
+    uid: u32,
+    gid: u32,
+    rdev: u64,
+    _pad1: u64,
+    size: i64,
+    blksize: i32,
+    _pad2: i32,
+    blocks: i64,
+    atime: [i64; 2],
+    mtime: [i64; 2],
+    ctime: [i64; 2],
+    _unused: [u32; 2],
+}
+
+impl From<Kstat> for Stat {
+    fn from(kstat: Kstat) -> Self {
+        Self {
+            dev: kstat.dev,
+            ino: kstat.ino,
+            nlink: kstat.nlink as _,
+            mode: kstat.mode,
+            rdev: kstat.rdev,
+            size: kstat.size as i64,
+            blksize: kstat.blksize as _,
+            blocks: kstat.blocks as i64,
+            ..Default::default()
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct StatxTimestamp {
+    sec: i64,
+    nsec: u32,
+    _reserved: i32,
+}
+
+/// `struct statx`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Statx {
+    mask: u32,
+    blksize: u32,
+    attributes: u64,
+    nlink: u32,
+    uid: u32,
+    gid: u32,
+    mode: u16,
+    _spare0: u16,
+    ino: u64,
+    size: u64,
+    blocks: u64,
+    attributes_mask: u64,
+    atime: StatxTimestamp,
+    btime: StatxTimestamp,
+    ctime: StatxTimestamp,
+    mtime: StatxTimestamp,
+    rdev_major: u32,
+    rdev_minor: u32,
+    dev_major: u32,
+    dev_minor: u32,
+    mnt_id: u64,
+    dio_mem_align: u32,
+    dio_offset_align: u32,
+    _spare3: [u64; 12],
+}
+
+/// Returns the major number of the device number `dev`.
+fn major(dev: u64) -> u32 {
+    (((dev >> 32) & 0xffff_f000) | ((dev >> 8) & 0xfff)) as u32
+}
+
+/// Returns the minor number of the device number `dev`.
+fn minor(dev: u64) -> u32 {
+    (((dev >> 12) & 0xffff_ff00) | (dev & 0xff)) as u32
+}
+
+impl From<Kstat> for Statx {
+    fn from(kstat: Kstat) -> Self {
+        Self {
+            mask: STATX_FILLED,
+            blksize: kstat.blksize,
+            nlink: kstat.nlink,
+            mode: kstat.mode as u16,
+            ino: kstat.ino,
+            size: kstat.size,
+            blocks: kstat.blocks,
+            rdev_major: major(kstat.rdev),
+            rdev_minor: minor(kstat.rdev),
+            dev_major: major(kstat.dev),
+            dev_minor: minor(kstat.dev),
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the status of the file at `path` relative to `dirfd`, or of the
+/// file `dirfd` itself if `path` is empty and `flags` has `AT_EMPTY_PATH`.
+fn stat_at(dirfd: i32, path: *const c_char, flags: u32) -> LinuxResult<Kstat> {
+    let path = get_user_str(path)?;
+    if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+        if dirfd == fs::AT_FDCWD {
+            return fs::stat(&fs::current_dir());
+        }
+        return get_file(dirfd)?.stat();
+    }
+    let follow = flags & AT_SYMLINK_NOFOLLOW == 0;
+    let kstat = fs::stat(&fs::resolve_path(dirfd, &path, follow)?)?;
+    // A trailing slash requires a directory.
+    if path.ends_with('/') && kstat.mode & S_IFMT != S_IFDIR {
+        return Err(LinuxError::ENOTDIR);
+    }
+    Ok(kstat)
+}
+
+/// Get the status of the file `fd`.
+pub(crate) fn sys_fstat(fd: i32, statbuf: *mut Stat) -> isize {
+    syscall_body!(sys_fstat, {
+        put_user(statbuf, get_file(fd)?.stat()?.into())?;
+        Ok(0)
+    })
+}
+
+/// Get the status of the file at `path`, relative to the directory `dirfd`
+/// if it is relative.
+///
+/// # Arguments
+/// * `dirfd` - The directory, or `AT_FDCWD` for the current directory
+/// * `path` - The path of the file
+/// * `statbuf` - Where to store the status
+/// * `flags` - `AT_EMPTY_PATH`, `AT_SYMLINK_NOFOLLOW` or `AT_NO_AUTOMOUNT`
+pub(crate) fn sys_newfstatat(
+    dirfd: i32,
+    path: *const c_char,
+    statbuf: *mut Stat,
+    flags: u32,
+) -> isize {
+    syscall_body!(sys_newfstatat, {
+        if flags & !(AT_EMPTY_PATH | AT_SYMLINK_NOFOLLOW | AT_NO_AUTOMOUNT) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        put_user(statbuf, stat_at(dirfd, path, flags)?.into())?;
+        Ok(0)
+    })
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_stat(path: *const c_char, statbuf: *mut Stat) -> isize {
+    sys_newfstatat(fs::AT_FDCWD, path, statbuf, 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_lstat(path: *const c_char, statbuf: *mut Stat) -> isize {
+    sys_newfstatat(fs::AT_FDCWD, path, statbuf, AT_SYMLINK_NOFOLLOW)
+}
+
+/// Get the extended status of the file at `path`, relative to the directory
+/// `dirfd` if it is relative.
+///
+/// All the fields which are known are returned, whatever `mask` asks for.
+pub(crate) fn sys_statx(
+    dirfd: i32,
+    path: *const c_char,
+    flags: u32,
+    _mask: u32,
+    statxbuf: *mut Statx,
+) -> isize {
+    syscall_body!(sys_statx, {
+        let known = AT_EMPTY_PATH | AT_SYMLINK_NOFOLLOW | AT_NO_AUTOMOUNT | AT_STATX_SYNC_TYPE;
+        if flags & !known != 0 || flags & AT_STATX_SYNC_TYPE == AT_STATX_SYNC_TYPE {
+            return Err(LinuxError::EINVAL);
+        }
+        put_user(statxbuf, stat_at(dirfd, path, flags)?.into())?;
+        Ok(0)
+    })
+}
+
+/// Check whether the calling process can access the file at `path`,
+/// relative to the directory `dirfd` if it is relative.
+///
+/// Processes run as root, so reading and writing are always allowed, and
+/// executing is allowed if any execute permission is set.
+///
+/// # Arguments
+/// * `dirfd` - The directory, or `AT_FDCWD` for the current directory
+/// * `path` - The path of the file
+/// * `mode` - `F_OK`, or a combination of `R_OK`, `W_OK` and `X_OK`
+/// * `flags` - `AT_EACCESS`, `AT_SYMLINK_NOFOLLOW` or `AT_EMPTY_PATH`
+pub(crate) fn sys_faccessat2(dirfd: i32, path: *const c_char, mode: u32, flags: u32) -> isize {
+    syscall_body!(sys_faccessat2, {
+        if mode & !(R_OK | W_OK | X_OK) != 0
+            || flags & !(AT_EACCESS | AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH) != 0
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let kstat = stat_at(dirfd, path, flags)?;
+        let is_dir = kstat.mode & S_IFMT == S_IFDIR;
+        if mode & X_OK != 0 && !is_dir && kstat.mode & 0o111 == 0 {
+            return Err(LinuxError::EACCES);
+        }
+        Ok(0)
+    })
+}
+
+pub(crate) fn sys_faccessat(dirfd: i32, path: *const c_char, mode: u32) -> isize {
+    sys_faccessat2(dirfd, path, mode, 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_access(path: *const c_char, mode: u32) -> isize {
+    sys_faccessat2(fs::AT_FDCWD, path, mode, 0)
+}
@@ -0,0 +1,106 @@
+use core::time::Duration;
+
+use arceos_posix_api as api;
+use axerrno::LinuxError;
+
+use crate::{
+    file::{add_file, get_file, TimerFd, CLOCK_MONOTONIC, CLOCK_REALTIME},
+    syscall_body,
+    syscall_imp::time::{duration_to_timespec, timespec_to_duration},
+    uaccess::{get_user, put_user},
+};
+
+/// The flags of `timerfd_create`, the same as `O_NONBLOCK` and `O_CLOEXEC`.
+const TFD_NONBLOCK: u32 = 0o4000;
+const TFD_CLOEXEC: u32 = 0o200_0000;
+
+/// The flags of `timerfd_settime`
+const TFD_TIMER_ABSTIME: u32 = 1;
+/// Only cancels the timer when the realtime clock is set, which never
+/// happens.
+const TFD_TIMER_CANCEL_ON_SET: u32 = 2;
+
+/// `struct itimerspec`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct ITimerSpec {
+    interval: api::ctypes::timespec,
+    value: api::ctypes::timespec,
+}
+
+impl ITimerSpec {
+    fn new(value: Duration, interval: Duration) -> Self {
+        Self {
+            interval: duration_to_timespec(interval),
+            value: duration_to_timespec(value),
+        }
+    }
+}
+
+/// Create a disarmed timer read as a file.
+///
+/// # Arguments
+/// * `clockid` - `CLOCK_REALTIME` or `CLOCK_MONOTONIC`, the clock of the
+///   absolute expirations
+/// * `flags` - `TFD_NONBLOCK` and `TFD_CLOEXEC`
+pub(crate) fn sys_timerfd_create(clockid: u32, flags: u32) -> isize {
+    syscall_body!(sys_timerfd_create, {
+        if !matches!(clockid, CLOCK_REALTIME | CLOCK_MONOTONIC)
+            || flags & !(TFD_NONBLOCK | TFD_CLOEXEC) != 0
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let timerfd = TimerFd::new(clockid, flags & TFD_NONBLOCK != 0);
+        Ok(add_file(timerfd, flags & TFD_CLOEXEC != 0)? as isize)
+    })
+}
+
+/// Arm or disarm a timerfd.
+///
+/// # Arguments
+/// * `fd` - The timerfd
+/// * `flags` - `TFD_TIMER_ABSTIME` for an expiration at a time of the clock
+///   of the timer rather than after a delay, and `TFD_TIMER_CANCEL_ON_SET`
+/// * `new_value` - The first expiration, 0 to disarm the timer, and the
+///   interval of the next ones, 0 for a single expiration
+/// * `old_value` - Where to store the previous setting, may be null
+pub(crate) fn sys_timerfd_settime(
+    fd: i32,
+    flags: u32,
+    new_value: *const ITimerSpec,
+    old_value: *mut ITimerSpec,
+) -> isize {
+    syscall_body!(sys_timerfd_settime, {
+        if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let new = get_user(new_value)?;
+        let value = timespec_to_duration(new.value)?;
+        let interval = timespec_to_duration(new.interval)?;
+        let file = get_file(fd)?;
+        let timerfd = file
+            .as_any()
+            .downcast_ref::<TimerFd>()
+            .ok_or(LinuxError::EINVAL)?;
+        let (old, old_interval) = timerfd.set(value, interval, flags & TFD_TIMER_ABSTIME != 0);
+        if !old_value.is_null() {
+            put_user(old_value, ITimerSpec::new(old, old_interval))?;
+        }
+        Ok(0)
+    })
+}
+
+/// Get the time until the next expiration of a timerfd, 0 if it is
+/// disarmed, and its interval.
+pub(crate) fn sys_timerfd_gettime(fd: i32, curr_value: *mut ITimerSpec) -> isize {
+    syscall_body!(sys_timerfd_gettime, {
+        let file = get_file(fd)?;
+        let timerfd = file
+            .as_any()
+            .downcast_ref::<TimerFd>()
+            .ok_or(LinuxError::EINVAL)?;
+        let (value, interval) = timerfd.get();
+        put_user(curr_value, ITimerSpec::new(value, interval))?;
+        Ok(0)
+    })
+}
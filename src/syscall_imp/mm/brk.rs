@@ -0,0 +1,31 @@
+use axtask::{current, TaskExtRef};
+use memory_addr::VirtAddr;
+
+use crate::{resource::RLIMIT_DATA, syscall_body};
+
+/// Move the program break to `addr`, growing or shrinking the heap.
+///
+/// The heap cannot grow over other mappings, or beyond the `RLIMIT_DATA` of
+/// the process.
+///
+/// Returns the new program break, or the current one if `addr` is below the
+/// start of the heap or the break cannot be moved, e.g. with `addr` 0 to get
+/// it.
+pub(crate) fn sys_brk(addr: usize) -> usize {
+    syscall_body!(sys_brk, {
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let aspace = process.aspace();
+        let mut aspace = aspace.lock();
+        let heap_start = aspace.heap_start().as_usize();
+        let limit = process.rlimits.lock().get(RLIMIT_DATA).cur;
+        let grows = addr > aspace.brk().as_usize();
+        if addr < heap_start || grows && (addr - heap_start) as u64 > limit {
+            return Ok(aspace.brk().as_usize());
+        }
+        if let Err(e) = aspace.set_brk(VirtAddr::from(addr)) {
+            debug!("sys_brk: cannot move the break to {:#x}: {:?}", addr, e);
+        }
+        Ok(aspace.brk().as_usize())
+    })
+}
@@ -1,9 +1,9 @@
 use axerrno::LinuxError;
 use axhal::paging::MappingFlags;
 use axtask::{current, TaskExtRef};
-use memory_addr::{VirtAddr, VirtAddrRange};
+use memory_addr::{VirtAddr, PAGE_SIZE_4K};
 
-use crate::syscall_body;
+use crate::{file::get_file, fs::File, syscall_body};
 
 bitflags::bitflags! {
     /// permissions for sys_mmap
@@ -40,12 +40,14 @@ bitflags::bitflags! {
     /// flags for sys_mmap
     ///
     /// See <https://github.com/bminor/glibc/blob/master/bits/mman.h>
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Eq)]
     struct MmapFlags: i32 {
         /// Share changes
         const MAP_SHARED = 1 << 0;
         /// Changes private; copy pages on write.
         const MAP_PRIVATE = 1 << 1;
+        /// Share changes, like `MAP_SHARED` as unknown flags are not checked.
+        const MAP_SHARED_VALIDATE = 0x03;
         /// Map address must be exactly as requested, no matter whether it is available.
         const MAP_FIXED = 1 << 4;
         /// Don't use a file.
@@ -57,42 +59,229 @@ bitflags::bitflags! {
     }
 }
 
+/// The bits of the mmap flags holding the mapping type.
+const MAP_TYPE: i32 = 0x0f;
+
 pub(crate) fn sys_mmap(
     addr: *mut usize,
     length: usize,
     prot: i32,
     flags: i32,
-    _fd: i32,
-    _offset: isize,
+    fd: i32,
+    offset: isize,
 ) -> usize {
     syscall_body!(sys_mmap, {
-        let curr = current();
-        let curr_ext = curr.task_ext();
-        let mut aspace = curr_ext.aspace.lock();
         let permission_flags = MmapProt::from_bits_truncate(prot);
-        // TODO: check illegal flags for mmap
-        // An example is the flags contained none of MAP_PRIVATE, MAP_SHARED, or MAP_SHARED_VALIDATE.
         let map_flags = MmapFlags::from_bits_truncate(flags);
+        // The mapping type is either shared or private.
+        let shared = match MmapFlags::from_bits(flags & MAP_TYPE) {
+            Some(MmapFlags::MAP_PRIVATE) => false,
+            Some(MmapFlags::MAP_SHARED | MmapFlags::MAP_SHARED_VALIDATE) => true,
+            _ => return Err(LinuxError::EINVAL),
+        };
 
+        // Only regular files can be mapped, from a page-aligned offset.
+        let file = if map_flags.contains(MmapFlags::MAP_ANONYMOUS) {
+            None
+        } else {
+            let file = get_file(fd)?;
+            if !file.as_any().is::<File>() {
+                return Err(LinuxError::ENODEV);
+            }
+            let file_flags = file.status_flags().get();
+            if !file_flags.readable()
+                || shared
+                    && permission_flags.contains(MmapProt::PROT_WRITE)
+                    && !file_flags.writable()
+            {
+                return Err(LinuxError::EACCES);
+            }
+            if offset < 0 || !memory_addr::is_aligned_4k(offset as usize) {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(file)
+        };
+
+        if length == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let curr = current();
+        let curr_ext = curr.task_ext();
+        let aspace = curr_ext.process.aspace();
+        let mut aspace = aspace.lock();
+
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE_4K)
+            .ok_or(LinuxError::ENOMEM)?;
         let start_addr = if map_flags.contains(MmapFlags::MAP_FIXED) {
-            VirtAddr::from(addr as usize)
+            // The new mapping replaces the previous ones, which are kept if
+            // it cannot be added.
+            let start_addr = VirtAddr::from(addr as usize);
+            aspace.check_range(start_addr, length)?;
+            aspace.unmap(start_addr, length)?;
+            start_addr
         } else {
             aspace
-                .find_free_area(
-                    VirtAddr::from(addr as usize),
-                    length,
-                    VirtAddrRange::new(aspace.base(), aspace.end()),
-                )
-                .or(aspace.find_free_area(
-                    aspace.base(),
-                    length,
-                    VirtAddrRange::new(aspace.base(), aspace.end()),
-                ))
+                .find_free_area(VirtAddr::from(addr as usize), length)
                 .ok_or(LinuxError::ENOMEM)?
         };
 
-        aspace.map_alloc(start_addr, length, permission_flags.into(), false)?;
+        match file {
+            Some(file) => aspace.map_file(
+                start_addr,
+                length,
+                permission_flags.into(),
+                shared,
+                file,
+                offset as u64,
+            )?,
+            None => aspace.map(start_addr, length, permission_flags.into(), shared, false)?,
+        }
 
         Ok(start_addr.as_usize())
     })
 }
+
+/// Remove the mappings of `[addr, addr + length)`, splitting those which are
+/// only partly in it.
+///
+/// # Arguments
+/// * `addr` - The start of the range, which must be page-aligned
+/// * `length` - The size of the range, rounded up to a multiple of the page
+///   size, which must not be 0
+pub(crate) fn sys_munmap(addr: usize, length: usize) -> isize {
+    syscall_body!(sys_munmap, {
+        if length == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE_4K)
+            .ok_or(LinuxError::EINVAL)?;
+        let curr = current();
+        let aspace = curr.task_ext().process.aspace();
+        let mut aspace = aspace.lock();
+        aspace.unmap(VirtAddr::from(addr), length)?;
+        Ok(0)
+    })
+}
+
+/// Change the permissions of the mappings of `[addr, addr + length)`,
+/// splitting those which are only partly in it.
+///
+/// # Arguments
+/// * `addr` - The start of the range, which must be page-aligned
+/// * `length` - The size of the range, rounded up to a multiple of the page
+///   size, which must be entirely mapped
+/// * `prot` - The new permissions, `PROT_NONE` or a combination of
+///   `PROT_READ`, `PROT_WRITE` and `PROT_EXEC`
+pub(crate) fn sys_mprotect(addr: usize, length: usize, prot: i32) -> isize {
+    syscall_body!(sys_mprotect, {
+        let permission_flags = MmapProt::from_bits(prot).ok_or(LinuxError::EINVAL)?;
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE_4K)
+            .ok_or(LinuxError::ENOMEM)?;
+        if length == 0 {
+            return if memory_addr::is_aligned_4k(addr) {
+                Ok(0)
+            } else {
+                Err(LinuxError::EINVAL)
+            };
+        }
+        let curr = current();
+        let aspace = curr.task_ext().process.aspace();
+        let mut aspace = aspace.lock();
+        aspace.protect(VirtAddr::from(addr), length, permission_flags.into())?;
+        Ok(0)
+    })
+}
+
+/// The flags of `msync`
+const MS_ASYNC: i32 = 1;
+const MS_INVALIDATE: i32 = 2;
+const MS_SYNC: i32 = 4;
+
+/// Write the pages of the shared file mappings of `[addr, addr + length)`
+/// back to their files.
+///
+/// # Arguments
+/// * `addr` - The start of the range, which must be page-aligned
+/// * `length` - The size of the range, rounded up to a multiple of the page
+///   size, which must be entirely mapped
+/// * `flags` - `MS_ASYNC` or `MS_SYNC`, which both write the pages back
+///   before returning, optionally with `MS_INVALIDATE`, which has no effect
+///   as the mappings share the cached pages of the files
+pub(crate) fn sys_msync(addr: usize, length: usize, flags: i32) -> isize {
+    syscall_body!(sys_msync, {
+        if flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+            || flags & MS_ASYNC != 0 && flags & MS_SYNC != 0
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let length = length
+            .checked_next_multiple_of(PAGE_SIZE_4K)
+            .ok_or(LinuxError::ENOMEM)?;
+        let curr = current();
+        let aspace = curr.task_ext().process.aspace();
+        let aspace = aspace.lock();
+        aspace.sync(VirtAddr::from(addr), length)?;
+        Ok(0)
+    })
+}
+
+/// The flags of `mremap`
+const MREMAP_MAYMOVE: u32 = 1;
+const MREMAP_FIXED: u32 = 2;
+
+/// Resize a mapping, moving it if needed and allowed.
+///
+/// # Arguments
+/// * `old_address` - The start of the part of a mapping to resize, which
+///   must be page-aligned
+/// * `old_size` - The size of the part, rounded up to a multiple of the page
+///   size, which must be in a single mapping
+/// * `new_size` - The new size, rounded up to a multiple of the page size,
+///   which must not be 0
+/// * `flags` - `MREMAP_MAYMOVE` to move the part if it cannot grow in
+///   place, and `MREMAP_FIXED` with it to move it to `new_address`
+/// * `new_address` - Where to move the part with `MREMAP_FIXED`, replacing
+///   the mappings there
+///
+/// Returns the new start of the part.
+pub(crate) fn sys_mremap(
+    old_address: usize,
+    old_size: usize,
+    new_size: usize,
+    flags: u32,
+    new_address: usize,
+) -> usize {
+    syscall_body!(sys_mremap, {
+        if flags & !(MREMAP_MAYMOVE | MREMAP_FIXED) != 0
+            || flags & MREMAP_FIXED != 0 && flags & MREMAP_MAYMOVE == 0
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let old_size = old_size
+            .checked_next_multiple_of(PAGE_SIZE_4K)
+            .ok_or(LinuxError::EINVAL)?;
+        let new_size = new_size
+            .checked_next_multiple_of(PAGE_SIZE_4K)
+            .ok_or(LinuxError::EINVAL)?;
+        // Duplicating a shared mapping with an `old_size` of 0 is not
+        // supported.
+        if old_size == 0 || new_size == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let new_start = (flags & MREMAP_FIXED != 0).then(|| VirtAddr::from(new_address));
+        let curr = current();
+        let aspace = curr.task_ext().process.aspace();
+        let mut aspace = aspace.lock();
+        let start = aspace.remap(
+            VirtAddr::from(old_address),
+            old_size,
+            new_size,
+            flags & MREMAP_MAYMOVE != 0,
+            new_start,
+        )?;
+        Ok(start.as_usize())
+    })
+}
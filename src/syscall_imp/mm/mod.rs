@@ -1,3 +1,5 @@
+mod brk;
 mod mmap;
 
+pub(crate) use self::brk::*;
 pub(crate) use self::mmap::*;
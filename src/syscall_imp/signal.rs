@@ -0,0 +1,451 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{mem::size_of, time::Duration};
+
+use arceos_posix_api as api;
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef, WaitQueue};
+
+use super::time::timespec_to_duration;
+use crate::{
+    file::{add_file, get_file},
+    signal::{
+        self, SigAction, SigActionFlags, SigInfo, SigSet, SigStack, SignalFd, NSIG, SI_TKILL,
+        SI_USER,
+    },
+    syscall_body,
+    task::{
+        get_process, get_thread, init_process, process_group, processes, wait_interruptible,
+        wait_interruptible_timeout, Pid,
+    },
+    uaccess::{get_user, put_user},
+};
+
+/// The layout of `struct sigaction` of the kernel.
+///
+/// There is no `sa_restorer` on riscv64, whose handlers always return to the
+/// signal trampoline.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct KSigAction {
+    handler: usize,
+    flags: u64,
+    #[cfg(not(target_arch = "riscv64"))]
+    restorer: usize,
+    mask: SigSet,
+}
+
+impl From<KSigAction> for SigAction {
+    fn from(act: KSigAction) -> Self {
+        Self {
+            handler: act.handler,
+            flags: SigActionFlags::from_bits_truncate(act.flags),
+            #[cfg(not(target_arch = "riscv64"))]
+            restorer: act.restorer,
+            #[cfg(target_arch = "riscv64")]
+            restorer: 0,
+            mask: act.mask & !SigSet::UNCATCHABLE,
+        }
+    }
+}
+
+impl From<SigAction> for KSigAction {
+    fn from(action: SigAction) -> Self {
+        Self {
+            handler: action.handler,
+            flags: action.flags.bits(),
+            #[cfg(not(target_arch = "riscv64"))]
+            restorer: action.restorer,
+            mask: action.mask,
+        }
+    }
+}
+
+/// The `how` of sys_rt_sigprocmask
+const SIG_BLOCK: i32 = 0;
+const SIG_UNBLOCK: i32 = 1;
+const SIG_SETMASK: i32 = 2;
+
+/// The flags of `signalfd4`, the same as `O_NONBLOCK` and `O_CLOEXEC`.
+const SFD_NONBLOCK: u32 = 0o4000;
+const SFD_CLOEXEC: u32 = 0o200_0000;
+
+/// Checks the size of `sigset_t` passed by user space.
+fn check_sigsetsize(sigsetsize: usize) -> LinuxResult {
+    if sigsetsize != size_of::<SigSet>() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(())
+}
+
+/// Blocks the signals in `mask` instead of the current ones until the system
+/// call returns, if `mask` is not null, as `ppoll`, `pselect6` and
+/// `epoll_pwait` do.
+pub(crate) fn set_temporary_mask(mask: *const SigSet, sigsetsize: usize) -> LinuxResult {
+    if mask.is_null() {
+        return Ok(());
+    }
+    check_sigsetsize(sigsetsize)?;
+    let mask = get_user(mask)?;
+    current().task_ext().signals.set_temporary_mask(mask);
+    Ok(())
+}
+
+/// Checks a signal number, where 0 only checks that the target exists.
+fn check_signo(signo: u32) -> LinuxResult {
+    if signo > NSIG {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(())
+}
+
+/// Examine and change the action of a signal.
+///
+/// # Arguments
+/// * `signo` - The signal, which cannot be `SIGKILL` or `SIGSTOP` if `act` is
+///   not null
+/// * `act` - The new action, may be null
+/// * `oldact` - Where to store the old action, may be null
+/// * `sigsetsize` - The size of `sigset_t`, which must be 8
+pub(crate) fn sys_rt_sigaction(
+    signo: u32,
+    act: *const KSigAction,
+    oldact: *mut KSigAction,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_rt_sigaction, {
+        debug!(
+            "sys_rt_sigaction <= signo: {}, act: {:p}, oldact: {:p}",
+            signo, act, oldact
+        );
+        check_sigsetsize(sigsetsize)?;
+        if signo == 0 || signo > NSIG {
+            return Err(LinuxError::EINVAL);
+        }
+        let action = if act.is_null() {
+            None
+        } else {
+            if SigSet::UNCATCHABLE.contains(signo) {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(SigAction::from(get_user(act)?))
+        };
+
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let signal_actions = process.signal_actions();
+        let mut actions = signal_actions.lock();
+        let old = actions.get(signo);
+        if let Some(action) = action {
+            actions.set(signo, action);
+            drop(actions);
+            // Ignoring a signal discards its pending instances.
+            if action.is_ignored(signo) {
+                signal::discard_pending(process, SigSet::single(signo));
+            }
+        } else {
+            drop(actions);
+        }
+        if !oldact.is_null() {
+            put_user(oldact, old.into())?;
+        }
+        Ok(0)
+    })
+}
+
+/// Examine and change the blocked signals of the calling thread.
+///
+/// # Arguments
+/// * `how` - How `set` changes the mask: `SIG_BLOCK`, `SIG_UNBLOCK` or
+///   `SIG_SETMASK`
+/// * `set` - The signals to change, may be null to only get the mask
+/// * `oldset` - Where to store the old mask, may be null
+/// * `sigsetsize` - The size of `sigset_t`, which must be 8
+pub(crate) fn sys_rt_sigprocmask(
+    how: i32,
+    set: *const SigSet,
+    oldset: *mut SigSet,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_rt_sigprocmask, {
+        check_sigsetsize(sigsetsize)?;
+        let curr = current();
+        let signals = &curr.task_ext().signals;
+        let old = signals.mask();
+        if !set.is_null() {
+            let set = get_user(set)?;
+            let mask = match how {
+                SIG_BLOCK => old | set,
+                SIG_UNBLOCK => old & !set,
+                SIG_SETMASK => set,
+                _ => return Err(LinuxError::EINVAL),
+            };
+            signals.set_mask(mask);
+        }
+        if !oldset.is_null() {
+            put_user(oldset, old)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Get the signals pending for the calling thread while they are blocked.
+pub(crate) fn sys_rt_sigpending(set: *mut SigSet, sigsetsize: usize) -> isize {
+    syscall_body!(sys_rt_sigpending, {
+        check_sigsetsize(sigsetsize)?;
+        let curr = current();
+        let ext = curr.task_ext();
+        let pending = ext.signals.pending.set() | ext.process.pending_signals.set();
+        put_user(set, pending & ext.signals.mask())?;
+        Ok(0)
+    })
+}
+
+/// Send a signal to processes.
+///
+/// # Arguments
+/// * `pid` - The process ID, 0 for the process group of the caller, -1 for
+///   all processes except the caller and the init process, or `-pgid` for
+///   the process group `pgid`
+/// * `signo` - The signal, or 0 to only check that the processes exist
+pub(crate) fn sys_kill(pid: i32, signo: u32) -> isize {
+    syscall_body!(sys_kill, {
+        debug!("sys_kill <= pid: {}, signo: {}", pid, signo);
+        check_signo(signo)?;
+        let curr = current();
+        let curr_process = &curr.task_ext().process;
+        let targets: Vec<_> = match pid {
+            pid if pid > 0 => get_process(pid as Pid).into_iter().collect(),
+            -1 => {
+                let init_pid = init_process().pid();
+                processes()
+                    .into_iter()
+                    .filter(|p| p.pid() != init_pid && p.pid() != curr_process.pid())
+                    .collect()
+            }
+            pid => {
+                let pgid = if pid == 0 {
+                    curr_process.pgid()
+                } else {
+                    pid.unsigned_abs() as Pid
+                };
+                process_group(pgid)
+            }
+        };
+        if targets.is_empty() {
+            return Err(LinuxError::ESRCH);
+        }
+        if signo != 0 {
+            let info = SigInfo::new(signo, SI_USER).with_sender(curr_process.pid());
+            for process in targets {
+                signal::send_process_signal(&process, info);
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// Sends the signal of `info` to the thread `tid`, which must belong to the
+/// process `tgid` if it is `Some`.
+fn do_tkill(tgid: Option<Pid>, tid: i32, info: SigInfo) -> LinuxResult<isize> {
+    check_signo(info.signo())?;
+    if tid <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let thread = get_thread(tid as u64).ok_or(LinuxError::ESRCH)?;
+    let process = &thread.task_ext().process;
+    if tgid.is_some_and(|tgid| tgid != process.pid()) {
+        return Err(LinuxError::ESRCH);
+    }
+    if info.signo() != 0 {
+        signal::send_thread_signal(&thread, info);
+    }
+    Ok(0)
+}
+
+/// Returns the information of a signal sent by `tkill`.
+fn tkill_info(signo: u32) -> SigInfo {
+    SigInfo::new(signo, SI_TKILL).with_sender(current().task_ext().process.pid())
+}
+
+/// Send a signal to a thread.
+pub(crate) fn sys_tkill(tid: i32, signo: u32) -> isize {
+    syscall_body!(sys_tkill, do_tkill(None, tid, tkill_info(signo)))
+}
+
+/// Send a signal to the thread `tid` of the process `tgid`.
+pub(crate) fn sys_tgkill(tgid: i32, tid: i32, signo: u32) -> isize {
+    syscall_body!(sys_tgkill, {
+        if tgid <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        do_tkill(Some(tgid as Pid), tid, tkill_info(signo))
+    })
+}
+
+/// Reads the information of a signal `signo` queued by user space to the
+/// process `tgid`, which cannot pretend to be sent by `kill` or by the
+/// kernel unless it is sent to the caller itself.
+fn get_queued_info(tgid: i32, signo: u32, uinfo: *const SigInfo) -> LinuxResult<SigInfo> {
+    check_signo(signo)?;
+    let mut info = get_user(uinfo)?;
+    let sender = current().task_ext().process.pid();
+    if (info.code >= 0 || info.code == SI_TKILL) && tgid as Pid != sender {
+        return Err(LinuxError::EPERM);
+    }
+    info.signo = signo as i32;
+    Ok(info)
+}
+
+/// Queue a signal with its information to a process.
+///
+/// # Arguments
+/// * `tgid` - The process
+/// * `signo` - The signal, or 0 to only check that the process exists
+/// * `uinfo` - The information of the signal, whose `si_signo` is ignored
+pub(crate) fn sys_rt_sigqueueinfo(tgid: i32, signo: u32, uinfo: *const SigInfo) -> isize {
+    syscall_body!(sys_rt_sigqueueinfo, {
+        let info = get_queued_info(tgid, signo, uinfo)?;
+        let process = get_process(tgid as Pid).ok_or(LinuxError::ESRCH)?;
+        if signo != 0 {
+            signal::send_process_signal(&process, info);
+        }
+        Ok(0)
+    })
+}
+
+/// Queue a signal with its information to the thread `tid` of the process
+/// `tgid`.
+pub(crate) fn sys_rt_tgsigqueueinfo(
+    tgid: i32,
+    tid: i32,
+    signo: u32,
+    uinfo: *const SigInfo,
+) -> isize {
+    syscall_body!(sys_rt_tgsigqueueinfo, {
+        if tgid <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let info = get_queued_info(tgid, signo, uinfo)?;
+        do_tkill(Some(tgid as Pid), tid, info)
+    })
+}
+
+/// Replace the signal mask of the calling thread with `mask` and wait for a
+/// signal, restoring the mask once the signal is handled.
+///
+/// Always returns `EINTR`.
+pub(crate) fn sys_rt_sigsuspend(mask: *const SigSet, sigsetsize: usize) -> isize {
+    syscall_body!(sys_rt_sigsuspend, {
+        check_sigsetsize(sigsetsize)?;
+        let mask = get_user(mask)?;
+        current().task_ext().signals.set_temporary_mask(mask);
+        // Nobody wakes up the queue: only a signal ends the wait, with
+        // `EINTR`.
+        wait_interruptible(&WaitQueue::new(), || false)?;
+        Ok(0)
+    })
+}
+
+/// Wait for one of the signals in `set` to be pending, and remove it from
+/// the pending signals without handling it.
+///
+/// # Arguments
+/// * `set` - The awaited signals, usually blocked by the calling thread
+/// * `info` - Where to store the information of the signal, may be null
+/// * `timeout` - The longest time to wait, or null to wait indefinitely
+/// * `sigsetsize` - The size of `sigset_t`, which must be 8
+///
+/// Returns the signal, `EAGAIN` if the timeout expired, or `EINTR` if
+/// another signal was handled.
+pub(crate) fn sys_rt_sigtimedwait(
+    set: *const SigSet,
+    info: *mut SigInfo,
+    timeout: *const api::ctypes::timespec,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_rt_sigtimedwait, {
+        check_sigsetsize(sigsetsize)?;
+        let set = get_user(set)? & !SigSet::UNCATCHABLE;
+        let timeout = if timeout.is_null() {
+            None
+        } else {
+            Some(timespec_to_duration(get_user(timeout)?)?)
+        };
+
+        let mut sig = signal::dequeue_signal(set);
+        if sig.is_none() && timeout != Some(Duration::ZERO) {
+            let curr = current();
+            let signals = &curr.task_ext().signals;
+            let mask = signals.mask();
+            // The awaited signals are unblocked while waiting, so that they
+            // interrupt the wait.
+            signals.set_mask(mask & !set);
+            let res = wait_interruptible_timeout(&WaitQueue::new(), timeout, || false);
+            signals.set_mask(mask);
+            sig = signal::dequeue_signal(set);
+            if sig.is_none() && res == Err(LinuxError::EINTR) {
+                return Err(LinuxError::EINTR);
+            }
+        }
+        let sig = sig.ok_or(LinuxError::EAGAIN)?;
+        if !info.is_null() {
+            put_user(info, sig)?;
+        }
+        Ok(sig.signo() as isize)
+    })
+}
+
+/// Get and set the alternate signal stack of the calling thread.
+///
+/// # Arguments
+/// * `ss` - The new stack, may be null to only get the stack
+/// * `old_ss` - Where to store the old stack, may be null
+pub(crate) fn sys_sigaltstack(ss: *const SigStack, old_ss: *mut SigStack) -> isize {
+    syscall_body!(sys_sigaltstack, {
+        let new = if ss.is_null() {
+            None
+        } else {
+            Some(get_user(ss)?)
+        };
+        let old = signal::sigaltstack(new)?;
+        if !old_ss.is_null() {
+            put_user(old_ss, old)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Return from a signal handler, restoring the context saved in the signal
+/// frame on the user stack.
+pub(crate) fn sys_rt_sigreturn() -> isize {
+    signal::sigreturn()
+}
+
+/// Create a file to read the pending signals in `mask`, or change the mask
+/// of the signalfd `fd`.
+///
+/// # Arguments
+/// * `fd` - The signalfd to change, or -1 to create one
+/// * `mask` - The signals to read
+/// * `sizemask` - The size of `sigset_t`, which must be 8
+/// * `flags` - `SFD_NONBLOCK` and `SFD_CLOEXEC` for a new file
+pub(crate) fn sys_signalfd4(fd: i32, mask: *const SigSet, sizemask: usize, flags: u32) -> isize {
+    syscall_body!(sys_signalfd4, {
+        check_sigsetsize(sizemask)?;
+        if flags & !(SFD_NONBLOCK | SFD_CLOEXEC) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let mask = get_user(mask)?;
+        if fd != -1 {
+            let file = get_file(fd)?;
+            let signalfd = file
+                .as_any()
+                .downcast_ref::<SignalFd>()
+                .ok_or(LinuxError::EINVAL)?;
+            signalfd.set_mask(mask);
+            return Ok(fd as isize);
+        }
+        let signalfd = SignalFd::new(mask, flags & SFD_NONBLOCK != 0);
+        Ok(add_file(Arc::new(signalfd), flags & SFD_CLOEXEC != 0)? as isize)
+    })
+}
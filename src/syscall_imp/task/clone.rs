@@ -0,0 +1,229 @@
+use alloc::sync::Arc;
+
+use axerrno::LinuxError;
+use axhal::arch::{TrapFrame, UspaceContext};
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    signal::SIGCHLD,
+    syscall_body,
+    task::{new_user_task, Process, TaskExt},
+    uaccess::put_user,
+};
+
+bitflags::bitflags! {
+    /// Flags for sys_clone
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/sched.h>
+    #[derive(Debug, Clone, Copy)]
+    struct CloneFlags: u32 {
+        /// Share the virtual memory.
+        const CLONE_VM = 0x0000_0100;
+        /// Share the filesystem information (root, cwd, umask).
+        const CLONE_FS = 0x0000_0200;
+        /// Share the file descriptor table.
+        const CLONE_FILES = 0x0000_0400;
+        /// Share the signal handlers.
+        const CLONE_SIGHAND = 0x0000_0800;
+        /// Suspend the parent until the child exits or calls `execve`.
+        const CLONE_VFORK = 0x0000_4000;
+        /// The child has the same parent as the caller.
+        const CLONE_PARENT = 0x0000_8000;
+        /// The child is put in the same thread group as the caller.
+        const CLONE_THREAD = 0x0001_0000;
+        /// Set the TLS (thread local storage) of the child.
+        const CLONE_SETTLS = 0x0008_0000;
+        /// Store the child thread ID in the parent's memory.
+        const CLONE_PARENT_SETTID = 0x0010_0000;
+        /// Clear the child thread ID in the child's memory when the child exits.
+        const CLONE_CHILD_CLEARTID = 0x0020_0000;
+        /// Store the child thread ID in the child's memory.
+        const CLONE_CHILD_SETTID = 0x0100_0000;
+    }
+}
+
+/// The mask of the signal sent to the parent when the child exits.
+const CSIGNAL: u32 = 0xff;
+
+/// Create a new thread or process, which starts in user space right after
+/// the `clone` call with a return value of 0.
+///
+/// A child process created with `CLONE_VM` shares the address space of the
+/// caller until it calls `execve`, and gets a copy-on-write duplicate of it
+/// otherwise. With `CLONE_VFORK` the caller is suspended until the child
+/// exits or calls `execve`, which is what `vfork` and `posix_spawn` callers
+/// rely on.
+///
+/// # Arguments
+/// * `tf` - The trap frame of the caller
+/// * `flags` - The clone flags, with the exit signal in the lowest byte
+/// * `stack` - The user stack of the child, or 0 to use the same stack pointer as the caller
+/// * `ptid` - Where to store the child thread ID in the parent's memory (`CLONE_PARENT_SETTID`)
+/// * `tls` - The TLS of the child (`CLONE_SETTLS`)
+/// * `ctid` - Where to store or clear the child thread ID in the child's memory
+pub(crate) fn sys_clone(
+    tf: &TrapFrame,
+    flags: u32,
+    stack: usize,
+    ptid: *mut i32,
+    tls: usize,
+    ctid: *mut i32,
+) -> isize {
+    syscall_body!(sys_clone, {
+        let clone_flags = CloneFlags::from_bits_truncate(flags & !CSIGNAL);
+        debug!(
+            "sys_clone <= flags: {:?}, exit_signal: {}, stack: {:#x}, ptid: {:p}, tls: {:#x}, ctid: {:p}",
+            clone_flags,
+            flags & CSIGNAL,
+            stack,
+            ptid,
+            tls,
+            ctid
+        );
+        if (clone_flags.contains(CloneFlags::CLONE_THREAD)
+            && !clone_flags.contains(CloneFlags::CLONE_SIGHAND))
+            || (clone_flags.contains(CloneFlags::CLONE_SIGHAND)
+                && !clone_flags.contains(CloneFlags::CLONE_VM))
+        {
+            return Err(LinuxError::EINVAL);
+        }
+
+        let curr = current();
+        // A new process would outlive the process of the caller, which is
+        // exiting.
+        if curr.task_ext().is_killed() {
+            return Err(LinuxError::EINTR);
+        }
+        let mut uctx = UspaceContext::from(tf);
+        if stack != 0 {
+            uctx.set_sp(stack);
+        }
+        // Skip the `ecall` instruction, as `sepc` is advanced only after the
+        // syscall handler returns.
+        #[cfg(target_arch = "riscv64")]
+        uctx.set_ip(uctx.get_ip() + 4);
+        uctx.set_retval(0);
+
+        let set_child_tid = if clone_flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
+            ctid as usize
+        } else {
+            0
+        };
+        let mut task = new_user_task(curr.name(), set_child_tid);
+        let tid = task.id().as_u64();
+        // Done before the child process exists, so that a failure does not
+        // leave it behind.
+        if clone_flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
+            put_user(ptid, tid as i32)?;
+        }
+        if clone_flags.contains(CloneFlags::CLONE_SETTLS) {
+            #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+            task.ctx_mut().set_tls(tls.into());
+            #[cfg(target_arch = "riscv64")]
+            {
+                uctx.regs.tp = tls;
+            }
+        }
+
+        let curr_process = &curr.task_ext().process;
+        let process = if clone_flags.contains(CloneFlags::CLONE_THREAD) {
+            curr_process.clone()
+        } else {
+            let parent = if clone_flags.contains(CloneFlags::CLONE_PARENT) {
+                curr_process.parent()
+            } else {
+                Some(curr_process.clone())
+            };
+            let aspace = if clone_flags.contains(CloneFlags::CLONE_VM) {
+                curr_process.aspace()
+            } else {
+                Arc::new(Mutex::new(curr_process.aspace().lock().fork()?))
+            };
+            let signal_actions = if clone_flags.contains(CloneFlags::CLONE_SIGHAND) {
+                curr_process.signal_actions()
+            } else {
+                Arc::new(Mutex::new(curr_process.signal_actions().lock().clone()))
+            };
+            let fd_table = if clone_flags.contains(CloneFlags::CLONE_FILES) {
+                curr_process.fd_table()
+            } else {
+                Arc::new(curr_process.fd_table().fork())
+            };
+            let fs_context = if clone_flags.contains(CloneFlags::CLONE_FS) {
+                curr_process.fs_context()
+            } else {
+                Arc::new(curr_process.fs_context().fork())
+            };
+            let process = Process::new(
+                tid,
+                parent.as_ref(),
+                aspace,
+                signal_actions,
+                fd_table,
+                fs_context,
+                flags & CSIGNAL,
+            );
+            *process.rlimits.lock() = curr_process.rlimits.lock().clone();
+            process
+        };
+        task.ctx_mut()
+            .set_page_table_root(process.aspace().lock().page_table_root());
+
+        let task_ext = TaskExt::new(uctx, process.clone());
+        task_ext.signals.set_mask(curr.task_ext().signals.mask());
+        task_ext.set_nice(curr.task_ext().nice());
+        // A thread sharing the memory cannot share the alternate signal stack,
+        // unless the parent is suspended as for `vfork`.
+        if !clone_flags.contains(CloneFlags::CLONE_VM)
+            || clone_flags.contains(CloneFlags::CLONE_VFORK)
+        {
+            task_ext.signals.set_stack(curr.task_ext().signals.stack());
+        }
+        if clone_flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+            task_ext.set_clear_child_tid(ctid as _);
+        }
+        task.init_task_ext(task_ext);
+        if let Err(e) = process.spawn_thread(task) {
+            if !clone_flags.contains(CloneFlags::CLONE_THREAD) {
+                // The child would never exit to be reaped by `wait4`.
+                process.forget();
+            }
+            return Err(e);
+        }
+
+        if clone_flags.contains(CloneFlags::CLONE_VFORK) {
+            // The wait only fails if the caller is killed, which then exits
+            // instead of returning, while the child keeps running.
+            let _ = process.wait_vfork_done();
+        }
+        Ok(tid)
+    })
+}
+
+/// Create a child process, which is equivalent to `clone(SIGCHLD, 0)`.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_fork(tf: &TrapFrame) -> isize {
+    sys_clone(
+        tf,
+        SIGCHLD,
+        0,
+        core::ptr::null_mut(),
+        0,
+        core::ptr::null_mut(),
+    )
+}
+
+/// Create a child process sharing the memory of the caller, and suspend the
+/// caller until the child exits or calls `execve`.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_vfork(tf: &TrapFrame) -> isize {
+    sys_clone(
+        tf,
+        CloneFlags::CLONE_VM.bits() | CloneFlags::CLONE_VFORK.bits() | SIGCHLD,
+        0,
+        core::ptr::null_mut(),
+        0,
+        core::ptr::null_mut(),
+    )
+}
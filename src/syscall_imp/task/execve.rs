@@ -0,0 +1,123 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::ffi::c_char;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::UspaceContext;
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    fs::{self, File, OpenFlags, AT_FDCWD},
+    loader,
+    mm::{self, UserSpace},
+    signal::SigStack,
+    syscall_body,
+    uaccess::{get_user_str, get_user_str_vec},
+};
+
+/// Execute the program at `path`, replacing the image of the calling process.
+///
+/// On success it does not return: the new program is loaded by the ELF loader
+/// into a new address space, which replaces the old one, and the caller jumps
+/// to its entry point with a stack built from `argv` and `envp`. The old
+/// address space is kept if it is shared with another process, e.g. the
+/// parent of a `vfork` child.
+///
+/// The program is the regular file at `path`, which is resolved like the
+/// paths of the other syscalls. The apps linked into the kernel are installed
+/// in the root directory at boot.
+///
+/// Fails with `ENOENT` if there is no file at `path`, `EACCES` if it is not
+/// a regular file, or `ENOEXEC` if it is not an ELF file for the current
+/// architecture.
+///
+/// # Arguments
+/// * `path` - The path of the program
+/// * `argv` - The null-terminated array of arguments, may be null
+/// * `envp` - The null-terminated array of environment variables, may be null
+pub(crate) fn sys_execve(
+    path: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> isize {
+    syscall_body!(sys_execve, do_execve(path, argv, envp))
+}
+
+/// Returns only on errors, see [`sys_execve`].
+fn do_execve(
+    path: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> LinuxResult<isize> {
+    let path = get_user_str(path)?;
+    let mut args = get_user_str_vec(argv)?;
+    let envs = get_user_str_vec(envp)?;
+    debug!(
+        "sys_execve <= path: {:?}, args: {:?}, envs: {:?}",
+        path, args, envs
+    );
+    if args.is_empty() {
+        // Some programs (e.g. the nimbos apps) pass no arguments at all.
+        args = vec![path.clone()];
+    }
+
+    let curr = current();
+    let process = &curr.task_ext().process;
+    // Load the new image before tearing down the old one, so that the caller
+    // can still handle the errors.
+    let data = read_program(&path)?;
+    let mut aspace = UserSpace::new()?;
+    let elf_info = loader::load_elf(&data, aspace.base()).map_err(|_| LinuxError::ENOEXEC)?;
+    let (entry, ustack_top) = mm::map_elf(&mut aspace, &elf_info, &args, &envs)?;
+    // Freed now, as entering user space does not return.
+    drop(elf_info);
+    drop(data);
+
+    // The other threads of the process are terminated, as they would run
+    // the new image otherwise.
+    process.kill_other_threads();
+    process.wait_other_threads_exited()?;
+    process.set_aspace(Arc::new(Mutex::new(aspace)));
+
+    // The handlers and the alternate signal stack are gone with the old
+    // image, while the signal mask and the pending signals are kept. The
+    // handlers are reset in a copy, as they may be shared with other
+    // processes.
+    let mut signal_actions = process.signal_actions().lock().clone();
+    signal_actions.reset_handlers();
+    process.set_signal_actions(Arc::new(Mutex::new(signal_actions)));
+    curr.task_ext().signals.set_stack(SigStack::disabled());
+    // The file descriptor table stops being shared with other processes
+    // before the descriptors with `FD_CLOEXEC` are closed.
+    let fd_table = Arc::new(process.fd_table().fork());
+    fd_table.close_on_exec();
+    process.set_fd_table(fd_table);
+    process.notify_vfork_done();
+
+    let uctx = UspaceContext::new(entry.into(), ustack_top, 0);
+    let kstack_top = curr.kernel_stack_top().unwrap();
+    info!(
+        "Enter user space after execve: path={:?}, entry={:#x}, ustack={:#x}",
+        path, entry, ustack_top,
+    );
+    unsafe { uctx.enter_uspace(kstack_top) }
+}
+
+/// Reads the program at `path`, which must be a regular file.
+fn read_program(path: &str) -> LinuxResult<Vec<u8>> {
+    let path = fs::resolve_path(AT_FDCWD, path, true)?;
+    let file = fs::open(&path, OpenFlags::empty())?;
+    if !file.as_any().is::<File>() {
+        return Err(LinuxError::EACCES);
+    }
+    let mut data = vec![0; file.stat()?.size as usize];
+    let mut len = 0;
+    while len < data.len() {
+        match file.read_at(len as u64, &mut data[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    data.truncate(len);
+    Ok(data)
+}
@@ -0,0 +1,186 @@
+use core::{mem::size_of, time::Duration};
+
+use arceos_posix_api as api;
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+
+use super::super::time::timespec_to_duration;
+use crate::{
+    futex::{self, RobustListHead},
+    syscall_body,
+    task::get_thread,
+    uaccess::{get_user, put_user},
+};
+
+/// The futex operations
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/futex.h>
+const FUTEX_WAIT: u32 = 0;
+const FUTEX_WAKE: u32 = 1;
+const FUTEX_REQUEUE: u32 = 3;
+const FUTEX_CMP_REQUEUE: u32 = 4;
+const FUTEX_WAKE_OP: u32 = 5;
+const FUTEX_LOCK_PI: u32 = 6;
+const FUTEX_UNLOCK_PI: u32 = 7;
+const FUTEX_TRYLOCK_PI: u32 = 8;
+const FUTEX_WAIT_BITSET: u32 = 9;
+const FUTEX_WAKE_BITSET: u32 = 10;
+const FUTEX_LOCK_PI2: u32 = 13;
+
+/// The futex is only used by the threads of the calling process.
+const FUTEX_PRIVATE_FLAG: u32 = 128;
+/// The timeout of `FUTEX_WAIT_BITSET` and `FUTEX_LOCK_PI2` is measured by
+/// `CLOCK_REALTIME` instead of `CLOCK_MONOTONIC`.
+const FUTEX_CLOCK_REALTIME: u32 = 256;
+
+/// The bitset matching all waiters.
+const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Reads the absolute `timeout` measured by `CLOCK_REALTIME` if `realtime`
+/// is set, or `CLOCK_MONOTONIC` otherwise, and returns the time left.
+fn read_deadline(
+    timeout: *const api::ctypes::timespec,
+    realtime: bool,
+) -> LinuxResult<Option<Duration>> {
+    if timeout.is_null() {
+        return Ok(None);
+    }
+    let deadline = timespec_to_duration(get_user(timeout)?)?;
+    let now = if realtime {
+        axhal::time::wall_time()
+    } else {
+        axhal::time::monotonic_time()
+    };
+    Ok(Some(deadline.saturating_sub(now)))
+}
+
+/// Wait on, wake up or lock the futex at `uaddr`.
+///
+/// # Arguments
+/// * `uaddr` - The futex word
+/// * `futex_op` - The operation, with `FUTEX_PRIVATE_FLAG` and
+///   `FUTEX_CLOCK_REALTIME` flags
+/// * `val` - The expected value for waits, or the number of waiters to wake up
+/// * `timeout` - The timeout for waits, relative for `FUTEX_WAIT` and absolute
+///   for the others (always measured by `CLOCK_REALTIME` for
+///   `FUTEX_LOCK_PI`). It is the number of waiters to requeue or wake
+///   up on `uaddr2` (`val2`) for `FUTEX_*REQUEUE` and `FUTEX_WAKE_OP`.
+/// * `uaddr2` - The second futex of `FUTEX_*REQUEUE` and `FUTEX_WAKE_OP`
+/// * `val3` - The bitset for `FUTEX_*_BITSET`, the expected value for
+///   `FUTEX_CMP_REQUEUE`, or the encoded operation for `FUTEX_WAKE_OP`
+pub(crate) fn sys_futex(
+    uaddr: *mut u32,
+    futex_op: u32,
+    val: u32,
+    timeout: *const api::ctypes::timespec,
+    uaddr2: *mut u32,
+    val3: u32,
+) -> isize {
+    syscall_body!(sys_futex, {
+        debug!(
+            "sys_futex <= uaddr: {:p}, op: {:#x}, val: {}, timeout: {:p}, uaddr2: {:p}, val3: {:#x}",
+            uaddr, futex_op, val, timeout, uaddr2, val3
+        );
+        let private = futex_op & FUTEX_PRIVATE_FLAG != 0;
+        let realtime = futex_op & FUTEX_CLOCK_REALTIME != 0;
+        let cmd = futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+        if realtime && ![FUTEX_WAIT, FUTEX_WAIT_BITSET, FUTEX_LOCK_PI2].contains(&cmd) {
+            return Err(LinuxError::ENOSYS);
+        }
+        let uaddr = uaddr as usize;
+        let uaddr2 = uaddr2 as usize;
+        let val2 = timeout as usize as u32;
+
+        match cmd {
+            FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+                let bitset = if cmd == FUTEX_WAIT {
+                    FUTEX_BITSET_MATCH_ANY
+                } else {
+                    val3
+                };
+                if bitset == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let timeout = if cmd == FUTEX_WAIT_BITSET {
+                    read_deadline(timeout, realtime)?
+                } else if timeout.is_null() {
+                    None
+                } else {
+                    Some(timespec_to_duration(get_user(timeout)?)?)
+                };
+                futex::wait(uaddr, private, val, timeout, bitset)?;
+                Ok(0)
+            }
+            FUTEX_WAKE | FUTEX_WAKE_BITSET => {
+                let bitset = if cmd == FUTEX_WAKE {
+                    FUTEX_BITSET_MATCH_ANY
+                } else {
+                    val3
+                };
+                if bitset == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                futex::wake(uaddr, private, val as usize, bitset)
+            }
+            FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+                if (val as i32) < 0 || (val2 as i32) < 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let cmp = (cmd == FUTEX_CMP_REQUEUE).then_some(val3);
+                futex::requeue(uaddr, private, val as usize, uaddr2, val2 as usize, cmp)
+            }
+            FUTEX_WAKE_OP => {
+                futex::wake_op(uaddr, private, val as usize, uaddr2, val2 as usize, val3)
+            }
+            FUTEX_LOCK_PI | FUTEX_LOCK_PI2 => {
+                let timeout = read_deadline(timeout, cmd == FUTEX_LOCK_PI || realtime)?;
+                futex::lock_pi(uaddr, private, timeout, false)?;
+                Ok(0)
+            }
+            FUTEX_TRYLOCK_PI => {
+                futex::lock_pi(uaddr, private, None, true)?;
+                Ok(0)
+            }
+            FUTEX_UNLOCK_PI => {
+                futex::unlock_pi(uaddr, private)?;
+                Ok(0)
+            }
+            _ => Err(LinuxError::ENOSYS),
+        }
+    })
+}
+
+/// Set the head of the robust futex list of the calling thread.
+///
+/// # Arguments
+/// * `head` - The head of the list in user space
+/// * `len` - The size of the head, which must be `sizeof(struct robust_list_head)`
+pub(crate) fn sys_set_robust_list(head: *const RobustListHead, len: usize) -> isize {
+    syscall_body!(sys_set_robust_list, {
+        if len != size_of::<RobustListHead>() {
+            return Err(LinuxError::EINVAL);
+        }
+        current().task_ext().set_robust_list_head(head as usize);
+        Ok(0)
+    })
+}
+
+/// Get the head of the robust futex list of a thread.
+///
+/// # Arguments
+/// * `tid` - The thread ID, or 0 for the calling thread
+/// * `head_ptr` - Where to store the head
+/// * `len_ptr` - Where to store the size of the head
+pub(crate) fn sys_get_robust_list(tid: u32, head_ptr: *mut usize, len_ptr: *mut usize) -> isize {
+    syscall_body!(sys_get_robust_list, {
+        let head = if tid == 0 {
+            current().task_ext().robust_list_head()
+        } else {
+            let thread = get_thread(tid as u64).ok_or(LinuxError::ESRCH)?;
+            thread.task_ext().robust_list_head()
+        };
+        put_user(head_ptr, head)?;
+        put_user(len_ptr, size_of::<RobustListHead>())?;
+        Ok(0)
+    })
+}
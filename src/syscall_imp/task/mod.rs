@@ -1,5 +1,17 @@
+mod clone;
+mod execve;
+mod futex;
+mod resource;
 mod schedule;
+mod session;
 mod thread;
+mod wait;
 
+pub(crate) use self::clone::*;
+pub(crate) use self::execve::*;
+pub(crate) use self::futex::*;
+pub(crate) use self::resource::*;
 pub(crate) use self::schedule::*;
+pub(crate) use self::session::*;
 pub(crate) use self::thread::*;
+pub(crate) use self::wait::*;
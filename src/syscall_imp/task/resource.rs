@@ -0,0 +1,80 @@
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    file::NR_OPEN,
+    resource::{RLimit, RLIMIT_NOFILE, RLIM_NLIMITS},
+    syscall_body,
+    task::{get_process, Pid},
+    uaccess::{get_user, put_user},
+};
+
+/// Gets the limit of `resource` of the process `pid`, or of the calling
+/// process if `pid` is 0, and replaces it with `new` if it is not `None`.
+fn do_prlimit(pid: i32, resource: u32, new: Option<RLimit>) -> LinuxResult<RLimit> {
+    if resource >= RLIM_NLIMITS {
+        return Err(LinuxError::EINVAL);
+    }
+    if let Some(new) = new {
+        if new.cur > new.max {
+            return Err(LinuxError::EINVAL);
+        }
+        if resource == RLIMIT_NOFILE && new.max > NR_OPEN as u64 {
+            return Err(LinuxError::EPERM);
+        }
+    }
+    let process = match pid {
+        0 => current().task_ext().process.clone(),
+        pid if pid > 0 => get_process(pid as Pid).ok_or(LinuxError::ESRCH)?,
+        _ => return Err(LinuxError::ESRCH),
+    };
+    let mut rlimits = process.rlimits.lock();
+    let old = rlimits.get(resource);
+    if let Some(new) = new {
+        rlimits.set(resource, new);
+    }
+    Ok(old)
+}
+
+/// Get and set a resource limit of a process.
+///
+/// # Arguments
+/// * `pid` - The process, or 0 for the calling process
+/// * `resource` - The resource, e.g. `RLIMIT_NOFILE`
+/// * `new_limit` - The new limit, may be null to only get the limit
+/// * `old_limit` - Where to store the old limit, may be null
+pub(crate) fn sys_prlimit64(
+    pid: i32,
+    resource: u32,
+    new_limit: *const RLimit,
+    old_limit: *mut RLimit,
+) -> isize {
+    syscall_body!(sys_prlimit64, {
+        let new = if new_limit.is_null() {
+            None
+        } else {
+            Some(get_user(new_limit)?)
+        };
+        let old = do_prlimit(pid, resource, new)?;
+        if !old_limit.is_null() {
+            put_user(old_limit, old)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Get a resource limit of the calling process.
+pub(crate) fn sys_getrlimit(resource: u32, rlim: *mut RLimit) -> isize {
+    syscall_body!(sys_getrlimit, {
+        put_user(rlim, do_prlimit(0, resource, None)?)?;
+        Ok(0)
+    })
+}
+
+/// Set a resource limit of the calling process.
+pub(crate) fn sys_setrlimit(resource: u32, rlim: *const RLimit) -> isize {
+    syscall_body!(sys_setrlimit, {
+        do_prlimit(0, resource, Some(get_user(rlim)?))?;
+        Ok(0)
+    })
+}
@@ -1,12 +1,126 @@
+use alloc::{vec, vec::Vec};
+
 use arceos_posix_api as api;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axtask::{current, AxTaskRef, TaskExtRef, WaitQueue};
+
+use super::super::time::{duration_to_timespec, timespec_to_duration};
+use crate::{
+    syscall_body,
+    task::{self, wait_interruptible_timeout},
+    uaccess::{get_user, put_user},
+};
 
 pub(crate) fn sys_sched_yield() -> i32 {
     api::sys_sched_yield()
 }
 
+/// Sleep for the time in `req`, unless interrupted by a signal.
+///
+/// # Arguments
+/// * `req` - The time to sleep
+/// * `rem` - Where to store the time left if the sleep is interrupted, may be
+///   null
 pub(crate) fn sys_nanosleep(
     req: *const api::ctypes::timespec,
     rem: *mut api::ctypes::timespec,
-) -> i32 {
-    unsafe { api::sys_nanosleep(req, rem) }
+) -> isize {
+    syscall_body!(sys_nanosleep, {
+        let dur = timespec_to_duration(get_user(req)?)?;
+        let deadline = monotonic_time() + dur;
+        // Nobody wakes up the queue: the sleep ends by the timeout, or by a
+        // signal.
+        let wq = WaitQueue::new();
+        match wait_interruptible_timeout(&wq, Some(dur), || false) {
+            Err(LinuxError::ETIMEDOUT) => Ok(0),
+            Err(LinuxError::EINTR) if !rem.is_null() => {
+                let left = deadline.saturating_sub(monotonic_time());
+                put_user(rem, duration_to_timespec(left))?;
+                Err(LinuxError::EINTR)
+            }
+            res => res.map(|_| 0),
+        }
+    })
+}
+
+/// The targets of `setpriority` and `getpriority`.
+const PRIO_PROCESS: i32 = 0;
+const PRIO_PGRP: i32 = 1;
+const PRIO_USER: i32 = 2;
+
+/// The range of nice values.
+const MIN_NICE: i32 = -20;
+const MAX_NICE: i32 = 19;
+
+/// Returns the threads selected by `which` and `who`.
+///
+/// All processes belong to the root user, whose user ID is 0.
+fn priority_targets(which: i32, who: u32) -> LinuxResult<Vec<AxTaskRef>> {
+    let curr = current();
+    let threads = match which {
+        PRIO_PROCESS if who == 0 => vec![curr.as_task_ref().clone()],
+        PRIO_PROCESS => task::get_thread(who as _).into_iter().collect(),
+        PRIO_PGRP => {
+            let pgid = if who == 0 {
+                curr.task_ext().process.pgid()
+            } else {
+                who as _
+            };
+            task::process_group(pgid)
+                .iter()
+                .flat_map(|process| process.threads())
+                .collect()
+        }
+        PRIO_USER if who == 0 => task::processes()
+            .iter()
+            .flat_map(|process| process.threads())
+            .collect(),
+        PRIO_USER => Vec::new(),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    if threads.is_empty() {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(threads)
+}
+
+/// Get the highest priority of the threads selected by `which` and `who`.
+///
+/// As the system call in Linux, returns `20 - nice` so that it is positive.
+///
+/// # Arguments
+/// * `which` - `PRIO_PROCESS` for the thread `who`, `PRIO_PGRP` for the
+///   threads of the process group `who`, or `PRIO_USER` for the threads of
+///   the user `who`, where 0 means the current one
+/// * `who` - The ID of the thread, process group or user
+pub(crate) fn sys_getpriority(which: i32, who: u32) -> isize {
+    syscall_body!(sys_getpriority, {
+        let nice = priority_targets(which, who)?
+            .iter()
+            .map(|thread| thread.task_ext().nice())
+            .min()
+            .unwrap();
+        Ok(20 - nice)
+    })
+}
+
+/// Set the nice value of the threads selected by `which` and `who`.
+///
+/// The value is clamped to the range of nice values. The current thread
+/// applies it when returning to user space, and the others when they next
+/// enter the kernel, since the scheduler only allows a thread to change its
+/// own priority.
+///
+/// # Arguments
+/// * `which`, `who` - The threads, as in [`sys_getpriority`]
+/// * `prio` - The nice value
+pub(crate) fn sys_setpriority(which: i32, who: u32, prio: i32) -> isize {
+    syscall_body!(sys_setpriority, {
+        let nice = prio.clamp(MIN_NICE, MAX_NICE) as isize;
+        for thread in priority_targets(which, who)? {
+            thread.task_ext().set_nice(nice);
+        }
+        Ok(0)
+    })
 }
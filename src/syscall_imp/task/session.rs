@@ -0,0 +1,88 @@
+use alloc::sync::Arc;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    syscall_body,
+    task::{get_process, process_group, Pid, Process},
+};
+
+/// Returns the process `pid`, or the calling process if `pid` is 0.
+fn process_or_current(pid: i32) -> LinuxResult<Arc<Process>> {
+    match pid {
+        0 => Ok(current().task_ext().process.clone()),
+        pid if pid > 0 => get_process(pid as Pid).ok_or(LinuxError::ESRCH),
+        _ => Err(LinuxError::ESRCH),
+    }
+}
+
+/// Create a new session led by the calling process, in a new process group,
+/// without a controlling terminal.
+///
+/// Fails with `EPERM` if the calling process leads a process group.
+pub(crate) fn sys_setsid() -> isize {
+    syscall_body!(sys_setsid, {
+        let process = current().task_ext().process.clone();
+        if !process_group(process.pid()).is_empty() {
+            return Err(LinuxError::EPERM);
+        }
+        process.new_session();
+        Ok(process.pid())
+    })
+}
+
+/// Move the process `pid` to the process group `pgid`.
+///
+/// # Arguments
+/// * `pid` - The calling process or one of its children, or 0 for the
+///   calling process, which must not be a session leader
+/// * `pgid` - A process group of the session of the calling process, or the
+///   process ID of the process to create a process group, or 0 for the
+///   process ID of the process
+pub(crate) fn sys_setpgid(pid: i32, pgid: i32) -> isize {
+    syscall_body!(sys_setpgid, {
+        if pgid < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let curr = current().task_ext().process.clone();
+        let process = process_or_current(pid)?;
+        if process.pid() != curr.pid() && process.ppid() != curr.pid() {
+            return Err(LinuxError::ESRCH);
+        }
+        if process.sid() != curr.sid() || process.is_session_leader() {
+            return Err(LinuxError::EPERM);
+        }
+        let pgid = if pgid == 0 {
+            process.pid()
+        } else {
+            pgid as Pid
+        };
+        if pgid != process.pid()
+            && !process_group(pgid)
+                .iter()
+                .any(|member| member.sid() == curr.sid())
+        {
+            return Err(LinuxError::EPERM);
+        }
+        process.set_pgid(pgid);
+        Ok(0)
+    })
+}
+
+/// Get the process group ID of the process `pid`, or of the calling process
+/// if `pid` is 0.
+pub(crate) fn sys_getpgid(pid: i32) -> isize {
+    syscall_body!(sys_getpgid, Ok(process_or_current(pid)?.pgid()))
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_getpgrp() -> isize {
+    sys_getpgid(0)
+}
+
+/// Get the session ID of the process `pid`, or of the calling process if
+/// `pid` is 0.
+pub(crate) fn sys_getsid(pid: i32) -> isize {
+    syscall_body!(sys_getsid, Ok(process_or_current(pid)?.sid()))
+}
@@ -1,8 +1,10 @@
-use arceos_posix_api::{self as api};
 use axtask::{current, TaskExtRef};
 use num_enum::TryFromPrimitive;
 
-use crate::syscall_body;
+use crate::{
+    syscall_body,
+    task::{exit_current, WaitStatus},
+};
 
 /// ARCH_PRCTL codes
 ///
@@ -25,27 +27,26 @@ enum ArchPrctlCode {
     SetCpuid = 0x1012,
 }
 
-pub(crate) fn sys_getpid() -> i32 {
-    api::sys_getpid()
+pub(crate) fn sys_getpid() -> isize {
+    syscall_body!(sys_getpid, Ok(current().task_ext().process.pid()))
+}
+
+pub(crate) fn sys_getppid() -> isize {
+    syscall_body!(sys_getppid, Ok(current().task_ext().process.ppid()))
+}
+
+pub(crate) fn sys_gettid() -> isize {
+    syscall_body!(sys_gettid, Ok(current().id().as_u64()))
 }
 
 pub(crate) fn sys_exit(status: i32) -> ! {
-    let curr = current();
-    let clear_child_tid = curr.task_ext().clear_child_tid() as *mut i32;
-    if !clear_child_tid.is_null() {
-        // TODO: check whether the address is valid
-        unsafe {
-            // TODO: Encapsulate all operations that access user-mode memory into a unified function
-            *(clear_child_tid) = 0;
-        }
-        // TODO: wake up threads, which are blocked by futex, and waiting for the address pointed by clear_child_tid
-    }
-    axtask::exit(status);
+    exit_current(WaitStatus::Exited(status));
 }
 
 pub(crate) fn sys_exit_group(status: i32) -> ! {
-    warn!("Temporarily replace sys_exit_group with sys_exit");
-    axtask::exit(status);
+    let status = WaitStatus::Exited(status);
+    current().task_ext().process.exit_group(status);
+    exit_current(status);
 }
 
 /// To set the clear_child_tid field in the task extended data.
@@ -0,0 +1,196 @@
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{current, TaskExtRef};
+
+use crate::{
+    signal::{SigInfo, SIGCHLD},
+    syscall_body,
+    task::{Pid, Process, WaitStatus},
+    uaccess::put_user,
+};
+
+bitflags::bitflags! {
+    /// Options for sys_wait4 and sys_waitid
+    ///
+    /// See <https://github.com/torvalds/linux/blob/master/include/uapi/linux/wait.h>
+    #[derive(Debug, Clone, Copy)]
+    struct WaitOptions: u32 {
+        /// Return immediately if no child has changed its state.
+        const WNOHANG = 0x0000_0001;
+        /// Also report children that have stopped (`WSTOPPED` for waitid).
+        const WUNTRACED = 0x0000_0002;
+        /// Report children that have terminated (waitid only).
+        const WEXITED = 0x0000_0004;
+        /// Also report children that have been resumed by `SIGCONT`.
+        const WCONTINUED = 0x0000_0008;
+        /// Leave the child in a waitable state (waitid only).
+        const WNOWAIT = 0x0100_0000;
+        /// Only wait for children of the calling thread.
+        const __WNOTHREAD = 0x2000_0000;
+        /// Wait for all children, regardless of their exit signals.
+        const __WALL = 0x4000_0000;
+        /// Only wait for "clone" children, whose exit signal is not `SIGCHLD`.
+        const __WCLONE = 0x8000_0000;
+    }
+}
+
+/// The children to wait for.
+#[derive(Debug, Clone, Copy)]
+enum WaitTarget {
+    /// Any child.
+    Any,
+    /// The child with the process ID.
+    Pid(Pid),
+    /// Any child in the process group.
+    Pgid(Pid),
+}
+
+impl WaitTarget {
+    fn matches(self, child: &Process, options: WaitOptions) -> bool {
+        let target_matches = match self {
+            Self::Any => true,
+            Self::Pid(pid) => child.pid() == pid,
+            Self::Pgid(pgid) => child.pgid() == pgid,
+        };
+        let is_clone = child.exit_signal() != SIGCHLD;
+        target_matches
+            && (options.contains(WaitOptions::__WALL)
+                || is_clone == options.contains(WaitOptions::__WCLONE))
+    }
+}
+
+/// Waits for a child selected by `target` to change its state as requested
+/// in `options`, and reaps it if it has terminated (unless `WNOWAIT`).
+///
+/// Returns `None` if `WNOHANG` is set and no child has changed its state.
+fn do_wait(target: WaitTarget, options: WaitOptions) -> LinuxResult<Option<(Pid, WaitStatus)>> {
+    let curr = current();
+    let process = &curr.task_ext().process;
+    let peek = options.contains(WaitOptions::WNOWAIT);
+    loop {
+        let seen = process.child_events();
+        let mut found = false;
+        for child in process.children() {
+            if !target.matches(&child, options) {
+                continue;
+            }
+            found = true;
+            let Some(status) = child.wait_status(
+                options.contains(WaitOptions::WEXITED),
+                options.contains(WaitOptions::WUNTRACED),
+                options.contains(WaitOptions::WCONTINUED),
+                peek,
+            ) else {
+                continue;
+            };
+            if !peek && matches!(status, WaitStatus::Exited(_) | WaitStatus::Signaled(..)) {
+                process.reap_child(child.pid());
+            }
+            return Ok(Some((child.pid(), status)));
+        }
+        if !found {
+            return Err(LinuxError::ECHILD);
+        }
+        if options.contains(WaitOptions::WNOHANG) {
+            return Ok(None);
+        }
+        process.wait_child_event(seen)?;
+    }
+}
+
+/// The size of `struct rusage`.
+const RUSAGE_SIZE: usize = 144;
+
+/// Wait for a child process to change its state.
+///
+/// # Arguments
+/// * `pid` - The child to wait for: -1 for any child, 0 for any child in the
+///   process group of the caller, `-pgid` for any child in the process group
+///   `pgid`, or a process ID
+/// * `wstatus` - Where to store the status of the child, may be null
+/// * `options` - The wait options
+/// * `rusage` - Where to store the resource usage of the child, may be null.
+///   Resource usage is not accounted, so it is reported as all zeros.
+///
+/// # Returns
+/// The process ID of the child, or 0 if `WNOHANG` is set and no child has
+/// changed its state.
+pub(crate) fn sys_wait4(
+    pid: i32,
+    wstatus: *mut i32,
+    options: u32,
+    rusage: *mut [u8; RUSAGE_SIZE],
+) -> isize {
+    syscall_body!(sys_wait4, {
+        let mut options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)?;
+        debug!(
+            "sys_wait4 <= pid: {}, wstatus: {:p}, options: {:?}",
+            pid, wstatus, options
+        );
+        if options.intersects(WaitOptions::WEXITED | WaitOptions::WNOWAIT) {
+            return Err(LinuxError::EINVAL);
+        }
+        options |= WaitOptions::WEXITED;
+        let target = match pid {
+            -1 => WaitTarget::Any,
+            0 => WaitTarget::Pgid(current().task_ext().process.pgid()),
+            pid if pid < 0 => WaitTarget::Pgid(pid.unsigned_abs() as Pid),
+            pid => WaitTarget::Pid(pid as Pid),
+        };
+
+        let Some((pid, status)) = do_wait(target, options)? else {
+            return Ok(0);
+        };
+        if !wstatus.is_null() {
+            put_user(wstatus, status.as_raw())?;
+        }
+        if !rusage.is_null() {
+            put_user(rusage, [0; RUSAGE_SIZE])?;
+        }
+        Ok(pid as isize)
+    })
+}
+
+/// The ID types of sys_waitid
+const P_ALL: u32 = 0;
+const P_PID: u32 = 1;
+const P_PGID: u32 = 2;
+
+/// Wait for a child process to change its state, with finer control than
+/// `wait4`.
+///
+/// # Arguments
+/// * `idtype` - How `id` selects the children: `P_ALL`, `P_PID` or `P_PGID`
+/// * `id` - The process ID or process group ID of the children to wait for
+/// * `infop` - Where to store the information about the child, may be null
+/// * `options` - The wait options, with at least one of `WEXITED`,
+///   `WSTOPPED` and `WCONTINUED`
+pub(crate) fn sys_waitid(idtype: u32, id: u32, infop: *mut SigInfo, options: u32) -> isize {
+    syscall_body!(sys_waitid, {
+        let options = WaitOptions::from_bits(options).ok_or(LinuxError::EINVAL)?;
+        debug!(
+            "sys_waitid <= idtype: {}, id: {}, infop: {:p}, options: {:?}",
+            idtype, id, infop, options
+        );
+        if !options
+            .intersects(WaitOptions::WEXITED | WaitOptions::WUNTRACED | WaitOptions::WCONTINUED)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let target = match idtype {
+            P_ALL => WaitTarget::Any,
+            P_PID if id > 0 => WaitTarget::Pid(id as Pid),
+            P_PGID if id == 0 => WaitTarget::Pgid(current().task_ext().process.pgid()),
+            P_PGID => WaitTarget::Pgid(id as Pid),
+            _ => return Err(LinuxError::EINVAL),
+        };
+
+        let info = match do_wait(target, options)? {
+            Some((pid, status)) => SigInfo::child(SIGCHLD, pid, status),
+            None => SigInfo::empty(),
+        };
+        if !infop.is_null() {
+            put_user(infop, info)?;
+        }
+        Ok(0)
+    })
+}
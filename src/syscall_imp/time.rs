@@ -1,5 +1,25 @@
+use core::time::Duration;
+
 use arceos_posix_api as api;
+use axerrno::{LinuxError, LinuxResult};
 
 pub(crate) fn sys_clock_gettime(clock_id: i32, tp: *mut api::ctypes::timespec) -> i32 {
     unsafe { api::sys_clock_gettime(clock_id, tp) }
 }
+
+/// Converts a `timespec` from user space to a [`Duration`], checking that it
+/// is valid.
+pub(crate) fn timespec_to_duration(ts: api::ctypes::timespec) -> LinuxResult<Duration> {
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+/// Converts a [`Duration`] to a `timespec` for user space.
+pub(crate) fn duration_to_timespec(dur: Duration) -> api::ctypes::timespec {
+    api::ctypes::timespec {
+        tv_sec: dur.as_secs() as _,
+        tv_nsec: dur.subsec_nanos() as _,
+    }
+}
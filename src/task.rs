@@ -1,54 +1,868 @@
-use alloc::sync::Arc;
-use core::sync::atomic::AtomicU64;
+use alloc::{
+    collections::btree_map::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicIsize, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
 
+use axerrno::{LinuxError, LinuxResult};
 use axhal::arch::UspaceContext;
-use axmm::AddrSpace;
 use axsync::Mutex;
-use axtask::{AxTaskRef, TaskExtRef, TaskInner};
+use axtask::{AxTaskRef, TaskExtRef, TaskInner, WaitQueue};
+
+use crate::{
+    file::{FdTable, PollSet},
+    fs::FsContext,
+    futex,
+    mm::UserSpace,
+    resource::RLimits,
+    signal::{
+        self, PendingSignals, SigActionFlags, SigInfo, SignalActions, ThreadSignals, SIGCHLD,
+        SIGKILL, SIG_IGN,
+    },
+    tty::Tty,
+    uaccess::put_user,
+};
+
+/// The process ID type.
+///
+/// As on Linux, the ID of a process is the thread ID of its main thread.
+pub type Pid = u64;
+
+/// A change of the state of a child process reported by `wait`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    /// The process exited normally with the exit code.
+    Exited(i32),
+    /// The process was killed by the signal, with whether a core was dumped.
+    Signaled(u32, bool),
+    /// The process was stopped by the signal.
+    Stopped(u32),
+    /// The process was resumed by `SIGCONT`.
+    Continued,
+}
+
+impl WaitStatus {
+    /// Encodes the status in the format of the `wstatus` of `wait4`.
+    pub const fn as_raw(self) -> i32 {
+        match self {
+            Self::Exited(code) => (code & 0xff) << 8,
+            Self::Signaled(signo, core_dumped) => {
+                (signo & 0x7f) as i32 | if core_dumped { 0x80 } else { 0 }
+            }
+            Self::Stopped(signo) => ((signo & 0xff) << 8) as i32 | 0x7f,
+            Self::Continued => 0xffff,
+        }
+    }
+}
+
+/// The process that adopts orphaned processes, see [`init_process`].
+static INIT_PROCESS: Mutex<Option<Arc<Process>>> = Mutex::new(None);
+
+/// All processes which have not been reaped, indexed by process ID.
+static PROCESSES: Mutex<BTreeMap<Pid, Weak<Process>>> = Mutex::new(BTreeMap::new());
+
+/// Returns the process with the process ID `pid`, if it has not been reaped.
+pub fn get_process(pid: Pid) -> Option<Arc<Process>> {
+    PROCESSES.lock().get(&pid).and_then(Weak::upgrade)
+}
+
+/// Returns all processes which have not been reaped.
+pub fn processes() -> Vec<Arc<Process>> {
+    PROCESSES
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// Returns the processes of the process group `pgid`.
+pub fn process_group(pgid: Pid) -> Vec<Arc<Process>> {
+    processes()
+        .into_iter()
+        .filter(|process| process.pgid() == pgid)
+        .collect()
+}
+
+/// Whether the process group `pgid` is orphaned, i.e. the parent of each of
+/// its processes is either in the group or in another session, so that no
+/// job control shell can continue the group once it is stopped.
+pub fn is_orphaned_group(pgid: Pid) -> bool {
+    process_group(pgid).iter().all(|process| {
+        process
+            .parent()
+            .is_none_or(|parent| parent.pgid() == pgid || parent.sid() != process.sid())
+    })
+}
+
+/// The threads of all processes, indexed by thread ID.
+static THREADS: Mutex<BTreeMap<u64, AxTaskRef>> = Mutex::new(BTreeMap::new());
+
+/// Returns the user thread with the thread ID `tid`, if it has not exited.
+pub fn get_thread(tid: u64) -> Option<AxTaskRef> {
+    THREADS.lock().get(&tid).cloned()
+}
+
+/// The default nice value of user threads.
+///
+/// Priorities are nice values, used by the CFS scheduler: a lower value
+/// means a higher priority.
+const DEFAULT_PRIORITY: isize = 0;
+
+/// A process, i.e. a group of threads sharing the same address space.
+pub struct Process {
+    /// The process ID.
+    pid: Pid,
+    /// The process group ID.
+    pgid: AtomicU64,
+    /// The session ID, which is the process ID of the session leader.
+    sid: AtomicU64,
+    /// The controlling terminal of the session, if the process has one.
+    tty: Mutex<Option<Arc<Tty>>>,
+    /// The parent process.
+    ///
+    /// It is empty for the processes spawned directly by the kernel.
+    parent: Mutex<Weak<Process>>,
+    /// The child processes.
+    children: Mutex<Vec<Arc<Process>>>,
+    /// The threads of the process (the thread group), indexed by thread ID.
+    threads: Mutex<BTreeMap<u64, AxTaskRef>>,
+    /// Incremented whenever a thread exits.
+    thread_exits: AtomicU64,
+    /// The threads waiting for other threads to exit wait here.
+    thread_exit_wq: WaitQueue,
+    /// The status of the whole process set by `exit_group`, which overrides
+    /// the statuses of the exiting threads.
+    group_exit_status: Mutex<Option<WaitStatus>>,
+    /// The virtual memory address space shared by all threads, and by the
+    /// processes created with `CLONE_VM`.
+    aspace: Mutex<Arc<Mutex<UserSpace>>>,
+    /// The signal sent to the parent when the process terminates.
+    ///
+    /// A child with another signal than `SIGCHLD` is a "clone" child for
+    /// `wait`.
+    exit_signal: AtomicU32,
+    /// How the process terminated. It is a zombie once this is set, until
+    /// it is reaped by its parent.
+    exit_status: Mutex<Option<WaitStatus>>,
+    /// A stop or continue of the process not reported to the parent yet.
+    state_event: Mutex<Option<WaitStatus>>,
+    /// Incremented whenever a child changes its state.
+    child_events: AtomicU64,
+    /// The threads waiting for a child to change its state wait here.
+    child_wq: WaitQueue,
+    /// Whether the process has released the parent blocked in `vfork`, by
+    /// either exiting or calling `execve`.
+    vfork_done: AtomicBool,
+    /// The parent blocked in `vfork` waits here.
+    vfork_wq: WaitQueue,
+    /// The signal handlers, which may be shared with other processes created
+    /// by `clone` with `CLONE_SIGHAND`.
+    signal_actions: Mutex<Arc<Mutex<SignalActions>>>,
+    /// The signals sent to the process as a whole.
+    pub pending_signals: PendingSignals,
+    /// The threads reading a signalfd wait here for signals sent to the
+    /// process or any of its threads.
+    pub signalfd_wq: WaitQueue,
+    /// The wakers watching the signalfds of the process.
+    pub signalfd_poll: Arc<PollSet>,
+    /// Whether the process is stopped by a signal.
+    stopped: AtomicBool,
+    /// The threads of the stopped process wait here until it is continued.
+    stop_wq: WaitQueue,
+    /// The file descriptor table, which may be shared with other processes
+    /// created by `clone` with `CLONE_FILES`. It is replaced by an empty one
+    /// when the process exits, closing its files.
+    fd_table: Mutex<Arc<FdTable>>,
+    /// The current and root directories, which may be shared with other
+    /// processes created by `clone` with `CLONE_FS`.
+    fs_context: Mutex<Arc<FsContext>>,
+    /// The resource limits.
+    pub rlimits: Mutex<RLimits>,
+}
+
+impl Process {
+    /// Creates a new process whose main thread has the ID `pid`.
+    ///
+    /// `exit_signal` is sent to `parent` when the process terminates.
+    pub fn new(
+        pid: Pid,
+        parent: Option<&Arc<Process>>,
+        aspace: Arc<Mutex<UserSpace>>,
+        signal_actions: Arc<Mutex<SignalActions>>,
+        fd_table: Arc<FdTable>,
+        fs_context: Arc<FsContext>,
+        exit_signal: u32,
+    ) -> Arc<Self> {
+        let process = Arc::new(Self {
+            pid,
+            pgid: AtomicU64::new(parent.map_or(pid, |parent| parent.pgid())),
+            sid: AtomicU64::new(parent.map_or(pid, |parent| parent.sid())),
+            tty: Mutex::new(parent.and_then(|parent| parent.tty())),
+            parent: Mutex::new(parent.map_or(Weak::new(), Arc::downgrade)),
+            children: Mutex::new(Vec::new()),
+            threads: Mutex::new(BTreeMap::new()),
+            thread_exits: AtomicU64::new(0),
+            thread_exit_wq: WaitQueue::new(),
+            group_exit_status: Mutex::new(None),
+            aspace: Mutex::new(aspace),
+            exit_signal: AtomicU32::new(exit_signal),
+            exit_status: Mutex::new(None),
+            state_event: Mutex::new(None),
+            child_events: AtomicU64::new(0),
+            child_wq: WaitQueue::new(),
+            vfork_done: AtomicBool::new(false),
+            vfork_wq: WaitQueue::new(),
+            signal_actions: Mutex::new(signal_actions),
+            pending_signals: PendingSignals::new(),
+            signalfd_wq: WaitQueue::new(),
+            signalfd_poll: Arc::new(PollSet::new()),
+            stopped: AtomicBool::new(false),
+            stop_wq: WaitQueue::new(),
+            fd_table: Mutex::new(fd_table),
+            fs_context: Mutex::new(fs_context),
+            rlimits: Mutex::new(RLimits::new()),
+        });
+        PROCESSES.lock().insert(pid, Arc::downgrade(&process));
+        if let Some(parent) = parent {
+            parent.children.lock().push(process.clone());
+        }
+        process
+    }
+
+    /// Removes the process, whose first thread could not be spawned, from the
+    /// process table and from the children of its parent.
+    pub fn forget(&self) {
+        PROCESSES.lock().remove(&self.pid);
+        if let Some(parent) = self.parent() {
+            parent
+                .children
+                .lock()
+                .retain(|child| !core::ptr::eq(Arc::as_ptr(child), self));
+        }
+    }
+
+    /// Returns the process ID.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Returns the process group ID, which is inherited from the parent.
+    pub fn pgid(&self) -> Pid {
+        self.pgid.load(Ordering::Acquire)
+    }
+
+    /// Moves the process to the process group `pgid`, in its session.
+    pub fn set_pgid(&self, pgid: Pid) {
+        self.pgid.store(pgid, Ordering::Release);
+    }
+
+    /// Returns the session ID, which is inherited from the parent.
+    pub fn sid(&self) -> Pid {
+        self.sid.load(Ordering::Acquire)
+    }
+
+    /// Makes the process the leader of a new session and of a new process
+    /// group in it, without a controlling terminal.
+    pub fn new_session(&self) {
+        self.sid.store(self.pid, Ordering::Release);
+        self.pgid.store(self.pid, Ordering::Release);
+        *self.tty.lock() = None;
+    }
+
+    /// Whether the process is the leader of its session.
+    pub fn is_session_leader(&self) -> bool {
+        self.sid() == self.pid
+    }
+
+    /// Returns the controlling terminal, which is inherited from the parent.
+    pub fn tty(&self) -> Option<Arc<Tty>> {
+        self.tty.lock().clone()
+    }
+
+    /// Sets or clears (with `None`) the controlling terminal of the session,
+    /// as recorded by this process.
+    pub fn set_tty(&self, tty: Option<Arc<Tty>>) {
+        *self.tty.lock() = tty;
+    }
+
+    /// Returns the address space.
+    pub fn aspace(&self) -> Arc<Mutex<UserSpace>> {
+        self.aspace.lock().clone()
+    }
+
+    /// Replaces the address space, e.g. to stop sharing it in `execve`, and
+    /// switches the current thread, which must be the only thread of the
+    /// process, to it.
+    pub fn set_aspace(&self, aspace: Arc<Mutex<UserSpace>>) {
+        let root = aspace.lock().page_table_root();
+        let old = core::mem::replace(&mut *self.aspace.lock(), aspace);
+        let curr = axtask::current();
+        // SAFETY: the context of a task is only accessed when switching to or
+        // from it, which does not happen while it runs. It is updated first,
+        // so that a switch in between loads the new page table. The kernel is
+        // mapped in all page tables.
+        unsafe {
+            (*curr.ctx_mut_ptr()).set_page_table_root(root);
+            #[cfg(target_arch = "aarch64")]
+            axhal::arch::write_page_table_root0(root);
+            #[cfg(not(target_arch = "aarch64"))]
+            axhal::arch::write_page_table_root(root);
+        }
+        // The old page table is freed only after the switch, if no other
+        // process shares it.
+        drop(old);
+    }
+
+    /// Returns the signal handlers.
+    pub fn signal_actions(&self) -> Arc<Mutex<SignalActions>> {
+        self.signal_actions.lock().clone()
+    }
+
+    /// Replaces the signal handlers, e.g. to stop sharing them.
+    pub fn set_signal_actions(&self, signal_actions: Arc<Mutex<SignalActions>>) {
+        *self.signal_actions.lock() = signal_actions;
+    }
+
+    /// Returns the file descriptor table.
+    pub fn fd_table(&self) -> Arc<FdTable> {
+        self.fd_table.lock().clone()
+    }
+
+    /// Replaces the file descriptor table, e.g. to stop sharing it.
+    pub fn set_fd_table(&self, fd_table: Arc<FdTable>) {
+        *self.fd_table.lock() = fd_table;
+    }
+
+    /// Returns the current and root directories.
+    pub fn fs_context(&self) -> Arc<FsContext> {
+        self.fs_context.lock().clone()
+    }
+
+    /// Returns the parent process, if it is still alive.
+    pub fn parent(&self) -> Option<Arc<Process>> {
+        self.parent.lock().upgrade()
+    }
+
+    /// Returns the ID of the parent process, or 0 if there is none.
+    pub fn ppid(&self) -> Pid {
+        self.parent().map_or(0, |parent| parent.pid)
+    }
+
+    /// Returns the child processes.
+    pub fn children(&self) -> Vec<Arc<Process>> {
+        self.children.lock().clone()
+    }
+
+    /// Returns the threads that have not exited yet.
+    pub fn threads(&self) -> Vec<AxTaskRef> {
+        self.threads.lock().values().cloned().collect()
+    }
+
+    /// Returns the number of threads that have not exited yet.
+    pub fn thread_count(&self) -> usize {
+        self.threads.lock().len()
+    }
+
+    /// Returns the signal sent to the parent when the process terminates.
+    pub fn exit_signal(&self) -> u32 {
+        self.exit_signal.load(Ordering::Acquire)
+    }
+
+    /// Whether the process has terminated but not been reaped yet.
+    pub fn is_zombie(&self) -> bool {
+        self.exit_status.lock().is_some()
+    }
+
+    /// Spawns `task` as a new thread of the process.
+    ///
+    /// Fails with `EINTR` if the process is exiting by `exit_group`.
+    pub fn spawn_thread(&self, task: TaskInner) -> LinuxResult<AxTaskRef> {
+        // Hold the lock so that the thread cannot exit before it is
+        // registered, and cannot be missed by `exit_group`.
+        let mut threads = self.threads.lock();
+        if self.group_exit_status.lock().is_some() {
+            return Err(LinuxError::EINTR);
+        }
+        let task = axtask::spawn_task(task);
+        threads.insert(task.id().as_u64(), task.clone());
+        THREADS.lock().insert(task.id().as_u64(), task.clone());
+        Ok(task)
+    }
+
+    /// Kills all threads of the process except the current one.
+    ///
+    /// The killed threads are woken up if they are blocked, and exit when
+    /// they next return to user space from a system call or a page fault.
+    ///
+    /// A thread spinning in user space without system calls or page faults
+    /// keeps running, so `exit_group` does not terminate it. This cannot be
+    /// fixed here: axhal has no hook on the return from interrupts to user
+    /// space, and its only IRQ handler, owned by axruntime, is given neither
+    /// the trap frame nor whether user space was interrupted. Checking for
+    /// a kill on the timer interrupt needs such a hook in axhal first.
+    pub fn kill_other_threads(&self) {
+        let curr_id = axtask::current().id();
+        for thread in self.threads.lock().values() {
+            if thread.id() != curr_id {
+                thread.task_ext().kill(thread);
+            }
+        }
+    }
+
+    /// Blocks until the current thread is the only thread of the process.
+    pub fn wait_other_threads_exited(&self) -> LinuxResult {
+        loop {
+            let seen = self.thread_exits.load(Ordering::Acquire);
+            if self.thread_count() == 1 {
+                return Ok(());
+            }
+            wait_killable(&self.thread_exit_wq, || {
+                self.thread_exits.load(Ordering::Acquire) != seen
+            })?;
+        }
+    }
+
+    /// Terminates the whole process with `status`, by killing all threads
+    /// except the current one, which should exit by itself.
+    ///
+    /// If the process is already exiting, the status set first is kept.
+    pub fn exit_group(&self, status: WaitStatus) {
+        self.group_exit_status.lock().get_or_insert(status);
+        self.kill_other_threads();
+    }
+
+    /// Terminates the whole process with `status` by killing all its
+    /// threads, e.g. on `SIGKILL`.
+    ///
+    /// If the process is already exiting, the status set first is kept.
+    pub fn terminate(&self, status: WaitStatus) {
+        self.group_exit_status.lock().get_or_insert(status);
+        for thread in self.threads.lock().values() {
+            thread.task_ext().kill(thread);
+        }
+    }
+
+    /// Whether the process is stopped by a signal.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Stops the process by the signal `signo`, and notifies the parent.
+    ///
+    /// The other threads are interrupted, so that they stop at the end of
+    /// their current system call. The threads stay blocked in
+    /// [`Process::wait_while_stopped`] until the process is continued or
+    /// killed.
+    pub fn stop(&self, signo: u32) {
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let curr_id = axtask::current().id();
+        for thread in self.threads.lock().values() {
+            if thread.id() != curr_id {
+                thread.task_ext().interrupt(thread);
+            }
+        }
+        self.report_state_event(WaitStatus::Stopped(signo));
+    }
+
+    /// Continues the process if it is stopped, and notifies the parent.
+    pub fn resume(&self) {
+        if self.stopped.swap(false, Ordering::AcqRel) {
+            self.stop_wq.notify_all(false);
+            self.report_state_event(WaitStatus::Continued);
+        }
+    }
+
+    /// Blocks the current thread while the process is stopped, unless the
+    /// thread is killed.
+    pub fn wait_while_stopped(&self) {
+        // A killed thread exits right after.
+        let _ = wait_killable(&self.stop_wq, || !self.is_stopped());
+    }
+
+    /// Removes the thread `tid` from the thread group.
+    ///
+    /// Returns `true` if it was the last thread, in which case the process
+    /// becomes a zombie with `status` (or the status of `exit_group`), its
+    /// memory is released, its files are closed, it gives up its controlling
+    /// terminal, its children are adopted by the init process and its parent
+    /// is notified.
+    pub fn exit_thread(&self, tid: u64, status: WaitStatus) -> bool {
+        let mut threads = self.threads.lock();
+        threads.remove(&tid);
+        THREADS.lock().remove(&tid);
+        self.thread_exits.fetch_add(1, Ordering::AcqRel);
+        self.thread_exit_wq.notify_all(false);
+        if !threads.is_empty() {
+            return false;
+        }
+        drop(threads);
+        let status = self.group_exit_status.lock().unwrap_or(status);
+
+        // The memory is kept while another process shares it (`CLONE_VM`),
+        // and released when the last one drops it otherwise.
+        let aspace = self.aspace();
+        if Arc::strong_count(&aspace) == 2 {
+            aspace.lock().clear();
+        }
+        self.set_fd_table(Arc::new(FdTable::new()));
+        let tty = self.tty.lock().take();
+        if let Some(tty) = tty {
+            tty.leave(self);
+        }
+        let children = core::mem::take(&mut *self.children.lock());
+        if !children.is_empty() {
+            let init = init_process();
+            for child in children {
+                init.adopt(child);
+            }
+        }
+        *self.exit_status.lock() = Some(status);
+        // Notify the parent while holding the lock, so that the notification
+        // goes to the new parent if the process is being reparented.
+        let parent = self.parent.lock();
+        if let Some(parent) = parent.upgrade() {
+            let exit_signal = self.exit_signal();
+            if exit_signal != 0 {
+                signal::send_process_signal(&parent, SigInfo::child(exit_signal, self.pid, status));
+            }
+            // A parent ignoring `SIGCHLD` does not get zombies.
+            let action = parent.signal_actions().lock().get(SIGCHLD);
+            if exit_signal == SIGCHLD
+                && (action.handler == SIG_IGN
+                    || action.flags.contains(SigActionFlags::SA_NOCLDWAIT))
+            {
+                parent.reap_child(self.pid);
+            }
+            parent.notify_child_event();
+        }
+        drop(parent);
+        self.notify_vfork_done();
+        true
+    }
+
+    /// Makes `child`, whose parent has exited, a child of this process.
+    fn adopt(self: &Arc<Self>, child: Arc<Process>) {
+        // Add the child first, so that a waiter woken up by the child can
+        // find it.
+        self.children.lock().push(child.clone());
+        child.exit_signal.store(SIGCHLD, Ordering::Release);
+        *child.parent.lock() = Arc::downgrade(self);
+        if child.is_zombie() {
+            self.notify_child_event();
+        }
+    }
+
+    /// Records that the process was stopped or continued, and notifies the
+    /// parent, by `SIGCHLD` unless it has set `SA_NOCLDSTOP`.
+    fn report_state_event(&self, event: WaitStatus) {
+        *self.state_event.lock() = Some(event);
+        if let Some(parent) = self.parent() {
+            let action = parent.signal_actions().lock().get(SIGCHLD);
+            if !action.flags.contains(SigActionFlags::SA_NOCLDSTOP) {
+                signal::send_process_signal(&parent, SigInfo::child(SIGCHLD, self.pid, event));
+            }
+            parent.notify_child_event();
+        }
+    }
+
+    /// Returns the state change of the process to report to its parent, if
+    /// any of the requested kinds is pending.
+    ///
+    /// A stop or continue event is consumed unless `peek` is set. A zombie
+    /// is left as it is, the parent has to reap it by [`Process::reap_child`].
+    pub fn wait_status(
+        &self,
+        exited: bool,
+        stopped: bool,
+        continued: bool,
+        peek: bool,
+    ) -> Option<WaitStatus> {
+        if let Some(status) = *self.exit_status.lock() {
+            return exited.then_some(status);
+        }
+        let mut event = self.state_event.lock();
+        match *event {
+            Some(WaitStatus::Stopped(_)) if stopped => {}
+            Some(WaitStatus::Continued) if continued => {}
+            _ => return None,
+        }
+        if peek {
+            *event
+        } else {
+            event.take()
+        }
+    }
+
+    /// Removes the zombie child `pid`, releasing the last resources of it.
+    pub fn reap_child(&self, pid: Pid) {
+        self.children
+            .lock()
+            .retain(|child| child.pid != pid || !child.is_zombie());
+    }
+
+    /// Returns the number of state changes of the children so far, to be
+    /// passed to [`Process::wait_child_event`].
+    pub fn child_events(&self) -> u64 {
+        self.child_events.load(Ordering::Acquire)
+    }
+
+    /// Blocks until a child changes its state after `child_events` returned
+    /// `seen`.
+    pub fn wait_child_event(&self, seen: u64) -> LinuxResult {
+        wait_interruptible(&self.child_wq, || self.child_events() != seen)
+    }
+
+    fn notify_child_event(&self) {
+        self.child_events.fetch_add(1, Ordering::AcqRel);
+        self.child_wq.notify_all(false);
+    }
+
+    /// Wakes up the parent blocked in `vfork` for this process.
+    pub fn notify_vfork_done(&self) {
+        self.vfork_done.store(true, Ordering::Release);
+        self.vfork_wq.notify_all(false);
+    }
+
+    /// Blocks until this process, created by `vfork`, exits or calls
+    /// `execve`.
+    ///
+    /// Signals do not interrupt the wait, as the child is already running.
+    pub fn wait_vfork_done(&self) -> LinuxResult {
+        wait_killable(&self.vfork_wq, || self.vfork_done.load(Ordering::Acquire))
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        PROCESSES.lock().remove(&self.pid);
+    }
+}
 
 /// Task extended data for the monolithic kernel.
 pub struct TaskExt {
-    /// The process ID.
-    pub proc_id: usize,
+    /// The process which the task belongs to.
+    pub process: Arc<Process>,
     /// The clear thread tid field
     ///
     /// See <https://manpages.debian.org/unstable/manpages-dev/set_tid_address.2.en.html#clear_child_tid>
     ///
     /// When the thread exits, the kernel clears the word at this address if it is not NULL.
     clear_child_tid: AtomicU64,
+    /// The head of the robust futex list of the thread in user space.
+    ///
+    /// See <https://docs.kernel.org/locking/robust-futexes.html>
+    robust_list_head: AtomicUsize,
+    /// The nice value of the thread, set by `setpriority`.
+    nice: AtomicIsize,
+    /// The highest priority inherited from the threads blocked on the PI
+    /// futexes owned by the thread, or `isize::MAX` if there is none.
+    inherited_priority: AtomicIsize,
+    /// The priority of the thread last set in the scheduler.
+    applied_priority: AtomicIsize,
+    /// Whether the thread is killed. It exits when it next returns to user
+    /// space from a system call or a page fault.
+    killed: AtomicBool,
+    /// The wait queue on which the thread is blocked by
+    /// [`wait_interruptible`].
+    blocked_on: Mutex<Option<BlockedOn>>,
+    /// The signal mask and the signals sent to the thread.
+    pub signals: ThreadSignals,
     /// The user space context.
     pub uctx: UspaceContext,
-    /// The virtual memory address space.
-    pub aspace: Arc<Mutex<AddrSpace>>,
 }
 
+/// A pointer to the wait queue a thread is blocked on.
+///
+/// The wait queue outlives the pointer, since the thread keeps borrowing it
+/// until it removes the pointer after waking up.
+struct BlockedOn(*const WaitQueue);
+
+unsafe impl Send for BlockedOn {}
+unsafe impl Sync for BlockedOn {}
+
 impl TaskExt {
-    pub const fn new(uctx: UspaceContext, aspace: Arc<Mutex<AddrSpace>>) -> Self {
+    pub const fn new(uctx: UspaceContext, process: Arc<Process>) -> Self {
         Self {
-            proc_id: 233,
+            process,
             uctx,
             clear_child_tid: AtomicU64::new(0),
-            aspace,
+            robust_list_head: AtomicUsize::new(0),
+            nice: AtomicIsize::new(DEFAULT_PRIORITY),
+            inherited_priority: AtomicIsize::new(isize::MAX),
+            applied_priority: AtomicIsize::new(DEFAULT_PRIORITY),
+            killed: AtomicBool::new(false),
+            blocked_on: Mutex::new(None),
+            signals: ThreadSignals::new(),
+        }
+    }
+
+    pub(crate) fn robust_list_head(&self) -> usize {
+        self.robust_list_head.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_robust_list_head(&self, head: usize) {
+        self.robust_list_head.store(head, Ordering::Relaxed);
+    }
+
+    /// Returns the nice value of the thread.
+    pub fn nice(&self) -> isize {
+        self.nice.load(Ordering::Acquire)
+    }
+
+    /// Sets the nice value of the thread.
+    ///
+    /// The scheduler only allows the current thread to change its priority,
+    /// so the thread applies it by itself with [`apply_priority`].
+    pub fn set_nice(&self, nice: isize) {
+        self.nice.store(nice, Ordering::Release);
+    }
+
+    /// Returns the priority of the thread, including the inherited one.
+    pub fn effective_priority(&self) -> isize {
+        self.nice()
+            .min(self.inherited_priority.load(Ordering::Acquire))
+    }
+
+    /// Sets the priority inherited from the threads blocked on the PI
+    /// futexes owned by the thread, or `isize::MAX` if there is none.
+    ///
+    /// As for [`TaskExt::set_nice`], the thread applies it by itself.
+    pub fn set_inherited_priority(&self, priority: isize) {
+        self.inherited_priority.store(priority, Ordering::Release);
+    }
+
+    /// Whether the thread is killed.
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Acquire)
+    }
+
+    /// Whether the thread has to stop waiting in [`wait_interruptible`]: it
+    /// is killed, its process is stopped, or a signal it does not block is
+    /// pending.
+    pub fn is_interrupted(&self) -> bool {
+        let pending = self.signals.pending.set() | self.process.pending_signals.set();
+        self.is_killed()
+            || self.process.is_stopped()
+            || !(pending & !self.signals.mask()).is_empty()
+    }
+
+    /// Kills the thread `task` that this belongs to, waking it up if it is
+    /// blocked in [`wait_interruptible`].
+    fn kill(&self, task: &AxTaskRef) {
+        self.killed.store(true, Ordering::Release);
+        self.interrupt(task);
+    }
+
+    /// Wakes up `task`, which this belongs to, if it is blocked in
+    /// [`wait_interruptible`], so that it checks [`TaskExt::is_interrupted`].
+    pub(crate) fn interrupt(&self, task: &AxTaskRef) {
+        if let Some(BlockedOn(wq)) = *self.blocked_on.lock() {
+            unsafe { (*wq).notify_task(false, task) };
         }
     }
 
     pub(crate) fn clear_child_tid(&self) -> u64 {
-        self.clear_child_tid
-            .load(core::sync::atomic::Ordering::Relaxed)
+        self.clear_child_tid.load(Ordering::Relaxed)
     }
 
     pub(crate) fn set_clear_child_tid(&self, clear_child_tid: u64) {
         self.clear_child_tid
-            .store(clear_child_tid, core::sync::atomic::Ordering::Relaxed);
+            .store(clear_child_tid, Ordering::Relaxed);
     }
 }
 
 axtask::def_task_ext!(TaskExt);
 
-pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> AxTaskRef {
-    let mut task = TaskInner::new(
-        || {
+/// Blocks the current thread on `wq` until `condition` holds, unless the
+/// thread is interrupted in the meantime (see [`TaskExt::is_interrupted`]).
+///
+/// Returns `EINTR` if the thread is interrupted before `condition` holds.
+///
+/// Like for [`WaitQueue::wait_until`], `condition` is checked with the wait
+/// queue locked, so it must not block (e.g. by locking a [`Mutex`]).
+pub fn wait_interruptible<F>(wq: &WaitQueue, condition: F) -> LinuxResult
+where
+    F: Fn() -> bool,
+{
+    wait_interruptible_timeout(wq, None, condition)
+}
+
+/// Like [`wait_interruptible`], but only interrupted if the thread is
+/// killed, not by signals.
+pub fn wait_killable<F>(wq: &WaitQueue, condition: F) -> LinuxResult
+where
+    F: Fn() -> bool,
+{
+    block_on(wq, None, condition, TaskExt::is_killed)
+}
+
+/// Like [`wait_interruptible`], but gives up after `timeout` if it is not
+/// `None`, in which case it returns `ETIMEDOUT`.
+pub fn wait_interruptible_timeout<F>(
+    wq: &WaitQueue,
+    timeout: Option<Duration>,
+    condition: F,
+) -> LinuxResult
+where
+    F: Fn() -> bool,
+{
+    block_on(wq, timeout, condition, TaskExt::is_interrupted)
+}
+
+/// Blocks the current thread on `wq` until `condition` holds, `interrupted`
+/// holds for the thread, or `timeout` expires.
+fn block_on<F>(
+    wq: &WaitQueue,
+    timeout: Option<Duration>,
+    condition: F,
+    interrupted: fn(&TaskExt) -> bool,
+) -> LinuxResult
+where
+    F: Fn() -> bool,
+{
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    *ext.blocked_on.lock() = Some(BlockedOn(wq));
+    let woken = || condition() || interrupted(ext);
+    let timed_out = match timeout {
+        Some(timeout) => wq.wait_timeout_until(timeout, woken),
+        None => {
+            wq.wait_until(woken);
+            false
+        }
+    };
+    *ext.blocked_on.lock() = None;
+    if condition() {
+        Ok(())
+    } else if interrupted(ext) {
+        Err(LinuxError::EINTR)
+    } else if timed_out {
+        Err(LinuxError::ETIMEDOUT)
+    } else {
+        // Woken up without the condition, e.g. by a signal which another
+        // thread has handled since.
+        Err(LinuxError::EINTR)
+    }
+}
+
+/// Creates a user task which enters user space with the context in its
+/// [`TaskExt`] when it is first scheduled.
+///
+/// If `set_child_tid` is not 0, the thread ID is stored at this user address
+/// before entering user space.
+pub fn new_user_task(name: &str, set_child_tid: usize) -> TaskInner {
+    TaskInner::new(
+        move || {
             let curr = axtask::current();
+            if set_child_tid != 0 {
+                let _ = put_user(set_child_tid as *mut i32, curr.id().as_u64() as i32);
+            }
             let kstack_top = curr.kernel_stack_top().unwrap();
             info!(
                 "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
@@ -58,11 +872,136 @@ pub fn spawn_user_task(aspace: Arc<Mutex<AddrSpace>>, uctx: UspaceContext) -> Ax
             );
             unsafe { curr.task_ext().uctx.enter_uspace(kstack_top) };
         },
-        "userboot".into(),
+        name.into(),
         crate::config::KERNEL_STACK_SIZE,
-    );
+    )
+}
+
+/// Returns the init process, which adopts the processes whose parent has
+/// exited.
+///
+/// It stands for the kernel itself: it has no threads in user space, and the
+/// processes spawned by the kernel are its children.
+pub fn init_process() -> Arc<Process> {
+    let mut init = INIT_PROCESS.lock();
+    init.get_or_insert_with(|| {
+        let aspace = UserSpace::new().expect("failed to create the init address space");
+        Process::new(
+            axtask::current().id().as_u64(),
+            None,
+            Arc::new(Mutex::new(aspace)),
+            Arc::new(Mutex::new(SignalActions::new())),
+            Arc::new(FdTable::new()),
+            Arc::new(FsContext::new()),
+            SIGCHLD,
+        )
+    })
+    .clone()
+}
+
+/// Spawns the main thread of a new process running in `aspace`.
+///
+/// The new process is a child of the [`init_process`] and the leader of a
+/// new session, whose controlling terminal is the console, which is also its
+/// standard input, output and error.
+pub fn spawn_user_task(aspace: Arc<Mutex<UserSpace>>, uctx: UspaceContext) -> Arc<Process> {
+    let mut task = new_user_task("userboot", 0);
     task.ctx_mut()
         .set_page_table_root(aspace.lock().page_table_root());
-    task.init_task_ext(TaskExt::new(uctx, aspace));
-    axtask::spawn_task(task)
+    let process = Process::new(
+        task.id().as_u64(),
+        Some(&init_process()),
+        aspace,
+        Arc::new(Mutex::new(SignalActions::new())),
+        Arc::new(FdTable::with_stdio()),
+        Arc::new(FsContext::new()),
+        SIGCHLD,
+    );
+    task.init_task_ext(TaskExt::new(uctx, process.clone()));
+    process.new_session();
+    crate::tty::console().set_controlling(&process);
+    process
+        .spawn_thread(task)
+        .expect("a new process cannot be exiting");
+    process
+}
+
+/// Blocks until `process`, a child of the [`init_process`], terminates, and
+/// reaps it together with the orphans that have terminated meanwhile.
+pub fn wait_exit(process: &Process) -> WaitStatus {
+    let init = init_process();
+    let status = loop {
+        let seen = init.child_events();
+        if let Some(status) = process.wait_status(true, false, false, false) {
+            break status;
+        }
+        // The kernel cannot be killed, so wait uninterruptibly.
+        init.child_wq.wait_until(|| init.child_events() != seen);
+    };
+    for child in init.children() {
+        if child.is_zombie() {
+            init.reap_child(child.pid());
+        }
+    }
+    status
+}
+
+/// Terminates the current thread if it has been killed, e.g. by another
+/// thread calling `exit_group`.
+pub fn exit_if_killed() {
+    let curr = axtask::current();
+    if curr.task_ext().is_killed() {
+        // The status is overridden by the one of `exit_group`, if any.
+        exit_current(WaitStatus::Signaled(SIGKILL, false));
+    }
+}
+
+/// Updates the priority of the current thread in the scheduler, if its nice
+/// value or inherited priority has changed.
+pub fn apply_priority() {
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    let priority = ext.effective_priority();
+    if ext.applied_priority.swap(priority, Ordering::AcqRel) != priority {
+        axtask::set_priority(priority);
+    }
+}
+
+/// Terminates the current thread, and the process with `status` if it is
+/// the last thread.
+pub fn exit_current(status: WaitStatus) -> ! {
+    let curr = axtask::current();
+    let robust_list_head = curr.task_ext().robust_list_head();
+    if robust_list_head != 0 {
+        futex::exit_robust_list(robust_list_head as *const _);
+    }
+    let clear_child_tid = curr.task_ext().clear_child_tid() as *mut i32;
+    if !clear_child_tid.is_null() && put_user(clear_child_tid, 0).is_ok() {
+        // Wake up the thread joining this thread, e.g. in `pthread_join`.
+        let _ = futex::wake(clear_child_tid as usize, false, 1, u32::MAX);
+    }
+    curr.task_ext()
+        .process
+        .exit_thread(curr.id().as_u64(), status);
+    axtask::exit(status.as_raw());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WaitStatus;
+
+    #[test]
+    fn wait_status_encoding() {
+        // WIFEXITED, WEXITSTATUS
+        assert_eq!(WaitStatus::Exited(0).as_raw(), 0);
+        assert_eq!(WaitStatus::Exited(3).as_raw(), 0x300);
+        assert_eq!(WaitStatus::Exited(-1).as_raw(), 0xff00);
+        // WIFSIGNALED, WTERMSIG, WCOREDUMP
+        assert_eq!(WaitStatus::Signaled(9, false).as_raw(), 9);
+        assert_eq!(WaitStatus::Signaled(11, true).as_raw(), 0x8b);
+        // WIFSTOPPED, WSTOPSIG
+        assert_eq!(WaitStatus::Stopped(19).as_raw(), 0x137f);
+        // WIFCONTINUED
+        assert_eq!(WaitStatus::Continued.as_raw(), 0xffff);
+    }
 }
@@ -0,0 +1,61 @@
+//! The console of the machine, e.g. its serial port.
+
+use alloc::{boxed::Box, sync::Arc};
+use core::time::Duration;
+
+use axsync::Mutex;
+
+use super::{Tty, TtyDriver};
+use crate::file::makedev;
+
+/// The device number of `/dev/console`.
+const CONSOLE_RDEV: u64 = makedev(5, 1);
+
+/// How often the input of the console is polled, as it does not raise
+/// interrupts.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+struct ConsoleDriver;
+
+impl TtyDriver for ConsoleDriver {
+    fn write(&self, buf: &[u8]) {
+        axhal::console::write_bytes(buf);
+    }
+}
+
+static CONSOLE: Mutex<Option<Arc<Tty>>> = Mutex::new(None);
+
+/// Returns the terminal of the console, starting to poll its input when it
+/// is first used.
+///
+/// The input is polled by a kernel task rather than by the readers, so that
+/// the signals typed reach programs which do not read.
+pub fn console() -> Arc<Tty> {
+    let mut console = CONSOLE.lock();
+    console
+        .get_or_insert_with(|| {
+            let tty = Arc::new(Tty::new(Box::new(ConsoleDriver), CONSOLE_RDEV));
+            let polled = tty.clone();
+            axtask::spawn_raw(
+                move || poll_input(&polled),
+                "console".into(),
+                crate::config::KERNEL_STACK_SIZE,
+            );
+            tty
+        })
+        .clone()
+}
+
+fn poll_input(tty: &Tty) -> ! {
+    let mut buf = [0; 64];
+    loop {
+        let len = axhal::console::read_bytes(&mut buf);
+        if len == 0 {
+            axtask::sleep(POLL_INTERVAL);
+            continue;
+        }
+        for &c in &buf[..len] {
+            tty.receive(c);
+        }
+    }
+}
@@ -0,0 +1,740 @@
+//! Terminals.
+//!
+//! A [`Tty`] is the line discipline of a terminal: its driver hands it the
+//! characters typed, which it assembles into lines, echoes and turns into
+//! signals as its [`Termios`] settings say, and it passes the output of the
+//! programs to the driver. A [`TtyFile`] is a terminal opened by a process.
+//!
+//! A terminal may be the controlling terminal of a session, in which one
+//! process group is in the foreground: it receives the signals typed, and
+//! the other process groups are stopped when they read from the terminal,
+//! or write to it with `TOSTOP`.
+
+mod console;
+mod pty;
+mod termios;
+
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::{Mutex, MutexGuard};
+use axtask::{current, TaskExtRef, WaitQueue};
+
+use self::termios::*;
+pub use self::{
+    console::console,
+    pty::{open_ptmx, open_pts, pts_indices, pts_rdev, PTMX_RDEV},
+};
+use crate::{
+    file::{FileLike, Kstat, PollEvents, PollSet, StatusFlags, S_IFCHR},
+    fs::OpenFlags,
+    signal::{
+        send_process_signal, SigInfo, SIGCONT, SIGHUP, SIGINT, SIGQUIT, SIGTSTP, SIGTTIN, SIGTTOU,
+        SIGWINCH, SI_KERNEL,
+    },
+    task::{
+        is_orphaned_group, process_group, wait_interruptible, wait_interruptible_timeout, Pid,
+        Process,
+    },
+    uaccess::{get_user, put_user},
+};
+
+/// The `ioctl` commands of terminals
+///
+/// See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/ioctls.h>
+const TCGETS: u32 = 0x5401;
+const TCSETS: u32 = 0x5402;
+const TCSETSW: u32 = 0x5403;
+const TCSETSF: u32 = 0x5404;
+const TCFLSH: u32 = 0x540b;
+const TIOCSCTTY: u32 = 0x540e;
+const TIOCGPGRP: u32 = 0x540f;
+const TIOCSPGRP: u32 = 0x5410;
+const TIOCGWINSZ: u32 = 0x5413;
+const TIOCSWINSZ: u32 = 0x5414;
+const FIONREAD: u32 = 0x541b;
+const TIOCNOTTY: u32 = 0x5422;
+const TIOCGSID: u32 = 0x5429;
+
+/// The queues flushed by `TCFLSH`
+const TCIFLUSH: usize = 0;
+const TCOFLUSH: usize = 1;
+const TCIOFLUSH: usize = 2;
+
+/// The most input a terminal holds, beyond which the characters typed are
+/// dropped.
+const INPUT_MAX: usize = 4096;
+
+/// The device behind a terminal, which displays its output.
+pub trait TtyDriver: Send + Sync {
+    fn write(&self, buf: &[u8]);
+
+    /// Whether the output of programs can be written without blocking.
+    fn writable(&self) -> bool {
+        true
+    }
+
+    /// Blocks until the output of programs can be written, unless
+    /// `nonblocking` is set, in which case it fails with `EAGAIN`.
+    fn wait_writable(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+
+    /// Called when the last file referring to the terminal is closed.
+    fn closed(&self) {}
+}
+
+/// The input of a terminal.
+struct Input {
+    /// The input which can be read. In canonical mode, each chunk is a line,
+    /// and an empty chunk is an end of file typed with `VEOF`.
+    ready: VecDeque<Vec<u8>>,
+    /// The number of bytes in `ready`.
+    len: usize,
+    /// The line being typed in canonical mode.
+    line: Vec<u8>,
+}
+
+impl Input {
+    const fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+            len: 0,
+            line: Vec::new(),
+        }
+    }
+
+    /// Makes the line being typed readable.
+    fn commit_line(&mut self) {
+        let line = core::mem::take(&mut self.line);
+        self.len += line.len();
+        self.ready.push_back(line);
+    }
+
+    /// Makes `c` readable right away, as in non-canonical mode. Returns
+    /// whether there was room for it.
+    fn push(&mut self, c: u8) -> bool {
+        if self.len >= INPUT_MAX {
+            return false;
+        }
+        match self.ready.back_mut() {
+            Some(chunk) => chunk.push(c),
+            None => self.ready.push_back(alloc::vec![c]),
+        }
+        self.len += 1;
+        true
+    }
+
+    /// Reads the first line into `buf`, leaving what does not fit for the
+    /// next read. Returns `None` if there is no complete line.
+    fn read_line(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let mut line = self.ready.pop_front()?;
+        let len = buf.len().min(line.len());
+        buf[..len].copy_from_slice(&line[..len]);
+        if len < line.len() {
+            line.drain(..len);
+            self.ready.push_front(line);
+        }
+        self.len -= len;
+        Some(len)
+    }
+
+    /// Reads as many bytes as possible into `buf`, across lines.
+    fn read_bytes(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        while len < buf.len() {
+            match self.read_line(&mut buf[len..]) {
+                Some(read) => len += read,
+                None => break,
+            }
+        }
+        len
+    }
+
+    fn flush(&mut self) {
+        self.ready.clear();
+        self.len = 0;
+        self.line.clear();
+    }
+}
+
+/// Whether `c` is a control character echoed as `^X` with `ECHOCTL`.
+fn is_control(c: u8) -> bool {
+    (c < b' ' && c != b'\t' && c != b'\n') || c == 0x7f
+}
+
+/// Sends `signo` to the processes of the process group `pgid`, on behalf of
+/// a terminal.
+fn signal_group(pgid: Pid, signo: u32) {
+    for process in process_group(pgid) {
+        send_process_signal(&process, SigInfo::new(signo, SI_KERNEL));
+    }
+}
+
+/// The line discipline of a terminal.
+pub struct Tty {
+    driver: Box<dyn TtyDriver>,
+    /// The device number.
+    rdev: u64,
+    termios: Mutex<Termios>,
+    winsize: Mutex<WinSize>,
+    /// The session of which the terminal is the controlling terminal, or 0
+    /// if there is none.
+    session: AtomicU64,
+    /// The foreground process group of the session, or 0 if there is none.
+    foreground: AtomicU64,
+    /// The number of open files referring to the terminal.
+    files: AtomicUsize,
+    /// Whether the device is gone, e.g. the master of a pseudo-terminal was
+    /// closed: reads return the end of file and writes fail.
+    disconnected: AtomicBool,
+    input: Mutex<Input>,
+    /// The number of chunks and bytes of [`Input::ready`], read without
+    /// locking it.
+    ready_chunks: AtomicUsize,
+    ready_bytes: AtomicUsize,
+    /// Readers wait here for input.
+    read_wq: WaitQueue,
+    poll: Arc<PollSet>,
+}
+
+impl Tty {
+    pub fn new(driver: Box<dyn TtyDriver>, rdev: u64) -> Self {
+        Self {
+            driver,
+            rdev,
+            termios: Mutex::new(Termios::new()),
+            winsize: Mutex::new(WinSize::new()),
+            session: AtomicU64::new(0),
+            foreground: AtomicU64::new(0),
+            files: AtomicUsize::new(0),
+            disconnected: AtomicBool::new(false),
+            input: Mutex::new(Input::new()),
+            ready_chunks: AtomicUsize::new(0),
+            ready_bytes: AtomicUsize::new(0),
+            read_wq: WaitQueue::new(),
+            poll: Arc::new(PollSet::new()),
+        }
+    }
+
+    fn termios(&self) -> Termios {
+        *self.termios.lock()
+    }
+
+    /// Replaces the settings. Leaving canonical mode makes the line being
+    /// typed readable.
+    fn set_termios(&self, termios: Termios) {
+        let mut input = self.input.lock();
+        let old = core::mem::replace(&mut *self.termios.lock(), termios);
+        if old.canonical() && !termios.canonical() && !input.line.is_empty() {
+            input.commit_line();
+        }
+        self.input_changed(input);
+    }
+
+    fn session(&self) -> Pid {
+        self.session.load(Ordering::Acquire)
+    }
+
+    fn foreground(&self) -> Pid {
+        self.foreground.load(Ordering::Acquire)
+    }
+
+    /// Whether the terminal is the controlling terminal of `process`.
+    fn controls(&self, process: &Process) -> bool {
+        self.session() == process.sid()
+    }
+
+    /// Makes the terminal the controlling terminal of the session of
+    /// `process`, its leader, with its process group in the foreground.
+    pub fn set_controlling(self: &Arc<Self>, process: &Process) {
+        self.session.store(process.sid(), Ordering::Release);
+        self.foreground.store(process.pgid(), Ordering::Release);
+        process.set_tty(Some(self.clone()));
+    }
+
+    /// Detaches the terminal from its session, hanging up the foreground
+    /// process group with `SIGHUP` and `SIGCONT`.
+    fn hang_up(&self) {
+        self.signal_foreground(SIGHUP);
+        self.signal_foreground(SIGCONT);
+        self.session.store(0, Ordering::Release);
+        self.foreground.store(0, Ordering::Release);
+    }
+
+    /// Makes the terminal the controlling terminal of `process` if it is a
+    /// session leader without one, and the terminal has no session, as
+    /// opening it without `O_NOCTTY` does.
+    pub fn acquire(self: &Arc<Self>, process: &Process) {
+        let has_tty = process.tty().is_some_and(|tty| tty.controls(process));
+        if process.is_session_leader() && !has_tty && self.session() == 0 {
+            self.set_controlling(process);
+        }
+    }
+
+    /// Disconnects the terminal from its device, which is gone, hanging up
+    /// its session.
+    pub fn disconnect(&self) {
+        self.disconnected.store(true, Ordering::Release);
+        self.hang_up();
+        self.read_wq.notify_all(false);
+        self.poll.wake(PollEvents::READABLE | PollEvents::POLLHUP);
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Acquire)
+    }
+
+    /// Called when `process` gives up the terminal as its controlling
+    /// terminal, by exiting or with `TIOCNOTTY`. The session loses the
+    /// terminal if `process` is its leader.
+    pub fn leave(&self, process: &Process) {
+        if process.is_session_leader() && self.controls(process) {
+            self.hang_up();
+        }
+    }
+
+    /// Sends `signo` to the processes of the foreground process group.
+    fn signal_foreground(&self, signo: u32) {
+        signal_group(self.foreground(), signo);
+    }
+
+    /// Checks that the current process may read from the terminal, if
+    /// `signo` is `SIGTTIN`, or write to it or change its settings, if
+    /// `signo` is `SIGTTOU`.
+    ///
+    /// A process in the background of the session of the terminal may not:
+    /// its process group is sent `signo` to stop it, and the system call is
+    /// interrupted, to be restarted once it is continued. If `signo` is
+    /// ignored or blocked, reading fails with `EIO` and writing is allowed.
+    fn check_foreground(&self, signo: u32) -> LinuxResult {
+        let curr = current();
+        let ext = curr.task_ext();
+        let process = &ext.process;
+        let pgid = process.pgid();
+        if !self.controls(process) || pgid == self.foreground() {
+            return Ok(());
+        }
+        let action = process.signal_actions().lock().get(signo);
+        if action.is_ignored(signo) || ext.signals.mask().contains(signo) {
+            return if signo == SIGTTIN {
+                Err(LinuxError::EIO)
+            } else {
+                Ok(())
+            };
+        }
+        // Nobody would continue an orphaned process group.
+        if is_orphaned_group(pgid) {
+            return Err(LinuxError::EIO);
+        }
+        signal_group(pgid, signo);
+        Err(LinuxError::EINTR)
+    }
+
+    /// Publishes the state of `input` to the readers, after it changed.
+    fn input_changed(&self, input: MutexGuard<Input>) {
+        self.ready_chunks
+            .store(input.ready.len(), Ordering::Release);
+        self.ready_bytes.store(input.len, Ordering::Release);
+        drop(input);
+        self.read_wq.notify_all(false);
+        self.poll.wake(PollEvents::READABLE);
+    }
+
+    fn flush_input(&self) {
+        let mut input = self.input.lock();
+        input.flush();
+        self.input_changed(input);
+    }
+
+    /// Writes `buf` to the driver, translating newlines with `ONLCR`.
+    fn output(&self, termios: &Termios, buf: &[u8]) {
+        if termios.oflag & OPOST == 0 || termios.oflag & ONLCR == 0 {
+            self.driver.write(buf);
+            return;
+        }
+        for (i, line) in buf.split(|&c| c == b'\n').enumerate() {
+            if i > 0 {
+                self.driver.write(b"\r\n");
+            }
+            self.driver.write(line);
+        }
+    }
+
+    /// Echoes the character `c` typed, showing the control characters as
+    /// `^X` with `ECHOCTL`.
+    fn echo(&self, termios: &Termios, c: u8) {
+        if termios.lflag & ECHO == 0 && !(c == b'\n' && termios.lflag & ECHONL != 0) {
+            return;
+        }
+        if is_control(c) && termios.lflag & ECHOCTL != 0 {
+            self.output(termios, &[b'^', c ^ 0x40]);
+        } else {
+            self.output(termios, &[c]);
+        }
+    }
+
+    /// Processes the character `c` typed on the terminal, called by the
+    /// driver.
+    pub fn receive(&self, mut c: u8) {
+        let termios = self.termios();
+        if termios.iflag & ISTRIP != 0 {
+            c &= 0x7f;
+        }
+        match c {
+            b'\r' if termios.iflag & IGNCR != 0 => return,
+            b'\r' if termios.iflag & ICRNL != 0 => c = b'\n',
+            b'\n' if termios.iflag & INLCR != 0 => c = b'\r',
+            _ => {}
+        }
+        if termios.lflag & ISIG != 0 {
+            let signo = [(VINTR, SIGINT), (VQUIT, SIGQUIT), (VSUSP, SIGTSTP)]
+                .into_iter()
+                .find(|&(index, _)| termios.is_char(c, index));
+            if let Some((_, signo)) = signo {
+                if termios.lflag & NOFLSH == 0 {
+                    self.flush_input();
+                }
+                self.echo(&termios, c);
+                self.signal_foreground(signo);
+                return;
+            }
+        }
+
+        let mut input = self.input.lock();
+        if !termios.canonical() {
+            if input.push(c) {
+                self.echo(&termios, c);
+            }
+        } else if termios.is_char(c, VERASE)
+            || termios.is_char(c, VKILL)
+            || (termios.lflag & IEXTEN != 0 && termios.is_char(c, VWERASE))
+        {
+            self.edit(&termios, &mut input.line, c);
+        } else if c == b'\n' || termios.is_char(c, VEOL) || termios.is_char(c, VEOL2) {
+            input.line.push(c);
+            input.commit_line();
+            self.echo(&termios, c);
+        } else if termios.is_char(c, VEOF) {
+            // The end of file is not part of the line, and ends the reads
+            // with an empty line.
+            input.commit_line();
+        } else if input.len + input.line.len() + 1 < INPUT_MAX {
+            // There is always room left for the end of the line.
+            input.line.push(c);
+            self.echo(&termios, c);
+        }
+        self.input_changed(input);
+    }
+
+    /// Edits the line being typed in canonical mode with `c`, which erases
+    /// the last character (`VERASE`), the whole line (`VKILL`), or the last
+    /// word (`VWERASE`).
+    ///
+    /// With `ECHOE`, the erased characters are also erased on the screen, as
+    /// for the whole line with `ECHOKE`. Otherwise, `c` is echoed, followed
+    /// by a newline for `VKILL` with `ECHOK`.
+    fn edit(&self, termios: &Termios, line: &mut Vec<u8>, c: u8) {
+        let kill = termios.is_char(c, VKILL);
+        let start = if termios.is_char(c, VERASE) {
+            line.len().saturating_sub(1)
+        } else if kill {
+            0
+        } else {
+            // The blanks at the end of the line go with the word.
+            let blank = |c: &u8| *c == b' ' || *c == b'\t';
+            let end = line.iter().rposition(|c| !blank(c)).map_or(0, |i| i + 1);
+            line[..end].iter().rposition(blank).map_or(0, |i| i + 1)
+        };
+        let erased = line.split_off(start);
+        if erased.is_empty() || termios.lflag & ECHO == 0 {
+            return;
+        }
+        if termios.lflag & ECHOE != 0 && (!kill || termios.lflag & ECHOKE != 0) {
+            for &c in erased.iter().rev() {
+                // A control character is echoed as `^X` with `ECHOCTL`.
+                let width = if is_control(c) && termios.lflag & ECHOCTL != 0 {
+                    2
+                } else {
+                    1
+                };
+                for _ in 0..width {
+                    self.output(termios, b"\x08 \x08");
+                }
+            }
+        } else {
+            self.echo(termios, c);
+            if kill && termios.lflag & ECHOK != 0 {
+                self.output(termios, b"\n");
+            }
+        }
+    }
+
+    /// Reads the input into `buf`.
+    ///
+    /// In canonical mode, a read returns at most one line, and blocks until
+    /// a line is complete. Otherwise, it waits for `VMIN` bytes, with a
+    /// timeout of `VTIME` tenths of a second after the first one, or for
+    /// `VTIME` alone if `VMIN` is 0.
+    pub fn read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        self.check_foreground(SIGTTIN)?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let termios = self.termios();
+        if termios.canonical() {
+            loop {
+                let mut input = self.input.lock();
+                if let Some(len) = input.read_line(buf) {
+                    self.input_changed(input);
+                    return Ok(len);
+                }
+                drop(input);
+                if self.is_disconnected() {
+                    return Ok(0);
+                }
+                if nonblocking {
+                    return Err(LinuxError::EAGAIN);
+                }
+                wait_interruptible(&self.read_wq, || {
+                    self.ready_chunks.load(Ordering::Acquire) > 0 || self.is_disconnected()
+                })?;
+            }
+        }
+
+        let min = termios.cc[VMIN] as usize;
+        let time = termios.cc[VTIME];
+        let timeout = (time > 0).then(|| Duration::from_millis(time as u64 * 100));
+        let wanted = min.clamp(1, buf.len());
+        loop {
+            let blocking = !nonblocking && (min > 0 || timeout.is_some());
+            if blocking {
+                if min > 0 {
+                    wait_interruptible(&self.read_wq, || {
+                        self.ready_bytes.load(Ordering::Acquire) > 0 || self.is_disconnected()
+                    })?;
+                }
+                match wait_interruptible_timeout(&self.read_wq, timeout, || {
+                    self.ready_bytes.load(Ordering::Acquire) >= wanted || self.is_disconnected()
+                }) {
+                    Err(LinuxError::ETIMEDOUT) => {}
+                    res => res?,
+                }
+            }
+            let mut input = self.input.lock();
+            let len = input.read_bytes(buf);
+            self.input_changed(input);
+            if len == 0 && nonblocking && !self.is_disconnected() {
+                return Err(LinuxError::EAGAIN);
+            }
+            // Another reader may have taken the input first.
+            if len > 0 || !blocking || min == 0 || self.is_disconnected() {
+                return Ok(len);
+            }
+        }
+    }
+
+    /// Returns the events of the terminal: it is readable when a read does
+    /// not block, and hung up once disconnected.
+    fn poll(&self) -> PollEvents {
+        let mut events = PollEvents::empty();
+        let readable = if self.termios().canonical() {
+            self.ready_chunks.load(Ordering::Acquire) > 0
+        } else {
+            self.ready_bytes.load(Ordering::Acquire) > 0
+        };
+        if self.is_disconnected() {
+            events |= PollEvents::READABLE | PollEvents::POLLHUP;
+        } else if readable {
+            events |= PollEvents::READABLE;
+        }
+        if self.driver.writable() {
+            events |= PollEvents::WRITABLE;
+        }
+        events
+    }
+
+    /// Writes `buf` to the terminal, which background processes may only do
+    /// without `TOSTOP`.
+    pub fn write(&self, buf: &[u8], nonblocking: bool) -> LinuxResult<usize> {
+        let termios = self.termios();
+        if termios.lflag & TOSTOP != 0 {
+            self.check_foreground(SIGTTOU)?;
+        }
+        if self.is_disconnected() {
+            return Err(LinuxError::EIO);
+        }
+        self.driver.wait_writable(nonblocking)?;
+        self.output(&termios, buf);
+        Ok(buf.len())
+    }
+
+    /// Executes the terminal `ioctl` command `cmd`.
+    pub fn ioctl(self: &Arc<Self>, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        if matches!(cmd, TCSETS | TCSETSW | TCSETSF | TCFLSH | TIOCSPGRP) {
+            self.check_foreground(SIGTTOU)?;
+        }
+        let process = current().task_ext().process.clone();
+        let controlling = self.controls(&process);
+        match cmd {
+            TCGETS => put_user(arg as *mut Termios, self.termios())?,
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = get_user(arg as *const Termios)?;
+                // The output is written synchronously, so there is nothing
+                // to wait for with `TCSETSW`.
+                if cmd == TCSETSF {
+                    self.flush_input();
+                }
+                self.set_termios(termios);
+            }
+            TCFLSH => match arg {
+                TCIFLUSH | TCIOFLUSH => self.flush_input(),
+                TCOFLUSH => {}
+                _ => return Err(LinuxError::EINVAL),
+            },
+            TIOCGPGRP | TIOCSPGRP | TIOCGSID | TIOCNOTTY if !controlling => {
+                return Err(LinuxError::ENOTTY);
+            }
+            TIOCGPGRP => put_user(arg as *mut i32, self.foreground() as i32)?,
+            TIOCSPGRP => {
+                let pgid = get_user(arg as *const i32)?;
+                if pgid < 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let group = process_group(pgid as Pid);
+                let Some(leader) = group.first() else {
+                    return Err(LinuxError::ESRCH);
+                };
+                if leader.sid() != process.sid() {
+                    return Err(LinuxError::EPERM);
+                }
+                self.foreground.store(pgid as Pid, Ordering::Release);
+            }
+            TIOCGSID => put_user(arg as *mut i32, self.session() as i32)?,
+            TIOCSCTTY if !controlling => {
+                let has_tty = process.tty().is_some_and(|tty| tty.controls(&process));
+                if !process.is_session_leader() || has_tty {
+                    return Err(LinuxError::EPERM);
+                }
+                // The terminal of another session can only be stolen with 1
+                // as the argument, by root, which all processes are.
+                if self.session() != 0 && arg != 1 {
+                    return Err(LinuxError::EPERM);
+                }
+                self.set_controlling(&process);
+            }
+            TIOCSCTTY => {}
+            TIOCNOTTY => {
+                self.leave(&process);
+                process.set_tty(None);
+            }
+            TIOCGWINSZ => put_user(arg as *mut WinSize, *self.winsize.lock())?,
+            TIOCSWINSZ => {
+                let winsize = get_user(arg as *const WinSize)?;
+                if core::mem::replace(&mut *self.winsize.lock(), winsize) != winsize {
+                    self.signal_foreground(SIGWINCH);
+                }
+            }
+            FIONREAD => {
+                let len = self.ready_bytes.load(Ordering::Acquire);
+                put_user(arg as *mut i32, len as i32)?;
+            }
+            _ => {
+                warn!("Unimplemented tty ioctl: {:#x}", cmd);
+                return Err(LinuxError::ENOTTY);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// A terminal opened by a process.
+pub struct TtyFile {
+    tty: Arc<Tty>,
+    flags: StatusFlags,
+}
+
+impl TtyFile {
+    pub fn new(tty: Arc<Tty>, flags: OpenFlags) -> Self {
+        tty.files.fetch_add(1, Ordering::AcqRel);
+        Self {
+            tty,
+            flags: StatusFlags::new(flags),
+        }
+    }
+
+    fn do_read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        if !self.flags.get().readable() {
+            return Err(LinuxError::EBADF);
+        }
+        self.tty.read(buf, nonblocking)
+    }
+
+    fn do_write(&self, buf: &[u8], nonblocking: bool) -> LinuxResult<usize> {
+        if !self.flags.get().writable() {
+            return Err(LinuxError::EBADF);
+        }
+        self.tty.write(buf, nonblocking)
+    }
+}
+
+impl Drop for TtyFile {
+    fn drop(&mut self) {
+        if self.tty.files.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tty.driver.closed();
+        }
+    }
+}
+
+impl FileLike for TtyFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, self.flags.nonblocking())
+    }
+
+    fn read_nowait(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, true)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.do_write(buf, self.flags.nonblocking())
+    }
+
+    fn write_nowait(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.do_write(buf, true)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: S_IFCHR | 0o620,
+            nlink: 1,
+            rdev: self.tty.rdev,
+            blksize: 1024,
+            ..Default::default()
+        })
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    fn poll(&self) -> PollEvents {
+        self.tty.poll()
+    }
+
+    fn poll_set(&self) -> Option<Arc<PollSet>> {
+        Some(self.tty.poll.clone())
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        self.tty.ioctl(cmd, arg)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,298 @@
+//! Pseudo-terminals.
+//!
+//! A pseudo-terminal is a terminal, the slave, whose device is a file, the
+//! master: what is written to the master is typed on the slave, and the
+//! output of the slave is read from the master. The master is opened from
+//! `/dev/ptmx`, which creates a new pseudo-terminal, whose slave is then
+//! `/dev/pts/N`. The pseudo-terminal is gone once the master is closed.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use axerrno::{LinuxError, LinuxResult};
+use axsync::Mutex;
+use axtask::{current, TaskExtRef, WaitQueue};
+
+use super::{Tty, TtyDriver, TtyFile, TIOCGPGRP};
+use crate::{
+    file::{makedev, FileLike, Kstat, PollEvents, PollSet, StatusFlags, S_IFCHR},
+    fs::OpenFlags,
+    task::wait_interruptible,
+    uaccess::{get_user, put_user},
+};
+
+/// The device number of `/dev/ptmx`.
+pub const PTMX_RDEV: u64 = makedev(5, 2);
+/// The major device number of the slaves.
+const PTS_MAJOR: u32 = 136;
+
+/// The most output of the slave which has not been read from the master,
+/// beyond which the programs writing to the slave block.
+const OUTPUT_MAX: usize = 16 * 1024;
+
+/// The `ioctl` commands of the master
+const TIOCGPTN: u32 = 0x8004_5430;
+const TIOCSPTLCK: u32 = 0x4004_5431;
+const TIOCGPTLCK: u32 = 0x8004_5439;
+
+/// The output of the slave, read from the master.
+struct Output {
+    data: Mutex<VecDeque<u8>>,
+    /// The number of bytes in `data`, read without locking it.
+    len: AtomicUsize,
+    /// The readers of the master wait here for output, or for the slave to
+    /// be closed.
+    read_wq: WaitQueue,
+    /// The writers to the slave wait here for room.
+    write_wq: WaitQueue,
+    /// The wakers watching the master.
+    poll: Arc<PollSet>,
+}
+
+/// The driver of a slave, which hands its output to the master.
+struct PtyDriver(Arc<Output>);
+
+impl TtyDriver for PtyDriver {
+    fn write(&self, buf: &[u8]) {
+        let output = &self.0;
+        let mut data = output.data.lock();
+        data.extend(buf);
+        output.len.store(data.len(), Ordering::Release);
+        drop(data);
+        output.read_wq.notify_all(false);
+        output.poll.wake(PollEvents::READABLE);
+    }
+
+    fn writable(&self) -> bool {
+        self.0.len.load(Ordering::Acquire) < OUTPUT_MAX
+    }
+
+    fn wait_writable(&self, nonblocking: bool) -> LinuxResult {
+        if self.writable() {
+            return Ok(());
+        }
+        if nonblocking {
+            return Err(LinuxError::EAGAIN);
+        }
+        wait_interruptible(&self.0.write_wq, || self.writable())
+    }
+
+    fn closed(&self) {
+        self.0.read_wq.notify_all(false);
+        self.0.poll.wake(PollEvents::POLLHUP);
+    }
+}
+
+/// A pseudo-terminal.
+struct Pty {
+    /// The number of the slave in `/dev/pts`.
+    index: u32,
+    slave: Arc<Tty>,
+    output: Arc<Output>,
+    /// Whether the slave cannot be opened, until `TIOCSPTLCK` unlocks it.
+    locked: AtomicBool,
+    /// Whether the slave has been opened, after which the reads from the
+    /// master fail with `EIO` whenever it is closed.
+    slave_opened: AtomicBool,
+}
+
+impl Pty {
+    fn slave_closed(&self) -> bool {
+        self.slave_opened.load(Ordering::Acquire) && self.slave.files.load(Ordering::Acquire) == 0
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        PTYS.lock().remove(&self.index);
+        self.slave.disconnect();
+        self.output.write_wq.notify_all(false);
+    }
+}
+
+/// The pseudo-terminals, indexed by the number of their slave.
+static PTYS: Mutex<BTreeMap<u32, Weak<Pty>>> = Mutex::new(BTreeMap::new());
+
+fn get_pty(index: u32) -> Option<Arc<Pty>> {
+    PTYS.lock().get(&index).and_then(Weak::upgrade)
+}
+
+/// Returns the numbers of the slaves in `/dev/pts`.
+pub fn pts_indices() -> Vec<u32> {
+    PTYS.lock().keys().copied().collect()
+}
+
+/// Returns the device number of the slave `/dev/pts/{index}`.
+pub const fn pts_rdev(index: u32) -> u64 {
+    makedev(PTS_MAJOR, index)
+}
+
+/// Creates a pseudo-terminal, returning its master opened with `flags`. Its
+/// slave is locked.
+pub fn open_ptmx(flags: OpenFlags) -> LinuxResult<Arc<dyn FileLike>> {
+    let mut ptys = PTYS.lock();
+    let index = (0..u32::MAX)
+        .find(|index| !ptys.contains_key(index))
+        .ok_or(LinuxError::ENOSPC)?;
+    let output = Arc::new(Output {
+        data: Mutex::new(VecDeque::new()),
+        len: AtomicUsize::new(0),
+        read_wq: WaitQueue::new(),
+        write_wq: WaitQueue::new(),
+        poll: Arc::new(PollSet::new()),
+    });
+    let driver = Box::new(PtyDriver(output.clone()));
+    let pty = Arc::new(Pty {
+        index,
+        slave: Arc::new(Tty::new(driver, pts_rdev(index))),
+        output,
+        locked: AtomicBool::new(true),
+        slave_opened: AtomicBool::new(false),
+    });
+    ptys.insert(index, Arc::downgrade(&pty));
+    Ok(Arc::new(PtyMaster {
+        pty,
+        flags: StatusFlags::new(flags),
+    }))
+}
+
+/// Opens the slave `/dev/pts/{index}` with `flags`, which becomes the
+/// controlling terminal of the current process without `O_NOCTTY` if it is a
+/// session leader without one.
+///
+/// Fails with `EIO` while the slave is locked.
+pub fn open_pts(index: u32, flags: OpenFlags) -> LinuxResult<Arc<dyn FileLike>> {
+    let pty = get_pty(index).ok_or(LinuxError::ENOENT)?;
+    if pty.locked.load(Ordering::Acquire) {
+        return Err(LinuxError::EIO);
+    }
+    let file = TtyFile::new(pty.slave.clone(), flags);
+    pty.slave_opened.store(true, Ordering::Release);
+    if !flags.contains(OpenFlags::O_NOCTTY) {
+        pty.slave.acquire(&current().task_ext().process);
+    }
+    Ok(Arc::new(file))
+}
+
+/// The master of a pseudo-terminal.
+struct PtyMaster {
+    pty: Arc<Pty>,
+    flags: StatusFlags,
+}
+
+impl PtyMaster {
+    /// Reads the output of the slave, blocking until there is some unless
+    /// `nonblocking` is set. Fails with `EIO` once the slave is closed.
+    fn do_read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let output = &self.pty.output;
+        loop {
+            let mut data = output.data.lock();
+            if !data.is_empty() {
+                let len = buf.len().min(data.len());
+                for (dst, src) in buf.iter_mut().zip(data.drain(..len)) {
+                    *dst = src;
+                }
+                output.len.store(data.len(), Ordering::Release);
+                drop(data);
+                output.write_wq.notify_all(false);
+                self.pty.slave.poll.wake(PollEvents::WRITABLE);
+                return Ok(len);
+            }
+            drop(data);
+            if self.pty.slave_closed() {
+                return Err(LinuxError::EIO);
+            }
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            wait_interruptible(&output.read_wq, || {
+                output.len.load(Ordering::Acquire) > 0 || self.pty.slave_closed()
+            })?;
+        }
+    }
+}
+
+impl FileLike for PtyMaster {
+    /// Reads the output of the slave, blocking until there is some unless
+    /// the file is non-blocking. Fails with `EIO` once the slave is closed.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, self.flags.nonblocking())
+    }
+
+    fn read_nowait(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.do_read(buf, true)
+    }
+
+    /// Types `buf` on the slave.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        for &c in buf {
+            self.pty.slave.receive(c);
+        }
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: S_IFCHR | 0o666,
+            nlink: 1,
+            rdev: PTMX_RDEV,
+            blksize: 1024,
+            ..Default::default()
+        })
+    }
+
+    fn status_flags(&self) -> &StatusFlags {
+        &self.flags
+    }
+
+    /// The master is readable while the slave has output, and hung up once
+    /// the slave is closed. What is typed on the slave is never blocked.
+    fn poll(&self) -> PollEvents {
+        let mut events = PollEvents::WRITABLE;
+        if self.pty.output.len.load(Ordering::Acquire) > 0 {
+            events |= PollEvents::READABLE;
+        }
+        if self.pty.slave_closed() {
+            events |= PollEvents::POLLHUP;
+        }
+        events
+    }
+
+    fn poll_set(&self) -> Option<Arc<PollSet>> {
+        Some(self.pty.output.poll.clone())
+    }
+
+    /// Executes the `ioctl` commands of the master, or the ones of the
+    /// slave, which are executed on the slave.
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<isize> {
+        let pty = &self.pty;
+        match cmd {
+            TIOCGPTN => put_user(arg as *mut u32, pty.index)?,
+            TIOCSPTLCK => {
+                let locked = get_user(arg as *const i32)? != 0;
+                pty.locked.store(locked, Ordering::Release);
+            }
+            TIOCGPTLCK => put_user(arg as *mut i32, pty.locked.load(Ordering::Acquire) as i32)?,
+            // The master may get the foreground process group of the slave
+            // without it being its controlling terminal.
+            TIOCGPGRP => put_user(arg as *mut i32, pty.slave.foreground() as i32)?,
+            _ => return pty.slave.ioctl(cmd, arg),
+        }
+        Ok(0)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
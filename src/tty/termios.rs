@@ -0,0 +1,127 @@
+//! The terminal settings of `termios(3)`.
+//!
+//! See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/termbits.h>
+
+/// The number of control characters in [`Termios::cc`].
+const NCCS: usize = 19;
+
+/// The indexes of the control characters
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSTART: usize = 8;
+pub const VSTOP: usize = 9;
+pub const VSUSP: usize = 10;
+pub const VEOL: usize = 11;
+pub const VREPRINT: usize = 12;
+pub const VDISCARD: usize = 13;
+pub const VWERASE: usize = 14;
+pub const VLNEXT: usize = 15;
+pub const VEOL2: usize = 16;
+
+/// The input flags
+pub const ISTRIP: u32 = 0o40;
+pub const INLCR: u32 = 0o100;
+pub const IGNCR: u32 = 0o200;
+pub const ICRNL: u32 = 0o400;
+pub const IXON: u32 = 0o2000;
+
+/// The output flags
+pub const OPOST: u32 = 0o1;
+pub const ONLCR: u32 = 0o4;
+
+/// The control flags
+const B38400: u32 = 0o17;
+const CS8: u32 = 0o60;
+const CREAD: u32 = 0o200;
+const HUPCL: u32 = 0o2000;
+
+/// The local flags
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+pub const ECHOE: u32 = 0o20;
+pub const ECHOK: u32 = 0o40;
+pub const ECHONL: u32 = 0o100;
+pub const NOFLSH: u32 = 0o200;
+pub const TOSTOP: u32 = 0o400;
+pub const ECHOCTL: u32 = 0o1000;
+pub const ECHOKE: u32 = 0o4000;
+pub const IEXTEN: u32 = 0o100000;
+
+/// `struct termios` of the `TCGETS` and `TCSETS` ioctls.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// The settings of a new terminal, as `stty sane` sets them.
+    pub const fn new() -> Self {
+        let mut cc = [0; NCCS];
+        cc[VINTR] = 0x03; // ^C
+        cc[VQUIT] = 0x1c; // ^\
+        cc[VERASE] = 0x7f; // DEL
+        cc[VKILL] = 0x15; // ^U
+        cc[VEOF] = 0x04; // ^D
+        cc[VMIN] = 1;
+        cc[VSTART] = 0x11; // ^Q
+        cc[VSTOP] = 0x13; // ^S
+        cc[VSUSP] = 0x1a; // ^Z
+        cc[VREPRINT] = 0x12; // ^R
+        cc[VDISCARD] = 0x0f; // ^O
+        cc[VWERASE] = 0x17; // ^W
+        cc[VLNEXT] = 0x16; // ^V
+        Self {
+            iflag: ICRNL | IXON,
+            oflag: OPOST | ONLCR,
+            cflag: B38400 | CS8 | CREAD | HUPCL,
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            line: 0,
+            cc,
+        }
+    }
+
+    /// Whether the input is processed by lines (`ICANON`).
+    pub fn canonical(&self) -> bool {
+        self.lflag & ICANON != 0
+    }
+
+    /// Whether `c` is the control character at `index`, which is disabled
+    /// if it is 0.
+    pub fn is_char(&self, c: u8, index: usize) -> bool {
+        self.cc[index] != 0 && self.cc[index] == c
+    }
+}
+
+/// `struct winsize` of the `TIOCGWINSZ` and `TIOCSWINSZ` ioctls.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WinSize {
+    pub row: u16,
+    pub col: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+impl WinSize {
+    /// The size of a new terminal, as a VT100.
+    pub const fn new() -> Self {
+        Self {
+            row: 24,
+            col: 80,
+            xpixel: 0,
+            ypixel: 0,
+        }
+    }
+}
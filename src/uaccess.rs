@@ -0,0 +1,170 @@
+//! Accessing user memory from system calls.
+//!
+//! The kernel shares the page table with the current user process, so user
+//! pointers can be dereferenced directly, but only after checking that the
+//! memory is mapped with the required permissions in the user address space,
+//! and while the address space is locked, so that another thread cannot
+//! unmap it. Otherwise a bad pointer from user space would crash the kernel.
+//!
+//! User buffers are copied to or from kernel buffers, as the file operations
+//! using them may block, which they cannot do with the address space locked.
+
+use alloc::{string::String, vec::Vec};
+use core::{ffi::c_char, mem::size_of, sync::atomic::AtomicU32};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::paging::MappingFlags;
+use axtask::{current, TaskExtRef};
+use memory_addr::{VirtAddr, PAGE_SIZE_4K};
+
+/// Checks that `[start, start + size)` is accessible with `access_flags` in
+/// the current user address space, populating the pages in it.
+pub fn check_region(start: usize, size: usize, access_flags: MappingFlags) -> LinuxResult {
+    with_region(start, size, access_flags, || ())
+}
+
+/// Checks the region as [`check_region`], and calls `f` to access it with
+/// the address space locked, so that the region cannot be unmapped by
+/// another thread in the meantime.
+fn with_region<R>(
+    start: usize,
+    size: usize,
+    access_flags: MappingFlags,
+    f: impl FnOnce() -> R,
+) -> LinuxResult<R> {
+    if start.checked_add(size).is_none() {
+        return Err(LinuxError::EFAULT);
+    }
+    let curr = current();
+    let aspace = curr.task_ext().process.aspace();
+    let mut aspace = aspace.lock();
+    if size == 0 || aspace.populate(VirtAddr::from(start), size, access_flags) {
+        Ok(f())
+    } else {
+        Err(LinuxError::EFAULT)
+    }
+}
+
+/// Reads a value of type `T` from user space.
+pub fn get_user<T: Copy>(ptr: *const T) -> LinuxResult<T> {
+    with_region(
+        ptr as usize,
+        size_of::<T>(),
+        MappingFlags::READ,
+        || unsafe { ptr.read_unaligned() },
+    )
+}
+
+/// Allocates a zeroed kernel buffer of `len` bytes for the data of a user
+/// buffer, failing with `ENOMEM` instead of panicking if it is too large.
+pub fn kernel_buf(len: usize) -> LinuxResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| LinuxError::ENOMEM)?;
+    buf.resize(len, 0);
+    Ok(buf)
+}
+
+/// Copies the user buffer of `len` bytes at `ptr` into the kernel.
+pub fn copy_from_user(ptr: *const u8, len: usize) -> LinuxResult<Vec<u8>> {
+    let mut buf = kernel_buf(len)?;
+    with_region(ptr as usize, len, MappingFlags::READ, || unsafe {
+        buf.as_mut_ptr().copy_from_nonoverlapping(ptr, len)
+    })?;
+    Ok(buf)
+}
+
+/// Copies `buf` to the user buffer at `ptr`.
+pub fn copy_to_user(ptr: *mut u8, buf: &[u8]) -> LinuxResult {
+    with_region(ptr as usize, buf.len(), MappingFlags::WRITE, || unsafe {
+        ptr.copy_from_nonoverlapping(buf.as_ptr(), buf.len())
+    })
+}
+
+/// Reads at most `len` bytes with `read` into a kernel buffer, and copies
+/// them to the user buffer at `ptr`, returning the number of bytes read.
+///
+/// The user buffer is checked to be writable before reading, so that no
+/// input is lost in case it is not.
+pub fn read_to_user(
+    ptr: *mut u8,
+    len: usize,
+    read: impl FnOnce(&mut [u8]) -> LinuxResult<usize>,
+) -> LinuxResult<usize> {
+    check_region(ptr as usize, len, MappingFlags::WRITE)?;
+    let mut buf = kernel_buf(len)?;
+    let read = read(&mut buf)?;
+    copy_to_user(ptr, &buf[..read])?;
+    Ok(read)
+}
+
+/// Reads a null-terminated string from user space.
+pub fn get_user_str(ptr: *const c_char) -> LinuxResult<String> {
+    let mut bytes = Vec::new();
+    let mut addr = ptr as usize;
+    loop {
+        // Copy page by page, as the string may end before an unmapped page.
+        let len = PAGE_SIZE_4K - addr % PAGE_SIZE_4K;
+        let end = with_region(addr, len, MappingFlags::READ, || {
+            let chunk = unsafe { core::slice::from_raw_parts(addr as *const u8, len) };
+            let end = chunk.iter().position(|&c| c == 0);
+            bytes.extend_from_slice(&chunk[..end.unwrap_or(len)]);
+            end
+        })?;
+        if end.is_some() {
+            break;
+        }
+        addr += len;
+    }
+    String::from_utf8(bytes).map_err(|_| LinuxError::EINVAL)
+}
+
+/// Reads a null-terminated array of null-terminated strings (e.g. `argv`)
+/// from user space.
+///
+/// A null `ptr` is treated as an empty array.
+pub fn get_user_str_vec(ptr: *const *const c_char) -> LinuxResult<Vec<String>> {
+    let mut strs = Vec::new();
+    if ptr.is_null() {
+        return Ok(strs);
+    }
+    loop {
+        let str_ptr = get_user(ptr.wrapping_add(strs.len()))?;
+        if str_ptr.is_null() {
+            break;
+        }
+        strs.push(get_user_str(str_ptr)?);
+    }
+    Ok(strs)
+}
+
+/// Calls `f` to access the 32-bit word at `ptr` atomically, e.g. a futex,
+/// with the address space locked.
+///
+/// The word must be readable and writable. Fails with `EINVAL` if it is not
+/// aligned.
+pub fn with_user_atomic<R>(
+    ptr: *const AtomicU32,
+    f: impl FnOnce(&AtomicU32) -> LinuxResult<R>,
+) -> LinuxResult<R> {
+    if !ptr.is_aligned() {
+        return Err(LinuxError::EINVAL);
+    }
+    with_region(
+        ptr as usize,
+        size_of::<AtomicU32>(),
+        MappingFlags::READ | MappingFlags::WRITE,
+        // SAFETY: user space may modify the word concurrently, which is why
+        // it is accessed atomically.
+        || f(unsafe { &*ptr }),
+    )?
+}
+
+/// Writes a value of type `T` to user space.
+pub fn put_user<T: Copy>(ptr: *mut T, value: T) -> LinuxResult {
+    with_region(
+        ptr as usize,
+        size_of::<T>(),
+        MappingFlags::WRITE,
+        || unsafe { ptr.write_unaligned(value) },
+    )
+}